@@ -0,0 +1,272 @@
+use std::path::Path;
+
+use serde::{Deserialize, Deserializer};
+use tracing::info;
+
+use crate::Result;
+use crate::connectivity;
+
+pub const CONFIG_PATH: &str = "/etc/nmlinkd/nmlinkd.toml";
+
+/// Daemon configuration, loaded from `/etc/nmlinkd/nmlinkd.toml`.
+/// Every field is optional; a missing file yields the defaults.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Reject every D-Bus method that would modify kernel network state.
+    pub read_only: bool,
+    /// Well-known bus name to claim. Another name lets nmlinkd run next to
+    /// NetworkManager, serving the same object tree. Only read at startup.
+    pub bus_name: String,
+    /// Emit `DeviceAdded` for every initial device once the bus name is
+    /// claimed, for clients that track devices purely from signals.
+    pub announce_initial_devices: bool,
+    /// Interfaces never exported over D-Bus. Each entry is an exact name, a
+    /// prefix ending in `#` (followed by digits only: `tun#` matches `tun0`
+    /// but not `tunnelbroker`) or a prefix ending in `*` (any suffix).
+    pub ignore_interfaces: Vec<String>,
+    /// Export interfaces matching `ignore_interfaces` anyway, as bare
+    /// UNMANAGED devices: no type-specific interface, no connection, and
+    /// nothing nmlinkd will change on them.
+    pub show_ignored_as_unmanaged: bool,
+    /// Interfaces exported as UNMANAGED (same pattern syntax): still listed,
+    /// but shown as not under nmlinkd's control.
+    pub unmanaged: Vec<String>,
+    /// Interfaces exported as WWAN modems (same pattern syntax).
+    pub modem_interfaces: Vec<String>,
+    /// Interfaces exported as VPN connections (same pattern syntax): shown
+    /// even if `ignore_interfaces` matches, typed as WireGuard, and with
+    /// `Vpn=true` on their active connection.
+    pub vpn_interfaces: Vec<String>,
+    /// Most devices exported at once; further interfaces are left out (with
+    /// a warning) until existing ones go away.
+    pub max_devices: usize,
+    /// Milliseconds within which a device returning to the state it just
+    /// left is treated as flapping: the change is held back and only the
+    /// state it settles in is announced. 0 announces every change.
+    pub state_hysteresis_ms: u64,
+    /// Seconds `ActivateConnection` waits for the device to reach ACTIVATED;
+    /// past that the device is put in FAILED and the call returns an error.
+    /// 0 returns as soon as the link is set up.
+    pub activation_timeout: u64,
+    /// Seconds between full re-reads of links, addresses and gateways, to
+    /// correct any drift left by missed netlink events; 0 disables them.
+    /// Only read at startup.
+    pub reconcile_interval: u64,
+    /// Follow the kernel neighbour (ARP/NDP) table, for `Device.GetNeighbors`.
+    /// Only read at startup.
+    pub track_neighbors: bool,
+    /// resolv.conf files to read nameservers from, in order of preference:
+    /// the first that exists and lists a nameserver wins. A single path or
+    /// a list.
+    #[serde(deserialize_with = "one_or_many")]
+    pub resolv_conf_path: Vec<String>,
+    pub connectivity: ConnectivityConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            bus_name: "org.freedesktop.NetworkManager".to_string(),
+            announce_initial_devices: false,
+            show_ignored_as_unmanaged: false,
+            ignore_interfaces: [
+                "lo",              // loopback
+                "docker#",         // docker default bridge
+                "docker_gwbridge", // docker swarm bridge
+                "br-*",            // docker user-defined bridges (br-<hex>)
+                "veth*",           // virtual ethernet (containers)
+                "virbr#",          // libvirt bridges
+                "vnet#",           // libvirt tap devices
+                "tun#",            // TUN devices
+                "tap#",            // TAP devices
+                "tailscale#",      // Tailscale VPN
+                "podman#",         // Podman container networks
+                // Fallback devices created when tunnel modules load; give
+                // real tunnels other names to have them shown.
+                "tunl0",
+                "gre0",
+                "gretap0",
+                "erspan0",
+                "sit0",
+                "ip6tnl0",
+                "ip6gre0",
+            ]
+            .map(String::from)
+            .to_vec(),
+            unmanaged: Vec::new(),
+            modem_interfaces: ["wwan#", "wwp*"].map(String::from).to_vec(),
+            vpn_interfaces: Vec::new(),
+            max_devices: 512,
+            state_hysteresis_ms: 0,
+            activation_timeout: 0,
+            reconcile_interval: 60,
+            track_neighbors: false,
+            resolv_conf_path: ["/run/systemd/resolve/resolv.conf", "/etc/resolv.conf"]
+                .map(String::from)
+                .to_vec(),
+            connectivity: ConnectivityConfig::default(),
+        }
+    }
+}
+
+/// `[connectivity]` section: HTTP captive-portal probe, as NetworkManager does.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectivityConfig {
+    /// Seconds between periodic checks; 0 disables probing entirely.
+    pub interval: u64,
+    pub uri: String,
+    /// Expected prefix of the response body when fully online.
+    pub response: String,
+}
+
+impl Default for ConnectivityConfig {
+    fn default() -> Self {
+        Self {
+            interval: 300,
+            uri: "http://nmcheck.gnome.org/check_network_status.txt".to_string(),
+            response: "NetworkManager is online".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// The first pattern in `patterns` that `name` matches, if any.
+    pub fn matching_pattern<'a>(patterns: &'a [String], name: &str) -> Option<&'a str> {
+        patterns
+            .iter()
+            .find(|p| interface_matches(p, name))
+            .map(String::as_str)
+    }
+
+    /// Whether `name` matches one of the `unmanaged` patterns.
+    pub fn is_unmanaged(&self, name: &str) -> bool {
+        self.unmanaged.iter().any(|p| interface_matches(p, name))
+    }
+
+    /// Whether `name` matches one of the `vpn_interfaces` patterns.
+    pub fn is_vpn(&self, name: &str) -> bool {
+        self.vpn_interfaces
+            .iter()
+            .any(|p| interface_matches(p, name))
+    }
+
+    /// Whether `name` matches one of the `modem_interfaces` patterns.
+    pub fn is_modem(&self, name: &str) -> bool {
+        self.modem_interfaces
+            .iter()
+            .any(|p| interface_matches(p, name))
+    }
+
+    /// Settings that parse but can't work as intended, one message each,
+    /// for `--check-config`.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (key, patterns) in [
+            ("ignore_interfaces", &self.ignore_interfaces),
+            ("unmanaged", &self.unmanaged),
+            ("modem_interfaces", &self.modem_interfaces),
+            ("vpn_interfaces", &self.vpn_interfaces),
+        ] {
+            for pattern in patterns {
+                if let Some(problem) = pattern_problem(pattern) {
+                    problems.push(format!("{key}: pattern {pattern:?} {problem}"));
+                }
+            }
+        }
+        if zbus::names::WellKnownName::try_from(self.bus_name.as_str()).is_err() {
+            problems.push(format!(
+                "bus_name: {:?} is not a valid D-Bus name",
+                self.bus_name
+            ));
+        }
+        if self.max_devices == 0 {
+            problems.push("max_devices: must be at least 1".to_string());
+        }
+        if let Some(path) = self.resolv_conf_path.iter().find(|p| !p.starts_with('/')) {
+            problems.push(format!(
+                "resolv_conf_path: {path:?} is not an absolute path"
+            ));
+        } else if !self.resolv_conf_path.iter().any(|p| Path::new(p).exists()) {
+            problems.push("resolv_conf_path: none of the files exist".to_string());
+        }
+        if self.connectivity.interval > 0
+            && connectivity::parse_http_uri(&self.connectivity.uri).is_none()
+        {
+            problems.push(format!(
+                "connectivity.uri: {:?} is not an http://host[:port]/path URI",
+                self.connectivity.uri
+            ));
+        }
+        problems
+    }
+}
+
+/// Why an interface pattern can never match a kernel interface name, if it
+/// can't: names are 1–15 bytes without `/`, `:` or whitespace.
+fn pattern_problem(pattern: &str) -> Option<&'static str> {
+    let (stem, min_len) = match pattern.strip_suffix(['#', '*']) {
+        Some(prefix) => (prefix, prefix.len() + 1),
+        None => (pattern, pattern.len()),
+    };
+    if pattern.is_empty() {
+        Some("is empty")
+    } else if stem.contains(['/', ':', '#', '*']) || stem.contains(char::is_whitespace) {
+        Some(
+            "can never match: interface names contain no '/', ':' or whitespace, and '#'/'*' only work at the end",
+        )
+    } else if min_len > 15 {
+        Some("can never match: interface names are at most 15 characters")
+    } else {
+        None
+    }
+}
+
+/// Accept either a single string or a list of strings.
+fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+/// Match an interface name against one pattern: `prefix#` requires a
+/// non-empty all-digit suffix, `prefix*` any non-empty suffix, anything else
+/// must match exactly.
+pub fn interface_matches(pattern: &str, name: &str) -> bool {
+    if let Some(prefix) = pattern.strip_suffix('#') {
+        name.strip_prefix(prefix)
+            .is_some_and(|rest| !rest.is_empty() && rest.bytes().all(|b| b.is_ascii_digit()))
+    } else if let Some(prefix) = pattern.strip_suffix('*') {
+        name.strip_prefix(prefix)
+            .is_some_and(|rest| !rest.is_empty())
+    } else {
+        name == pattern
+    }
+}
+
+/// Load the configuration file, falling back to defaults if it doesn't exist.
+pub fn load() -> Result<Config> {
+    load_from(Path::new(CONFIG_PATH))
+}
+
+pub fn load_from(path: &Path) -> Result<Config> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let config: Config = toml::from_str(&contents)?;
+    info!(path = %path.display(), "loaded config");
+    Ok(config)
+}
@@ -0,0 +1,317 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::future::join_all;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream};
+use tracing::{debug, info, warn};
+use zbus::Connection;
+
+use crate::mapping::{nm_connectivity, nm_state};
+use crate::nm;
+use crate::state::SharedState;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RESPONSE_LEN: u64 = 16 * 1024;
+
+/// Run the connectivity probe every `connectivity.interval` seconds.
+/// Probing pauses while the global state is below CONNECTED_LOCAL.
+pub async fn run_periodic(nm_conn: Connection, shared: SharedState) {
+    let interval = shared.read().await.config.connectivity.interval;
+    if interval == 0 {
+        info!("periodic connectivity check disabled");
+        return;
+    }
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        check(&nm_conn, &shared).await;
+    }
+}
+
+/// Probe connectivity now, store the result and notify clients if it changed.
+/// Returns the resulting NMConnectivityState. With checking disabled
+/// (`connectivity.interval = 0`), nothing is probed and the state-derived
+/// value is returned.
+pub async fn check(nm_conn: &Connection, shared: &SharedState) -> u32 {
+    let (global_state, uri, expected) = {
+        let state = shared.read().await;
+        let cfg = &state.config.connectivity;
+        if cfg.interval == 0 {
+            return state.connectivity;
+        }
+        (state.global_state, cfg.uri.clone(), cfg.response.clone())
+    };
+
+    if global_state < nm_state::CONNECTED_LOCAL {
+        return shared.read().await.connectivity;
+    }
+
+    let result = probe(&uri, &expected, None).await;
+    debug!(uri, result, "connectivity probe finished");
+
+    let (old, new) = {
+        let mut state = shared.write().await;
+        let old = state.connectivity;
+        state.connectivity_check = Some(result);
+        state.recompute_global_state();
+        (old, state.connectivity)
+    };
+
+    if old != new {
+        info!(old, new, "connectivity changed");
+        nm::signals::notify_connectivity_changed(nm_conn, new).await;
+    }
+
+    check_links(nm_conn, shared, &uri, &expected).await;
+    new
+}
+
+/// Probe again through every link holding a default route, once per address
+/// family, so multihomed hosts see which uplink is behind a portal.
+async fn check_links(nm_conn: &Connection, shared: &SharedState, uri: &str, expected: &str) {
+    let targets: Vec<(i32, String, Family)> = {
+        let state = shared.read().await;
+        state
+            .devices
+            .values()
+            .flat_map(|dev| {
+                let v4 = dev.gateway4.is_some().then_some(Family::V4);
+                let v6 = dev.gateway6.is_some().then_some(Family::V6);
+                [v4, v6]
+                    .into_iter()
+                    .flatten()
+                    .map(|family| (dev.ifindex, dev.name.clone(), family))
+            })
+            .collect()
+    };
+
+    let results = join_all(targets.iter().map(|(_, name, family)| {
+        probe(
+            uri,
+            expected,
+            Some(Egress {
+                name,
+                family: *family,
+            }),
+        )
+    }))
+    .await;
+
+    let mut changed = Vec::new();
+    {
+        let mut state = shared.write().await;
+        for ((ifindex, name, family), result) in targets.into_iter().zip(results) {
+            debug!(iface = %name, ?family, result, "link connectivity probe finished");
+            let Some(dev) = state.devices.get_mut(&ifindex) else {
+                continue;
+            };
+            let old = (dev.ip4_connectivity(), dev.ip6_connectivity());
+            match family {
+                Family::V4 => dev.ip4_connectivity_check = Some(result),
+                Family::V6 => dev.ip6_connectivity_check = Some(result),
+            }
+            let new = (dev.ip4_connectivity(), dev.ip6_connectivity());
+            if old != new {
+                changed.retain(|&(idx, _)| idx != ifindex);
+                changed.push((ifindex, new));
+            }
+        }
+    }
+
+    for (ifindex, (ip4, ip6)) in changed {
+        nm::signals::notify_device_connectivity_changed(nm_conn, ifindex, ip4, ip6).await;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Family {
+    V4,
+    V6,
+}
+
+/// Interface and address family a per-link probe must leave through.
+struct Egress<'a> {
+    name: &'a str,
+    family: Family,
+}
+
+/// Fetch `uri` and classify the answer: the expected body means FULL, any
+/// other HTTP response means a captive PORTAL, no response means LIMITED.
+/// With an `egress`, the probe is bound to that interface (SO_BINDTODEVICE).
+async fn probe(uri: &str, expected: &str, egress: Option<Egress<'_>>) -> u32 {
+    let Some((host, port, path)) = parse_http_uri(uri) else {
+        warn!(
+            uri,
+            "unsupported connectivity check URI (only http:// is supported)"
+        );
+        return nm_connectivity::UNKNOWN;
+    };
+
+    match tokio::time::timeout(PROBE_TIMEOUT, fetch(host, port, path, egress)).await {
+        Ok(Ok(response)) => classify_response(&response, expected),
+        Ok(Err(e)) => {
+            debug!(uri, "connectivity probe failed: {e}");
+            nm_connectivity::LIMITED
+        }
+        Err(_) => {
+            debug!(uri, "connectivity probe timed out");
+            nm_connectivity::LIMITED
+        }
+    }
+}
+
+async fn fetch(
+    host: &str,
+    port: u16,
+    path: &str,
+    egress: Option<Egress<'_>>,
+) -> std::io::Result<String> {
+    let mut stream = match egress {
+        None => TcpStream::connect((host, port)).await?,
+        Some(egress) => connect_via(host, port, egress).await?,
+    };
+    let request = format!(
+        "GET {path} HTTP/1.1\r\nHost: {host}\r\nUser-Agent: nmlinkd/{}\r\nConnection: close\r\n\r\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut buf = Vec::new();
+    stream.take(MAX_RESPONSE_LEN).read_to_end(&mut buf).await?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// Connect to the first address of the egress family, from a socket bound
+/// to the egress interface.
+async fn connect_via(host: &str, port: u16, egress: Egress<'_>) -> std::io::Result<TcpStream> {
+    let addr = tokio::net::lookup_host((host, port))
+        .await?
+        .find(|a| match egress.family {
+            Family::V4 => a.is_ipv4(),
+            Family::V6 => a.is_ipv6(),
+        })
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::AddrNotAvailable,
+                format!("{host} has no {:?} address", egress.family),
+            )
+        })?;
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.bind_device(Some(egress.name.as_bytes()))?;
+    socket.connect(addr).await
+}
+
+/// FULL for a 200 carrying `expected`, PORTAL for any other HTTP response,
+/// and LIMITED, as in NM, when no status line was read at all: a peer that
+/// accepts and closes at once (a firewall, a resetting proxy) is no portal.
+fn classify_response(response: &str, expected: &str) -> u32 {
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((response, ""));
+    let status = head.lines().next().and_then(|l| {
+        let mut parts = l.split_whitespace();
+        let version = parts.next()?;
+        let code = parts.next()?;
+        (version.starts_with("HTTP/")
+            && code.len() == 3
+            && code.bytes().all(|b| b.is_ascii_digit()))
+        .then_some(code)
+    });
+    match status {
+        None => nm_connectivity::LIMITED,
+        Some("200") if body.trim_start().starts_with(expected) => nm_connectivity::FULL,
+        Some(_) => nm_connectivity::PORTAL,
+    }
+}
+
+/// Split `http://host[:port]/path` into its parts.
+pub fn parse_http_uri(uri: &str) -> Option<(&str, u16, &str)> {
+    let rest = uri.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) => (h, p.parse().ok()?),
+        None => (authority, 80),
+    };
+    (!host.is_empty()).then_some((host, port, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_http_uri_splits_host_port_and_path() {
+        assert_eq!(
+            parse_http_uri("http://nmcheck.gnome.org/check_network_status.txt"),
+            Some(("nmcheck.gnome.org", 80, "/check_network_status.txt"))
+        );
+        assert_eq!(
+            parse_http_uri("http://192.0.2.1:8080/probe?x=1"),
+            Some(("192.0.2.1", 8080, "/probe?x=1"))
+        );
+        assert_eq!(
+            parse_http_uri("http://example.com"),
+            Some(("example.com", 80, "/"))
+        );
+    }
+
+    #[test]
+    fn parse_http_uri_rejects_unsupported_uris() {
+        for uri in [
+            "https://example.com/",
+            "example.com/",
+            "http://",
+            "http:///path",
+            "http://:80/",
+            "http://example.com:http/",
+            "http://example.com:65536/",
+        ] {
+            assert_eq!(parse_http_uri(uri), None, "{uri:?} accepted");
+        }
+    }
+
+    #[test]
+    fn expected_body_is_full_connectivity() {
+        let response = "HTTP/1.1 200 OK\r\nContent-Length: 22\r\n\r\nNetworkManager is online\n";
+        assert_eq!(
+            classify_response(response, "NetworkManager is online"),
+            nm_connectivity::FULL
+        );
+    }
+
+    #[test]
+    fn other_responses_are_a_portal() {
+        let expected = "NetworkManager is online";
+        for response in [
+            "HTTP/1.1 200 OK\r\n\r\n<html>Sign in to the hotel Wi-Fi</html>",
+            "HTTP/1.1 302 Found\r\nLocation: http://portal.example/\r\n\r\n",
+            "HTTP/1.1 204 No Content\r\n\r\n",
+            "HTTP/1.1 500 Internal Server Error\r\n\r\nNetworkManager is online",
+        ] {
+            assert_eq!(
+                classify_response(response, expected),
+                nm_connectivity::PORTAL,
+                "{response:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn no_status_line_is_limited() {
+        let expected = "NetworkManager is online";
+        for response in ["", "garbage", "\r\n\r\nNetworkManager is online"] {
+            assert_eq!(
+                classify_response(response, expected),
+                nm_connectivity::LIMITED,
+                "{response:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+const ETHTOOL_GDRVINFO: u32 = 0x0000_0003;
+
+/// `struct ethtool_drvinfo` from `<linux/ethtool.h>`.
+#[repr(C)]
+#[allow(dead_code)] // filled in by the kernel; only some fields are read
+struct EthtoolDrvinfo {
+    cmd: u32,
+    driver: [u8; 32],
+    version: [u8; 32],
+    fw_version: [u8; 32],
+    bus_info: [u8; 32],
+    erom_version: [u8; 32],
+    reserved2: [u8; 12],
+    n_priv_flags: u32,
+    n_stats: u32,
+    testinfo_len: u32,
+    eedump_len: u32,
+    regdump_len: u32,
+}
+
+/// What `ethtool -i` reports about an interface's driver.
+#[derive(Debug, Clone, Default)]
+pub struct DriverInfo {
+    pub version: String,
+    pub fw_version: String,
+}
+
+/// Query ETHTOOL_GDRVINFO for `name`. Fails for interfaces whose driver
+/// doesn't implement it, which includes most virtual ones.
+pub fn driver_info(name: &str) -> io::Result<DriverInfo> {
+    let name = name.as_bytes();
+    if name.len() >= libc::IFNAMSIZ {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+
+    // SAFETY: plain socket(2) call; the fd is owned (and closed) right away.
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fd` is a freshly created descriptor nothing else owns.
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // SAFETY: both structs are plain data, valid when zeroed.
+    let mut drvinfo: EthtoolDrvinfo = unsafe { std::mem::zeroed() };
+    drvinfo.cmd = ETHTOOL_GDRVINFO;
+    let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, &src) in ifr.ifr_name.iter_mut().zip(name) {
+        *dst = src as libc::c_char;
+    }
+    ifr.ifr_ifru.ifru_data = (&raw mut drvinfo).cast();
+
+    // SAFETY: SIOCETHTOOL reads `ifr` and writes at most
+    // size_of::<EthtoolDrvinfo>() bytes through `ifru_data`, which points to
+    // `drvinfo`, alive for the whole call.
+    if unsafe { libc::ioctl(socket.as_raw_fd(), libc::SIOCETHTOOL, &mut ifr) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(DriverInfo {
+        version: c_string(&drvinfo.version),
+        fw_version: c_string(&drvinfo.fw_version),
+    })
+}
+
+/// A NUL-padded C string field, lossily decoded.
+fn c_string(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
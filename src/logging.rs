@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry, fmt, reload};
+
+/// NM logging domains nmlinkd emits, and the module paths (tracing targets)
+/// each one covers. CORE takes every module no other domain claims.
+const DOMAINS: &[(&str, &[&str])] = &[
+    ("CORE", &["nmlinkd"]),
+    ("PLATFORM", &["nmlinkd::netlink"]),
+    ("DEVICE", &["nmlinkd::nm"]),
+    ("DNS", &["nmlinkd::resolved", "nmlinkd::resolv_watch"]),
+    ("CONCHECK", &["nmlinkd::connectivity"]),
+];
+
+/// NM log levels and the tracing level each maps to.
+const LEVELS: &[(&str, &str)] = &[
+    ("ERR", "error"),
+    ("WARN", "warn"),
+    ("INFO", "info"),
+    ("DEBUG", "debug"),
+    ("TRACE", "trace"),
+    ("OFF", "off"),
+];
+
+/// Logging configuration as NM describes it: a default level and the enabled
+/// domains, each with its own level.
+struct Logging {
+    handle: reload::Handle<EnvFilter, Registry>,
+    level: &'static str,
+    domains: BTreeMap<&'static str, &'static str>,
+}
+
+static LOGGING: OnceLock<Mutex<Logging>> = OnceLock::new();
+
+/// Install the global subscriber. `RUST_LOG` overrides the default
+/// `nmlinkd=info`; `json` emits one JSON object per line. Logs go to stdout,
+/// or to stderr when `stderr` is set so stdout carries only the output.
+pub fn init(json: bool, stderr: bool) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "nmlinkd=info".into());
+    let (filter, handle) = reload::Layer::new(filter);
+    let writer = || {
+        if stderr {
+            BoxMakeWriter::new(std::io::stderr)
+        } else {
+            BoxMakeWriter::new(std::io::stdout)
+        }
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(json.then(|| {
+            fmt::layer()
+                .json()
+                .flatten_event(true)
+                .with_writer(writer())
+        }))
+        .with((!json).then(|| fmt::layer().with_writer(writer())))
+        .init();
+
+    let domains = DOMAINS.iter().map(|&(name, _)| (name, "INFO")).collect();
+    let _ = LOGGING.set(Mutex::new(Logging {
+        handle,
+        level: "INFO",
+        domains,
+    }));
+}
+
+/// Current level and domains in NM's form: a comma-separated domain list,
+/// with `:LEVEL` on domains whose level differs from the default. Reflects
+/// the last `set` — not a `RUST_LOG` override given at startup.
+pub fn get() -> (String, String) {
+    let Some(logging) = LOGGING.get() else {
+        return (String::new(), String::new());
+    };
+    let logging = logging.lock().unwrap();
+    let domains = logging
+        .domains
+        .iter()
+        .map(|(&name, &level)| {
+            if level == logging.level {
+                name.to_string()
+            } else {
+                format!("{name}:{level}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    (logging.level.to_string(), domains)
+}
+
+/// Apply NM's `SetLogging`. `level` is the default for listed domains (empty
+/// or KEEP keeps the current one); `domains` lists `DOMAIN[:LEVEL]` entries
+/// separated by commas or spaces, ALL/DEFAULT for every domain and NONE for
+/// none. Unlisted domains are disabled; an empty list keeps the current
+/// domains at the new level.
+pub fn set(level: &str, domains: &str) -> zbus::fdo::Result<()> {
+    let Some(logging) = LOGGING.get() else {
+        return Err(zbus::fdo::Error::Failed(
+            "Logging is not initialized".to_string(),
+        ));
+    };
+    let mut logging = logging.lock().unwrap();
+
+    let level = match level {
+        "" | "KEEP" => logging.level,
+        level => parse_level(level)?,
+    };
+
+    let mut enabled = BTreeMap::new();
+    let tokens: Vec<&str> = domains
+        .split([',', ' '])
+        .filter(|t| !t.is_empty())
+        .collect();
+    if tokens.is_empty() {
+        enabled.extend(logging.domains.keys().map(|&name| (name, level)));
+    }
+    for token in tokens {
+        let (name, domain_level) = match token.split_once(':') {
+            Some((name, l)) => (name, parse_level(l)?),
+            None => (token, level),
+        };
+        match name.to_ascii_uppercase().as_str() {
+            "NONE" => enabled.clear(),
+            "ALL" | "DEFAULT" => {
+                enabled.extend(DOMAINS.iter().map(|&(name, _)| (name, domain_level)));
+            }
+            upper => {
+                let Some(&(name, _)) = DOMAINS.iter().find(|&&(n, _)| n == upper) else {
+                    return Err(zbus::fdo::Error::InvalidArgs(format!(
+                        "Unknown logging domain {name}"
+                    )));
+                };
+                enabled.insert(name, domain_level);
+            }
+        }
+    }
+
+    let filter = EnvFilter::try_new(directives(&enabled))
+        .map_err(|e| zbus::fdo::Error::Failed(format!("Invalid log filter: {e}")))?;
+    logging
+        .handle
+        .reload(filter)
+        .map_err(|e| zbus::fdo::Error::Failed(format!("Failed to set log filter: {e}")))?;
+    logging.level = level;
+    logging.domains = enabled;
+    Ok(())
+}
+
+fn parse_level(level: &str) -> zbus::fdo::Result<&'static str> {
+    LEVELS
+        .iter()
+        .find(|(nm, _)| nm.eq_ignore_ascii_case(level))
+        .map(|&(nm, _)| nm)
+        .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("Unknown log level {level}")))
+}
+
+/// EnvFilter directives setting every domain's targets to its level, or off
+/// when it isn't enabled.
+fn directives(domains: &BTreeMap<&str, &str>) -> String {
+    let mut directives = Vec::new();
+    for &(name, targets) in DOMAINS {
+        let level = domains.get(name).map_or("off", |&level| {
+            LEVELS
+                .iter()
+                .find(|&&(nm, _)| nm == level)
+                .map_or("info", |&(_, tracing)| tracing)
+        });
+        directives.extend(targets.iter().map(|t| format!("{t}={level}")));
+    }
+    directives.join(",")
+}
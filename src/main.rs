@@ -1,7 +1,17 @@
+mod config;
+mod connectivity;
+mod ethtool;
+mod logging;
 mod mapping;
 mod netlink;
 mod nm;
+mod persistence;
+mod resolv_watch;
+mod resolved;
 mod state;
+mod state_file;
+#[cfg(test)]
+mod test_support;
 
 use tracing::{error, info};
 
@@ -18,37 +28,120 @@ pub enum Error {
 
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("Config error: {0}")]
+    Config(#[from] toml::de::Error),
+
+    #[error("{0} configuration problem(s) found")]
+    InvalidConfig(usize),
+
+    #[error(
+        "cannot subscribe to netlink events: {0} (AF_NETLINK must be allowed, \
+         e.g. by the unit's RestrictAddressFamilies)"
+    )]
+    Subscribe(#[source] std::io::Error),
 }
 
+/// Exit status when netlink events can't be subscribed to (LSB "insufficient
+/// privilege"); the unit doesn't restart on it.
+const EXIT_NOPERMISSION: i32 = 4;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "nmlinkd=info".parse().unwrap()),
-        )
-        .init();
+    let mode = std::env::args().nth(1);
+    // NMLINKD_LOG_FORMAT=json emits one JSON object per line for log aggregation
+    logging::init(
+        std::env::var("NMLINKD_LOG_FORMAT").is_ok_and(|f| f == "json"),
+        mode.as_deref() == Some("--once"),
+    );
 
-    if let Err(e) = run().await {
+    let result = match mode.as_deref() {
+        Some("--dump-state") => dump_state().await,
+        Some("--once") => once().await,
+        Some("--check-config") => check_config(std::env::args().nth(2)),
+        Some("--session") => run(nm::Bus::Session).await,
+        _ => run(nm::Bus::System).await,
+    };
+    if let Err(e) = result {
         error!("fatal: {e}");
-        std::process::exit(1);
+        let code = match e {
+            Error::Subscribe(_) => EXIT_NOPERMISSION,
+            _ => 1,
+        };
+        std::process::exit(code);
+    }
+}
+
+/// List every kernel interface with whether (and why not) it's exported.
+async fn dump_state() -> Result<()> {
+    let config = config::load()?;
+    netlink::dump_interfaces(&config).await
+}
+
+/// Load the initial state as the daemon would and print it as JSON (as
+/// Manager.DumpState returns it), without claiming the bus, following
+/// events or changing the kernel.
+async fn once() -> Result<()> {
+    let mut config = config::load()?;
+    // Stored profiles are only re-applied when not read-only.
+    config.read_only = true;
+    let shared = state::new_shared_state(config, state_file::load());
+    netlink::load_initial_state(&shared).await?;
+    let json =
+        serde_json::to_string_pretty(&shared.read().await.dump()).map_err(std::io::Error::other)?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Load and validate the config file (or the one at `path`, which must
+/// exist), printing each problem found. Fails if it doesn't parse or has any
+/// problem.
+fn check_config(path: Option<String>) -> Result<()> {
+    if let Some(path) = &path
+        && !std::path::Path::new(path).exists()
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{path}: no such file"),
+        )
+        .into());
+    }
+    let path = path.unwrap_or_else(|| config::CONFIG_PATH.to_string());
+    let config = config::load_from(std::path::Path::new(&path))?;
+    let problems = config.validate();
+    for problem in &problems {
+        println!("{path}: {problem}");
     }
+    if !problems.is_empty() {
+        return Err(Error::InvalidConfig(problems.len()));
+    }
+    println!("{path}: OK");
+    Ok(())
 }
 
-async fn run() -> Result<()> {
+async fn run(bus: nm::Bus) -> Result<()> {
     info!("starting nmlinkd");
 
-    let shared = state::new_shared_state();
+    let config = config::load()?;
+    let shared = state::new_shared_state(config, state_file::load());
 
-    // Load initial state from kernel via netlink
-    netlink::load_initial_state(&shared).await?;
+    // Load initial state from kernel via netlink, subscribing to events first
+    let events = netlink::load_initial_state(&shared).await?;
 
     // Serve NetworkManager D-Bus API
-    let nm_conn = nm::serve(shared.clone()).await?;
-    info!("claimed org.freedesktop.NetworkManager on system bus");
+    let nm_conn = nm::serve(shared.clone(), bus).await?;
+
+    // Periodic connectivity (captive portal) re-check
+    tokio::spawn(connectivity::run_periodic(nm_conn.clone(), shared.clone()));
+
+    // Reload nameservers whenever resolv.conf is rewritten
+    tokio::spawn(resolv_watch::run(nm_conn.clone(), shared.clone()));
+
+    // Follow speed/duplex changes announced by ethtool
+    tokio::spawn(netlink::linkmodes::run(nm_conn.clone(), shared.clone()));
 
     // Run netlink event loop
-    netlink::monitor::run(nm_conn, shared).await
+    netlink::monitor::run(nm_conn, shared, events).await
 }
@@ -1,6 +1,8 @@
 /// NetworkManager global state (NMState).
 pub mod nm_state {
     pub const DISCONNECTED: u32 = 20;
+    pub const DISCONNECTING: u32 = 30;
+    pub const CONNECTING: u32 = 40;
     pub const CONNECTED_LOCAL: u32 = 50;
     pub const CONNECTED_GLOBAL: u32 = 70;
 }
@@ -8,35 +10,74 @@ pub mod nm_state {
 /// NetworkManager device state (NMDeviceState).
 pub mod nm_device_state {
     pub const UNKNOWN: u32 = 0;
+    pub const UNMANAGED: u32 = 10;
     pub const UNAVAILABLE: u32 = 20;
     pub const DISCONNECTED: u32 = 30;
+    pub const PREPARE: u32 = 40;
+    pub const CONFIG: u32 = 50;
     pub const IP_CONFIG: u32 = 70;
     pub const ACTIVATED: u32 = 100;
+    pub const DEACTIVATING: u32 = 110;
+    pub const FAILED: u32 = 120;
 }
 
 /// NetworkManager device type (NMDeviceType).
 pub mod nm_device_type {
+    pub const UNKNOWN: u32 = 0;
     pub const ETHERNET: u32 = 1;
+    pub const MODEM: u32 = 8;
+    pub const INFINIBAND: u32 = 9;
+    pub const BOND: u32 = 10;
+    pub const VLAN: u32 = 11;
+    pub const BRIDGE: u32 = 13;
+    pub const GENERIC: u32 = 14;
+    pub const IP_TUNNEL: u32 = 17;
+    pub const MACVLAN: u32 = 18;
+    pub const VXLAN: u32 = 19;
+    pub const VETH: u32 = 20;
+    pub const DUMMY: u32 = 22;
+    pub const PPP: u32 = 23;
     pub const WIREGUARD: u32 = 29;
+    pub const VRF: u32 = 31;
+    pub const LOOPBACK: u32 = 32;
+}
+
+/// NetworkManager IP tunnel mode (NMIPTunnelMode).
+pub mod nm_ip_tunnel_mode {
+    pub const IPIP: u32 = 1;
+    pub const GRE: u32 = 2;
+    pub const SIT: u32 = 3;
+}
+
+/// NetworkManager device interface flags (NMDeviceInterfaceFlags).
+pub mod nm_device_interface_flags {
+    pub const UP: u32 = 0x1;
+    pub const LOWER_UP: u32 = 0x2;
+    pub const CARRIER: u32 = 0x10000;
 }
 
 /// NetworkManager connectivity state (NMConnectivityState).
 pub mod nm_connectivity {
     pub const UNKNOWN: u32 = 0;
     pub const NONE: u32 = 1;
+    pub const PORTAL: u32 = 2;
+    pub const LIMITED: u32 = 3;
     pub const FULL: u32 = 4;
 }
 
 /// NetworkManager device state reason (NMDeviceStateReason).
 pub mod nm_device_state_reason {
     pub const NONE: u32 = 0;
+    pub const IP_CONFIG_UNAVAILABLE: u32 = 5;
     pub const USER_REQUESTED: u32 = 39;
+    pub const CARRIER: u32 = 40;
 }
 
 /// NetworkManager active connection state (NMActiveConnectionState).
 pub mod nm_active_connection_state {
     pub const UNKNOWN: u32 = 0;
     pub const ACTIVATED: u32 = 2;
+    pub const DEACTIVATING: u32 = 3;
     pub const DEACTIVATED: u32 = 4;
 }
 
@@ -54,72 +95,504 @@ pub mod netlink_flags {
     pub const IFF_DORMANT: u32 = 0x20000;
 }
 
+/// Active connection state for a device state. The connection still exists
+/// (and counts as active) while the device is deactivating.
+pub fn device_state_to_ac_state(device_state: u32) -> u32 {
+    match device_state {
+        nm_device_state::ACTIVATED => nm_active_connection_state::ACTIVATED,
+        nm_device_state::DEACTIVATING => nm_active_connection_state::DEACTIVATING,
+        _ => nm_active_connection_state::DEACTIVATED,
+    }
+}
+
+/// Whether a device in this state has an ActiveConnection: once activated,
+/// until it finishes deactivating.
+pub fn has_active_connection(device_state: u32) -> bool {
+    (nm_device_state::ACTIVATED..=nm_device_state::DEACTIVATING).contains(&device_state)
+}
+
+/// RFC 2863 operational state (IFLA_OPERSTATE, IF_OPER_*).
+pub mod if_oper {
+    pub const UNKNOWN: u8 = 0;
+    pub const NOTPRESENT: u8 = 1;
+    pub const DOWN: u8 = 2;
+    pub const LOWERLAYERDOWN: u8 = 3;
+    pub const TESTING: u8 = 4;
+    pub const DORMANT: u8 = 5;
+    pub const UP: u8 = 6;
+}
+
+/// Map netlink link flags to the NM interface flag bitmask.
+pub fn netlink_flags_to_interface_flags(flags: u32, carrier: Option<bool>) -> u32 {
+    use netlink_flags::*;
+
+    let mut iface_flags = 0;
+    if (flags & IFF_UP) != 0 {
+        iface_flags |= nm_device_interface_flags::UP;
+    }
+    if (flags & IFF_LOWER_UP) != 0 {
+        iface_flags |= nm_device_interface_flags::LOWER_UP;
+    }
+    if carrier.unwrap_or_else(|| flags_carrier(flags)) {
+        iface_flags |= nm_device_interface_flags::CARRIER;
+    }
+    iface_flags
+}
+
+/// Carrier as the legacy flags imply it, for when IFLA_CARRIER is absent.
+pub fn flags_carrier(flags: u32) -> bool {
+    use netlink_flags::*;
+    (flags & (IFF_RUNNING | IFF_LOWER_UP)) != 0
+}
+
 /// Deduce global NM state from device states and routes.
+///
+/// A default gateway alone is enough for CONNECTED_GLOBAL: PPPoE and tunnel
+/// links often route via the peer without a global address of their own.
+/// Without any connection, a device still activating makes the state
+/// CONNECTING, and one deactivating makes it DISCONNECTING; the addresses
+/// and gateway of a deactivating device no longer count. IP_CONFIG only
+/// counts as activating while `activation_pending` says an activation was
+/// requested: it is also where a link with carrier and no address rests.
+/// Unmanaged devices, ignored interfaces shown as unmanaged included, do not
+/// count.
 pub fn deduce_global_state(
     devices: &std::collections::HashMap<i32, crate::state::DeviceInfo>,
+    activation_pending: impl Fn(i32) -> bool,
 ) -> u32 {
     let mut has_local = false;
+    let mut activating = false;
+    let mut deactivating = false;
 
-    for dev in devices.values() {
-        let has_ip = !dev.ipv4_addrs.is_empty() || !dev.ipv6_addrs.is_empty();
-        if has_ip {
+    for dev in devices.values().filter(|d| d.managed) {
+        if dev.nm_state == nm_device_state::DEACTIVATING {
+            deactivating = true;
+            continue;
+        }
+        if dev.has_gateway() {
+            return nm_state::CONNECTED_GLOBAL;
+        }
+        if !dev.ipv4_addrs.is_empty() || !dev.ipv6_addrs.is_empty() {
             has_local = true;
-            if dev.has_gateway() {
-                return nm_state::CONNECTED_GLOBAL;
-            }
+        }
+        match dev.nm_state {
+            nm_device_state::PREPARE | nm_device_state::CONFIG => activating = true,
+            nm_device_state::IP_CONFIG if activation_pending(dev.ifindex) => activating = true,
+            _ => {}
         }
     }
 
     if has_local {
         nm_state::CONNECTED_LOCAL
+    } else if activating {
+        nm_state::CONNECTING
+    } else if deactivating {
+        nm_state::DISCONNECTING
     } else {
         nm_state::DISCONNECTED
     }
 }
 
 /// Deduce connectivity from global state.
-/// For a read-only bridge, we assume full connectivity if connected,
-/// since we don't perform actual connectivity checks.
+/// Until a connectivity probe has run, we assume full connectivity if connected.
 pub fn global_state_to_connectivity(global_state: u32) -> u32 {
     match global_state {
         nm_state::CONNECTED_LOCAL..=nm_state::CONNECTED_GLOBAL => nm_connectivity::FULL,
-        nm_state::DISCONNECTED => nm_connectivity::NONE,
+        nm_state::DISCONNECTED..nm_state::CONNECTED_LOCAL => nm_connectivity::NONE,
         _ => nm_connectivity::UNKNOWN,
     }
 }
 
 /// Map device type to NM connection type string.
 pub fn device_type_to_connection_type(device_type: u32) -> &'static str {
-    if device_type == nm_device_type::WIREGUARD {
-        "wireguard"
-    } else {
-        "802-3-ethernet"
+    match device_type {
+        nm_device_type::WIREGUARD => "wireguard",
+        nm_device_type::VLAN => "vlan",
+        nm_device_type::IP_TUNNEL => "ip-tunnel",
+        nm_device_type::MODEM => "gsm",
+        t if is_ethernet_like(t) => "802-3-ethernet",
+        _ => "generic",
     }
 }
 
-/// Map netlink link flags to NM device state.
-pub fn netlink_flags_to_nm_device(flags: u32, has_ipv4: bool, has_ipv6: bool) -> u32 {
+/// Whether a device type has an Ethernet link layer and is served with the
+/// Device.Wired interface. Everything else without a dedicated interface
+/// (InfiniBand, PPP, unknown kinds) is served as Device.Generic.
+pub fn is_ethernet_like(device_type: u32) -> bool {
+    use nm_device_type::*;
+    matches!(
+        device_type,
+        ETHERNET | BOND | BRIDGE | MACVLAN | VXLAN | VETH | DUMMY | VRF
+    )
+}
+
+/// Map netlink link flags and operational state to NM device state.
+/// Unmanaged devices are always UNMANAGED, whatever their link state.
+///
+/// IFLA_OPERSTATE is preferred when the driver reports it; many virtual
+/// links (WireGuard, TUN, ...) only ever report UNKNOWN, and then the
+/// IFF_* flags decide, with carrier taken from IFLA_CARRIER when given.
+/// DORMANT waits in CONFIG; DOWN, LOWERLAYERDOWN, NOTPRESENT and TESTING
+/// are UNAVAILABLE.
+/// With carrier, `has_ip_config` (see [`DeviceInfo::has_ip_config`]) tells
+/// ACTIVATED from IP_CONFIG.
+///
+/// [`DeviceInfo::has_ip_config`]: crate::state::DeviceInfo::has_ip_config
+pub fn netlink_flags_to_nm_device(
+    flags: u32,
+    carrier: Option<bool>,
+    operstate: u8,
+    managed: bool,
+    has_ip_config: bool,
+) -> u32 {
     use netlink_flags::*;
 
-    let is_up = (flags & IFF_UP) != 0;
-    let is_running = (flags & IFF_RUNNING) != 0;
-    let is_lower_up = (flags & IFF_LOWER_UP) != 0;
-    let is_dormant = (flags & IFF_DORMANT) != 0;
+    if !managed {
+        return nm_device_state::UNMANAGED;
+    }
 
+    let is_up = (flags & IFF_UP) != 0;
     if !is_up {
         return nm_device_state::DISCONNECTED;
     }
 
-    if is_dormant {
-        return nm_device_state::UNAVAILABLE;
+    let with_carrier = if has_ip_config {
+        nm_device_state::ACTIVATED
+    } else {
+        nm_device_state::IP_CONFIG
+    };
+
+    match operstate {
+        if_oper::UP => return with_carrier,
+        if_oper::DORMANT => return nm_device_state::CONFIG,
+        // A link in test mode carries no traffic and isn't authenticating.
+        if_oper::DOWN | if_oper::LOWERLAYERDOWN | if_oper::NOTPRESENT | if_oper::TESTING => {
+            return nm_device_state::UNAVAILABLE;
+        }
+        _ => {}
     }
 
-    let has_carrier = is_running || is_lower_up;
-    let has_ip = has_ipv4 || has_ipv6;
+    let is_dormant = (flags & IFF_DORMANT) != 0;
+    let has_carrier = carrier.unwrap_or_else(|| flags_carrier(flags));
 
-    match (has_carrier, has_ip) {
+    // A dormant link with carrier is waiting on an upper layer (802.1X, Wi-Fi
+    // association) — it's authenticating, not unavailable.
+    match (has_carrier, is_dormant) {
         (false, _) => nm_device_state::UNAVAILABLE,
-        (true, false) => nm_device_state::IP_CONFIG,
-        (true, true) => nm_device_state::ACTIVATED,
+        (true, true) => nm_device_state::CONFIG,
+        (true, false) => with_carrier,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netlink_flags::{IFF_DORMANT, IFF_LOWER_UP, IFF_RUNNING, IFF_UP};
+
+    #[test]
+    fn dormant_links_wait_in_config_only_with_carrier() {
+        // (flags, IFLA_CARRIER, operstate, has IP config, expected)
+        let cases = [
+            // IFF_DORMANT with operstate UNKNOWN: the flags decide.
+            (
+                IFF_UP | IFF_LOWER_UP | IFF_DORMANT,
+                None,
+                if_oper::UNKNOWN,
+                false,
+                nm_device_state::CONFIG,
+            ),
+            (
+                IFF_UP | IFF_DORMANT,
+                None,
+                if_oper::UNKNOWN,
+                false,
+                nm_device_state::UNAVAILABLE,
+            ),
+            (
+                IFF_UP | IFF_LOWER_UP | IFF_DORMANT,
+                Some(true),
+                if_oper::UNKNOWN,
+                false,
+                nm_device_state::CONFIG,
+            ),
+            (
+                IFF_UP | IFF_DORMANT,
+                Some(false),
+                if_oper::UNKNOWN,
+                false,
+                nm_device_state::UNAVAILABLE,
+            ),
+            // The kernel reports DORMANT only above a lower layer that is up.
+            (
+                IFF_UP | IFF_LOWER_UP | IFF_DORMANT,
+                Some(true),
+                if_oper::DORMANT,
+                false,
+                nm_device_state::CONFIG,
+            ),
+            (
+                IFF_UP,
+                Some(false),
+                if_oper::LOWERLAYERDOWN,
+                false,
+                nm_device_state::UNAVAILABLE,
+            ),
+            // Other operstates that leave the link unusable, even with an
+            // address.
+            (
+                IFF_UP,
+                Some(false),
+                if_oper::DOWN,
+                true,
+                nm_device_state::UNAVAILABLE,
+            ),
+            (
+                IFF_UP,
+                None,
+                if_oper::NOTPRESENT,
+                false,
+                nm_device_state::UNAVAILABLE,
+            ),
+            (
+                IFF_UP | IFF_LOWER_UP | IFF_RUNNING,
+                Some(true),
+                if_oper::TESTING,
+                true,
+                nm_device_state::UNAVAILABLE,
+            ),
+            // An address doesn't make a dormant link usable.
+            (
+                IFF_UP | IFF_LOWER_UP | IFF_DORMANT,
+                Some(true),
+                if_oper::DORMANT,
+                true,
+                nm_device_state::CONFIG,
+            ),
+            (
+                IFF_UP | IFF_LOWER_UP | IFF_DORMANT,
+                None,
+                if_oper::UNKNOWN,
+                true,
+                nm_device_state::CONFIG,
+            ),
+            (
+                IFF_UP | IFF_DORMANT,
+                Some(false),
+                if_oper::UNKNOWN,
+                true,
+                nm_device_state::UNAVAILABLE,
+            ),
+            // Not dormant: carrier alone decides, IP config finishes it.
+            (
+                IFF_UP | IFF_LOWER_UP | IFF_RUNNING,
+                None,
+                if_oper::UNKNOWN,
+                false,
+                nm_device_state::IP_CONFIG,
+            ),
+            (
+                IFF_UP | IFF_LOWER_UP | IFF_RUNNING,
+                None,
+                if_oper::UNKNOWN,
+                true,
+                nm_device_state::ACTIVATED,
+            ),
+            (
+                IFF_UP | IFF_LOWER_UP | IFF_RUNNING,
+                Some(true),
+                if_oper::UP,
+                true,
+                nm_device_state::ACTIVATED,
+            ),
+            (
+                IFF_UP,
+                None,
+                if_oper::UNKNOWN,
+                false,
+                nm_device_state::UNAVAILABLE,
+            ),
+            // Administratively down wins over everything.
+            (
+                IFF_LOWER_UP | IFF_DORMANT,
+                Some(true),
+                if_oper::DORMANT,
+                true,
+                nm_device_state::DISCONNECTED,
+            ),
+        ];
+        for (flags, carrier, operstate, has_ip_config, expected) in cases {
+            assert_eq!(
+                netlink_flags_to_nm_device(flags, carrier, operstate, true, has_ip_config),
+                expected,
+                "flags {flags:#x}, carrier {carrier:?}, operstate {operstate}, \
+                 IP config {has_ip_config}"
+            );
+        }
+    }
+
+    #[test]
+    fn idle_link_without_address_is_not_connecting() {
+        use crate::state::DeviceInfo;
+        use std::collections::HashMap;
+
+        // Up with carrier, no DHCP client or static address: IP_CONFIG
+        // indefinitely.
+        let mut dev = DeviceInfo::new(2, "eth0".to_string());
+        dev.nm_state = nm_device_state::IP_CONFIG;
+        let devices = HashMap::from([(2, dev)]);
+
+        assert_eq!(
+            deduce_global_state(&devices, |_| false),
+            nm_state::DISCONNECTED
+        );
+        assert_eq!(
+            deduce_global_state(&devices, |ifindex| ifindex == 2),
+            nm_state::CONNECTING
+        );
+    }
+
+    #[test]
+    fn global_state_from_mixed_device_transitions() {
+        use crate::state::{AddrInfo, DeviceInfo};
+        use std::collections::HashMap;
+        use std::net::Ipv4Addr;
+
+        use nm_device_state::*;
+
+        let device = |ifindex: i32, nm_state: u32| {
+            let mut dev = DeviceInfo::new(ifindex, format!("eth{ifindex}"));
+            dev.nm_state = nm_state;
+            dev
+        };
+        let with_address = |mut dev: DeviceInfo| {
+            dev.ipv4_addrs.push(AddrInfo {
+                address: Ipv4Addr::new(192, 0, 2, 10),
+                prefix_len: 24,
+                is_permanent: true,
+                scope: 0,
+                label: None,
+            });
+            dev
+        };
+        let unmanaged = |mut dev: DeviceInfo| {
+            dev.managed = false;
+            dev
+        };
+
+        let cases = [
+            (
+                vec![device(2, CONFIG), device(3, DEACTIVATING)],
+                nm_state::CONNECTING,
+            ),
+            (
+                vec![device(2, IP_CONFIG), device(3, DISCONNECTED)],
+                nm_state::DISCONNECTED,
+            ),
+            (
+                vec![device(3, IP_CONFIG), device(4, DISCONNECTED)],
+                nm_state::CONNECTING,
+            ),
+            (
+                vec![device(2, DEACTIVATING), device(3, UNAVAILABLE)],
+                nm_state::DISCONNECTING,
+            ),
+            (
+                vec![device(2, PREPARE), with_address(device(3, ACTIVATED))],
+                nm_state::CONNECTED_LOCAL,
+            ),
+            (
+                vec![with_address(device(2, DEACTIVATING)), device(3, CONFIG)],
+                nm_state::CONNECTING,
+            ),
+            (
+                vec![
+                    with_address(device(2, DEACTIVATING)),
+                    device(3, DISCONNECTED),
+                ],
+                nm_state::DISCONNECTING,
+            ),
+            (
+                vec![unmanaged(device(2, CONFIG)), device(3, DISCONNECTED)],
+                nm_state::DISCONNECTED,
+            ),
+            (
+                vec![unmanaged(device(2, DEACTIVATING)), device(3, CONFIG)],
+                nm_state::CONNECTING,
+            ),
+        ];
+        for (devices, expected) in cases {
+            let states: Vec<u32> = devices.iter().map(|d| d.nm_state).collect();
+            let devices: HashMap<i32, DeviceInfo> =
+                devices.into_iter().map(|d| (d.ifindex, d)).collect();
+            // An activation was requested on ifindex 3 only.
+            let global = deduce_global_state(&devices, |ifindex| ifindex == 3);
+            assert_eq!(global, expected, "{states:?}");
+        }
+    }
+
+    #[test]
+    fn explicit_carrier_overrides_the_flags() {
+        use nm_device_interface_flags::{CARRIER, LOWER_UP, UP};
+
+        // (flags, IFLA_CARRIER, carrier, interface flags, device state)
+        let cases = [
+            // No IFLA_CARRIER: inferred from RUNNING or LOWER_UP.
+            (
+                IFF_UP | IFF_RUNNING,
+                None,
+                true,
+                UP | CARRIER,
+                nm_device_state::IP_CONFIG,
+            ),
+            (
+                IFF_UP | IFF_LOWER_UP,
+                None,
+                true,
+                UP | LOWER_UP | CARRIER,
+                nm_device_state::IP_CONFIG,
+            ),
+            (IFF_UP, None, false, UP, nm_device_state::UNAVAILABLE),
+            // IFLA_CARRIER agrees with the flags.
+            (
+                IFF_UP | IFF_RUNNING | IFF_LOWER_UP,
+                Some(true),
+                true,
+                UP | LOWER_UP | CARRIER,
+                nm_device_state::IP_CONFIG,
+            ),
+            (IFF_UP, Some(false), false, UP, nm_device_state::UNAVAILABLE),
+            // IFLA_CARRIER contradicts the flags, and wins.
+            (
+                IFF_UP | IFF_RUNNING,
+                Some(false),
+                false,
+                UP,
+                nm_device_state::UNAVAILABLE,
+            ),
+            (
+                IFF_UP,
+                Some(true),
+                true,
+                UP | CARRIER,
+                nm_device_state::IP_CONFIG,
+            ),
+        ];
+        for (flags, kernel_carrier, carrier, iface_flags, state) in cases {
+            let mut dev = crate::state::DeviceInfo::new(2, "eth0".to_string());
+            dev.link_flags = flags;
+            dev.kernel_carrier = kernel_carrier;
+            let case = format!("flags {flags:#x}, IFLA_CARRIER {kernel_carrier:?}");
+            assert_eq!(dev.carrier(), carrier, "{case}");
+            assert_eq!(
+                netlink_flags_to_interface_flags(flags, kernel_carrier),
+                iface_flags,
+                "{case}"
+            );
+            assert_eq!(
+                netlink_flags_to_nm_device(flags, kernel_carrier, if_oper::UNKNOWN, true, false),
+                state,
+                "{case}"
+            );
+        }
     }
 }
@@ -0,0 +1,363 @@
+use netlink_packet_core::NlasIterator;
+use netlink_sys::protocols::NETLINK_GENERIC;
+use netlink_sys::{AsyncSocket, AsyncSocketExt, SocketAddr, TokioSocket};
+use tracing::{debug, info, warn};
+use zbus::Connection;
+
+use crate::nm;
+use crate::state::SharedState;
+
+const NLMSG_HDRLEN: usize = 16;
+const GENL_HDRLEN: usize = 4;
+const NLMSG_ERROR: u16 = 2;
+const NLM_F_REQUEST: u16 = 1;
+
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+const CTRL_ATTR_MCAST_GROUPS: u16 = 7;
+const CTRL_ATTR_MCAST_GRP_NAME: u16 = 1;
+const CTRL_ATTR_MCAST_GRP_ID: u16 = 2;
+
+const ETHTOOL_GENL_NAME: &str = "ethtool";
+const ETHTOOL_MCGRP_MONITOR_NAME: &str = "monitor";
+const ETHTOOL_MSG_LINKMODES_NTF: u8 = 5;
+const ETHTOOL_A_LINKMODES_HEADER: u16 = 1;
+const ETHTOOL_A_LINKMODES_SPEED: u16 = 5;
+const ETHTOOL_A_LINKMODES_DUPLEX: u16 = 6;
+const ETHTOOL_A_HEADER_DEV_INDEX: u16 = 1;
+
+const SPEED_UNKNOWN: u32 = u32::MAX;
+const DUPLEX_HALF: u8 = 0;
+const DUPLEX_FULL: u8 = 1;
+
+/// A LINKMODES_NTF: the new speed (Mb/s, 0 if unknown) and duplex of a link.
+struct LinkModes {
+    ifindex: i32,
+    speed: u32,
+    duplex: &'static str,
+}
+
+/// Follow ethtool's netlink "monitor" group and announce speed and duplex
+/// changes. The kernel only notifies when link modes are set (`ethtool -s`);
+/// renegotiation after a carrier change is picked up from the link update.
+pub async fn run(nm_conn: Connection, shared: SharedState) {
+    let mut socket = match TokioSocket::new(NETLINK_GENERIC) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("generic netlink unavailable, link mode changes won't be tracked: {e}");
+            return;
+        }
+    };
+    if let Err(e) = socket.socket_mut().bind_auto() {
+        warn!("failed to bind generic netlink socket: {e}");
+        return;
+    }
+
+    let (family, group) = match resolve_family(&mut socket).await {
+        Ok(Some(ids)) => ids,
+        Ok(None) => {
+            info!("kernel has no ethtool netlink interface, link mode changes won't be tracked");
+            return;
+        }
+        Err(e) => {
+            warn!("failed to resolve the ethtool netlink family: {e}");
+            return;
+        }
+    };
+    if let Err(e) = socket.socket_mut().add_membership(group) {
+        warn!("failed to join the ethtool monitor group: {e}");
+        return;
+    }
+    debug!(family, group, "subscribed to ethtool notifications");
+
+    loop {
+        let buf = match socket.recv_from_full().await {
+            Ok((buf, _)) => buf,
+            Err(e) => {
+                warn!("ethtool netlink read failed: {e}");
+                return;
+            }
+        };
+        for (msg_type, payload) in messages(&buf) {
+            if msg_type != family {
+                continue;
+            }
+            if let Some(modes) = parse_linkmodes_ntf(payload) {
+                apply(&nm_conn, &shared, modes).await;
+            }
+        }
+    }
+}
+
+/// Record a device's new link modes, notifying clients if they changed.
+async fn apply(nm_conn: &Connection, shared: &SharedState, modes: LinkModes) {
+    let changed = {
+        let mut state = shared.write().await;
+        let Some(dev) = state
+            .devices
+            .get_mut(&modes.ifindex)
+            .filter(|d| d.has_wired_interface())
+        else {
+            return;
+        };
+        let (speed, duplex) = if dev.carrier() {
+            (modes.speed, modes.duplex)
+        } else {
+            (0, "")
+        };
+        dev.set_link_mode(speed, duplex)
+    };
+    if let Some((speed, duplex)) = changed {
+        nm::signals::notify_device_speed_changed(nm_conn, modes.ifindex, speed, &duplex).await;
+    }
+}
+
+/// Ask the generic netlink controller for the ethtool family id and its
+/// monitor multicast group. None if the kernel predates ethtool netlink.
+async fn resolve_family(socket: &mut TokioSocket) -> std::io::Result<Option<(u16, u32)>> {
+    let mut name = ETHTOOL_GENL_NAME.as_bytes().to_vec();
+    name.push(0);
+    let mut attr = Vec::new();
+    push_attr(&mut attr, CTRL_ATTR_FAMILY_NAME, &name);
+
+    let len = NLMSG_HDRLEN + GENL_HDRLEN + attr.len();
+    let mut req = Vec::with_capacity(len);
+    req.extend_from_slice(&(len as u32).to_ne_bytes());
+    req.extend_from_slice(&GENL_ID_CTRL.to_ne_bytes());
+    req.extend_from_slice(&NLM_F_REQUEST.to_ne_bytes());
+    req.extend_from_slice(&1u32.to_ne_bytes()); // sequence number
+    req.extend_from_slice(&0u32.to_ne_bytes()); // port id: the kernel's
+    req.extend_from_slice(&[CTRL_CMD_GETFAMILY, 1, 0, 0]);
+    req.extend_from_slice(&attr);
+    socket.send_to(&req, &SocketAddr::new(0, 0)).await?;
+
+    let (buf, _) = socket.recv_from_full().await?;
+    for (msg_type, payload) in messages(&buf) {
+        if msg_type == NLMSG_ERROR {
+            // ENOENT: no such family.
+            return Ok(None);
+        }
+        if msg_type != GENL_ID_CTRL || payload.len() < GENL_HDRLEN {
+            continue;
+        }
+        let mut family = None;
+        let mut group = None;
+        for nla in NlasIterator::new(&payload[GENL_HDRLEN..]).flatten() {
+            match nla.kind() {
+                CTRL_ATTR_FAMILY_ID => family = read_u16(nla.value()),
+                CTRL_ATTR_MCAST_GROUPS => group = monitor_group(nla.value()),
+                _ => {}
+            }
+        }
+        return Ok(family.zip(group));
+    }
+    Ok(None)
+}
+
+/// The id of the "monitor" entry in a CTRL_ATTR_MCAST_GROUPS nest.
+fn monitor_group(groups: &[u8]) -> Option<u32> {
+    NlasIterator::new(groups).flatten().find_map(|grp| {
+        let mut monitor = false;
+        let mut id = None;
+        for nla in NlasIterator::new(grp.value()).flatten() {
+            match nla.kind() {
+                CTRL_ATTR_MCAST_GRP_NAME => {
+                    monitor = nla.value().split(|&b| b == 0).next()
+                        == Some(ETHTOOL_MCGRP_MONITOR_NAME.as_bytes());
+                }
+                CTRL_ATTR_MCAST_GRP_ID => id = read_u32(nla.value()),
+                _ => {}
+            }
+        }
+        id.filter(|_| monitor)
+    })
+}
+
+/// Decode an ethtool message if it's a LINKMODES_NTF for a known ifindex.
+fn parse_linkmodes_ntf(payload: &[u8]) -> Option<LinkModes> {
+    if payload.len() < GENL_HDRLEN || payload[0] != ETHTOOL_MSG_LINKMODES_NTF {
+        return None;
+    }
+    let mut ifindex = None;
+    let mut speed = 0;
+    let mut duplex = "";
+    for nla in NlasIterator::new(&payload[GENL_HDRLEN..]).flatten() {
+        match nla.kind() {
+            ETHTOOL_A_LINKMODES_HEADER => {
+                ifindex = NlasIterator::new(nla.value())
+                    .flatten()
+                    .find(|h| h.kind() == ETHTOOL_A_HEADER_DEV_INDEX)
+                    .and_then(|h| read_u32(h.value()));
+            }
+            ETHTOOL_A_LINKMODES_SPEED => {
+                speed = read_u32(nla.value())
+                    .filter(|&s| s != SPEED_UNKNOWN)
+                    .unwrap_or(0);
+            }
+            ETHTOOL_A_LINKMODES_DUPLEX => {
+                duplex = match nla.value().first() {
+                    Some(&DUPLEX_HALF) => "half",
+                    Some(&DUPLEX_FULL) => "full",
+                    _ => "",
+                };
+            }
+            _ => {}
+        }
+    }
+    Some(LinkModes {
+        ifindex: ifindex? as i32,
+        speed,
+        duplex,
+    })
+}
+
+/// Split a datagram into (nlmsg_type, payload) pairs.
+fn messages(buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut rest = buf;
+    std::iter::from_fn(move || {
+        let len = read_u32(rest.get(..4)?)? as usize;
+        if len < NLMSG_HDRLEN || len > rest.len() {
+            return None;
+        }
+        let msg_type = read_u16(&rest[4..6])?;
+        let payload = &rest[NLMSG_HDRLEN..len];
+        rest = rest.get(align(len)..).unwrap_or_default();
+        Some((msg_type, payload))
+    })
+}
+
+fn push_attr(buf: &mut Vec<u8>, kind: u16, value: &[u8]) {
+    let len = 4 + value.len();
+    buf.extend_from_slice(&(len as u16).to_ne_bytes());
+    buf.extend_from_slice(&kind.to_ne_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(align(buf.len()), 0);
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn read_u16(bytes: &[u8]) -> Option<u16> {
+    Some(u16::from_ne_bytes(bytes.get(..2)?.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A netlink message of `msg_type` with a generic netlink header for
+    /// `cmd`, followed by `attrs`.
+    fn genl_message(msg_type: u16, cmd: u8, attrs: &[u8]) -> Vec<u8> {
+        let len = NLMSG_HDRLEN + GENL_HDRLEN + attrs.len();
+        let mut buf = Vec::with_capacity(len);
+        buf.extend_from_slice(&(len as u32).to_ne_bytes());
+        buf.extend_from_slice(&msg_type.to_ne_bytes());
+        buf.extend_from_slice(&0u16.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&[cmd, 1, 0, 0]);
+        buf.extend_from_slice(attrs);
+        buf
+    }
+
+    fn linkmodes_attrs(ifindex: Option<u32>, speed: u32, duplex: u8) -> Vec<u8> {
+        let mut attrs = Vec::new();
+        if let Some(ifindex) = ifindex {
+            let mut header = Vec::new();
+            push_attr(
+                &mut header,
+                ETHTOOL_A_HEADER_DEV_INDEX,
+                &ifindex.to_ne_bytes(),
+            );
+            push_attr(&mut attrs, ETHTOOL_A_LINKMODES_HEADER, &header);
+        }
+        push_attr(&mut attrs, ETHTOOL_A_LINKMODES_SPEED, &speed.to_ne_bytes());
+        push_attr(&mut attrs, ETHTOOL_A_LINKMODES_DUPLEX, &[duplex]);
+        attrs
+    }
+
+    fn parse(buf: &[u8]) -> Option<LinkModes> {
+        let (_, payload) = messages(buf).next()?;
+        parse_linkmodes_ntf(payload)
+    }
+
+    #[test]
+    fn linkmodes_ntf_is_decoded() {
+        let attrs = linkmodes_attrs(Some(3), 1000, DUPLEX_FULL);
+        let modes = parse(&genl_message(30, ETHTOOL_MSG_LINKMODES_NTF, &attrs)).unwrap();
+        assert_eq!(
+            (modes.ifindex, modes.speed, modes.duplex),
+            (3, 1000, "full")
+        );
+
+        let attrs = linkmodes_attrs(Some(4), 10, DUPLEX_HALF);
+        let modes = parse(&genl_message(30, ETHTOOL_MSG_LINKMODES_NTF, &attrs)).unwrap();
+        assert_eq!((modes.ifindex, modes.speed, modes.duplex), (4, 10, "half"));
+    }
+
+    #[test]
+    fn unknown_speed_and_duplex_read_as_unset() {
+        let attrs = linkmodes_attrs(Some(3), SPEED_UNKNOWN, 0xff);
+        let modes = parse(&genl_message(30, ETHTOOL_MSG_LINKMODES_NTF, &attrs)).unwrap();
+        assert_eq!((modes.speed, modes.duplex), (0, ""));
+    }
+
+    #[test]
+    fn other_messages_are_skipped() {
+        let attrs = linkmodes_attrs(Some(3), 1000, DUPLEX_FULL);
+        // LINKINFO_NTF carries no link modes.
+        assert!(parse(&genl_message(30, 3, &attrs)).is_none());
+        // Without a header naming the device there is nothing to update.
+        let attrs = linkmodes_attrs(None, 1000, DUPLEX_FULL);
+        assert!(parse(&genl_message(30, ETHTOOL_MSG_LINKMODES_NTF, &attrs)).is_none());
+        assert!(parse_linkmodes_ntf(&[ETHTOOL_MSG_LINKMODES_NTF]).is_none());
+    }
+
+    #[test]
+    fn datagram_is_split_into_messages() {
+        let mut buf = genl_message(30, ETHTOOL_MSG_LINKMODES_NTF, &[]);
+        buf.extend(genl_message(31, 1, &[0xaa]));
+        buf.resize(align(buf.len()), 0);
+        buf.extend(genl_message(32, 2, &[]));
+        let types: Vec<u16> = messages(&buf).map(|(t, _)| t).collect();
+        assert_eq!(types, [30, 31, 32]);
+    }
+
+    #[test]
+    fn truncated_message_ends_the_datagram() {
+        let mut buf = genl_message(30, ETHTOOL_MSG_LINKMODES_NTF, &[]);
+        let mut short = genl_message(31, 1, &[0; 8]);
+        short.truncate(short.len() - 4);
+        buf.extend(short);
+        let types: Vec<u16> = messages(&buf).map(|(t, _)| t).collect();
+        assert_eq!(types, [30]);
+        assert_eq!(messages(&[1, 0]).count(), 0);
+    }
+
+    #[test]
+    fn monitor_group_is_found_among_groups() {
+        let group = |name: &str, id: u32| {
+            let mut name = name.as_bytes().to_vec();
+            name.push(0);
+            let mut grp = Vec::new();
+            push_attr(&mut grp, CTRL_ATTR_MCAST_GRP_ID, &id.to_ne_bytes());
+            push_attr(&mut grp, CTRL_ATTR_MCAST_GRP_NAME, &name);
+            grp
+        };
+        let mut groups = Vec::new();
+        push_attr(&mut groups, 1, &group("config", 7));
+        push_attr(&mut groups, 2, &group("monitor", 9));
+        assert_eq!(monitor_group(&groups), Some(9));
+
+        let mut groups = Vec::new();
+        push_attr(&mut groups, 1, &group("monitors", 7));
+        assert_eq!(monitor_group(&groups), None);
+    }
+}
@@ -1,33 +1,96 @@
+pub mod linkmodes;
 pub mod monitor;
 pub mod queries;
+pub mod source;
 
-use futures::TryStreamExt;
-use netlink_packet_route::link::{InfoKind, LinkAttribute, LinkInfo};
-use tracing::info;
+use std::net::IpAddr;
+
+use netlink_packet_core::{Nla, NlasIterator};
+use netlink_packet_route::link::{
+    InfoBond, InfoData, InfoKind, InfoVlan, LinkAttribute, LinkInfo, LinkLayerType,
+};
+use netlink_sys::AsyncSocket;
+use rtnetlink::constants::{
+    RTMGRP_IPV4_IFADDR, RTMGRP_IPV4_ROUTE, RTMGRP_IPV6_IFADDR, RTMGRP_IPV6_ROUTE, RTMGRP_LINK,
+    RTMGRP_NEIGH,
+};
+use tracing::{debug, info, warn};
 
 use netlink_packet_route::link::LinkMessage;
 
-use crate::Result;
+use crate::config::{self, Config};
 use crate::mapping;
-use crate::state::{DeviceInfo, SharedState};
+use crate::persistence;
+use crate::state::{DeviceInfo, SharedState, TunnelInfo, VlanInfo};
+use crate::{Error, Result};
+
+use source::EventStream;
 
-/// Build a DeviceInfo from a netlink LinkMessage, or None if the interface should be ignored.
-pub fn device_from_link_msg(msg: &LinkMessage) -> Option<DeviceInfo> {
+/// Build a DeviceInfo from a netlink LinkMessage, or None if the interface
+/// matches one of the configured `ignore_interfaces` patterns. Reads sysfs;
+/// see [`read_sysfs_details`].
+pub fn device_from_link_msg(msg: &LinkMessage, config: &Config) -> Option<DeviceInfo> {
+    let mut dev = parse_link_msg(msg, config)?;
+    read_sysfs_details(&mut dev);
+    Some(dev)
+}
+
+/// [`device_from_link_msg`] without the sysfs reads: everything the message
+/// itself carries, which is all that identifies the link (name, kind, type,
+/// ignored). Cheap enough to run with the state lock held.
+pub fn parse_link_msg(msg: &LinkMessage, config: &Config) -> Option<DeviceInfo> {
     let ifindex = msg.header.index as i32;
     let flags = msg.header.flags.bits();
 
     let mut name = None;
+    let mut alias = None;
     let mut mac = None;
-    let mut is_wireguard = false;
+    let mut perm_mac = None;
+    let mut mtu = 0;
+    let mut tx_queue_len = 0;
+    let mut num_tx_queues = 0;
+    let mut operstate = mapping::if_oper::UNKNOWN;
+    let mut carrier = None;
+    let mut kind = None;
+    let mut parent = None;
+    let mut controller = None;
+    let mut vlan = None;
+    let mut tunnel_data = Vec::new();
 
     for attr in &msg.attributes {
         match attr {
             LinkAttribute::IfName(n) => name = Some(n.clone()),
+            LinkAttribute::IfAlias(a) => alias = Some(a.clone()).filter(|a| !a.is_empty()),
             LinkAttribute::Address(bytes) => mac = Some(queries::format_mac(bytes)),
+            LinkAttribute::PermAddress(bytes) => {
+                perm_mac = Some(queries::format_mac(bytes)).filter(|m| !m.is_empty());
+            }
+            LinkAttribute::Link(idx) => parent = Some(*idx as i32),
+            LinkAttribute::Controller(idx) => controller = Some(*idx as i32),
+            LinkAttribute::Mtu(m) => mtu = *m,
+            LinkAttribute::TxQueueLen(q) => tx_queue_len = *q,
+            LinkAttribute::NumTxQueues(n) => num_tx_queues = *n,
+            LinkAttribute::OperState(s) => operstate = (*s).into(),
+            LinkAttribute::Carrier(c) => carrier = Some(*c != 0),
             LinkAttribute::LinkInfo(infos) => {
                 for info in infos {
-                    if let LinkInfo::Kind(InfoKind::Wireguard) = info {
-                        is_wireguard = true;
+                    match info {
+                        LinkInfo::Kind(k) => kind = Some(k.clone()),
+                        LinkInfo::Data(InfoData::Vlan(data)) => vlan = Some(parse_vlan(data)),
+                        LinkInfo::Data(InfoData::GreTun(data)) => {
+                            tunnel_data = data.iter().map(nla_parts).collect();
+                        }
+                        LinkInfo::Data(InfoData::SitTun(data)) => {
+                            tunnel_data = data.iter().map(nla_parts).collect();
+                        }
+                        // ipip data has no typed representation
+                        LinkInfo::Data(InfoData::Other(bytes)) => {
+                            tunnel_data = NlasIterator::new(bytes.as_slice())
+                                .filter_map(|nla| nla.ok())
+                                .map(|nla| (nla.kind(), nla.value().to_vec()))
+                                .collect();
+                        }
+                        _ => {}
                     }
                 }
             }
@@ -35,93 +98,547 @@ pub fn device_from_link_msg(msg: &LinkMessage) -> Option<DeviceInfo> {
         }
     }
 
-    let iface_name = name?;
+    let iface_name = name.filter(|n| is_valid_ifname(n))?;
 
-    // WireGuard interfaces bypass the prefix filter
-    if !is_wireguard && should_ignore_interface(&iface_name) {
+    // WireGuard and configured VPN interfaces bypass the prefix filter
+    let is_wireguard = kind == Some(InfoKind::Wireguard);
+    let is_vpn = config.is_vpn(&iface_name);
+    let ignored =
+        !is_wireguard && !is_vpn && should_ignore_interface(&iface_name, &config.ignore_interfaces);
+    if ignored && !config.show_ignored_as_unmanaged {
         return None;
     }
 
     let mut dev = DeviceInfo::new(ifindex, iface_name);
+    dev.ignored = ignored;
+    dev.alias = alias;
+    dev.managed = !ignored && !config.is_unmanaged(&dev.name);
+    dev.vpn = is_vpn;
     if let Some(m) = mac {
         dev.hw_address = m;
     }
-    if is_wireguard {
-        dev.device_type = mapping::nm_device_type::WIREGUARD;
+    dev.perm_hw_address = perm_mac;
+    dev.controller = controller;
+    dev.device_type = if is_vpn {
+        mapping::nm_device_type::WIREGUARD
+    } else if config.is_modem(&dev.name) {
+        mapping::nm_device_type::MODEM
+    } else {
+        detect_device_type(kind.as_ref(), msg.header.link_layer_type)
+    };
+    dev.arp_type = msg.header.link_layer_type.into();
+    dev.type_description = match &kind {
+        Some(k) => k.to_string(),
+        None => format!("{:?}", msg.header.link_layer_type).to_lowercase(),
+    };
+    if let Some(mut vlan) = vlan {
+        vlan.parent = parent;
+        dev.vlan = Some(vlan);
+    }
+    if let Some(kind) = &kind {
+        dev.tunnel = parse_tunnel(kind, &tunnel_data);
+    }
+    if let Some(active_slave) = bond_active_slave(msg) {
+        dev.bond_active_slave = active_slave;
     }
     dev.link_flags = flags;
-    dev.nm_state = mapping::netlink_flags_to_nm_device(flags, false, false);
+    dev.kernel_carrier = carrier;
+    dev.operstate = operstate;
+    dev.mtu = mtu;
+    dev.tx_queue_len = tx_queue_len;
+    dev.num_tx_queues = num_tx_queues;
+    dev.nm_state =
+        mapping::netlink_flags_to_nm_device(flags, carrier, operstate, dev.managed, false);
     Some(dev)
 }
 
+/// Fill in what the link message doesn't carry: the driver, and speed and
+/// duplex of wired links. Blocking sysfs reads, so never with the state lock
+/// held.
+pub fn read_sysfs_details(dev: &mut DeviceInfo) {
+    dev.driver = DeviceInfo::read_driver(&dev.name);
+    if dev.has_wired_interface() {
+        (dev.link_speed, dev.duplex) = DeviceInfo::read_link_mode(&dev.name, dev.carrier());
+    }
+}
+
+/// Whether `name` is an interface name the kernel could have produced
+/// (`dev_valid_name`). Anything else comes from a corrupt message, and must
+/// not reach sysfs paths or D-Bus.
+fn is_valid_ifname(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() < 16
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', ':'])
+        && !name.contains(char::is_whitespace)
+}
+
+/// Determine the NM device type from IFLA_INFO_KIND, falling back to the
+/// ARPHRD link-layer type for physical devices (which carry no kind).
+fn detect_device_type(kind: Option<&InfoKind>, link_layer: LinkLayerType) -> u32 {
+    use mapping::nm_device_type::*;
+
+    match kind {
+        Some(InfoKind::Wireguard) => WIREGUARD,
+        Some(InfoKind::Vlan) => VLAN,
+        Some(InfoKind::Bond) => BOND,
+        Some(InfoKind::Bridge) => BRIDGE,
+        Some(InfoKind::Dummy) => DUMMY,
+        Some(InfoKind::Veth) => VETH,
+        Some(InfoKind::Vxlan) => VXLAN,
+        Some(InfoKind::Vrf) => VRF,
+        Some(InfoKind::MacVlan | InfoKind::MacVtap) => MACVLAN,
+        Some(InfoKind::Ipoib) => INFINIBAND,
+        Some(InfoKind::GreTun | InfoKind::SitTun | InfoKind::IpTun) => IP_TUNNEL,
+        Some(_) => GENERIC,
+        None => match link_layer {
+            LinkLayerType::Ether => ETHERNET,
+            LinkLayerType::Infiniband => INFINIBAND,
+            LinkLayerType::Loopback => LOOPBACK,
+            LinkLayerType::Ppp => PPP,
+            _ => GENERIC,
+        },
+    }
+}
+
+/// Extract VLAN id and flags from IFLA_INFO_DATA.
+fn parse_vlan(data: &[InfoVlan]) -> VlanInfo {
+    let mut vlan = VlanInfo::default();
+    for nla in data {
+        match nla {
+            InfoVlan::Id(id) => vlan.id = *id,
+            InfoVlan::Flags((flags, _mask)) => vlan.flags = *flags,
+            _ => {}
+        }
+    }
+    vlan
+}
+
+/// A bond's active slave (IFLA_BOND_ACTIVE_SLAVE) from IFLA_INFO_DATA: None
+/// when the message carries no bond data, Some(None) when no slave is active.
+pub fn bond_active_slave(msg: &LinkMessage) -> Option<Option<i32>> {
+    msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::LinkInfo(infos) => infos.iter().find_map(|info| match info {
+            LinkInfo::Data(InfoData::Bond(data)) => Some(data.iter().find_map(|nla| match nla {
+                InfoBond::ActivePort(idx) if *idx != 0 => Some(*idx as i32),
+                _ => None,
+            })),
+            _ => None,
+        }),
+        _ => None,
+    })
+}
+
+/// Type and raw value of a netlink attribute.
+fn nla_parts(nla: &impl Nla) -> (u16, Vec<u8>) {
+    let mut value = vec![0; nla.value_len()];
+    nla.emit_value(&mut value);
+    (nla.kind(), value)
+}
+
+/// Extract mode and endpoints of a GRE/SIT/IPIP tunnel from its
+/// IFLA_INFO_DATA attributes. None for any other link kind.
+fn parse_tunnel(kind: &InfoKind, data: &[(u16, Vec<u8>)]) -> Option<TunnelInfo> {
+    use mapping::nm_ip_tunnel_mode::*;
+
+    // (mode, local attr, remote attr): IFLA_GRE_LOCAL/REMOTE for GRE,
+    // IFLA_IPTUN_LOCAL/REMOTE for SIT and IPIP.
+    let (mode, local_attr, remote_attr) = match kind {
+        InfoKind::GreTun => (GRE, 6, 7),
+        InfoKind::SitTun => (SIT, 2, 3),
+        InfoKind::IpTun => (IPIP, 2, 3),
+        _ => return None,
+    };
+
+    let mut tunnel = TunnelInfo {
+        mode,
+        ..TunnelInfo::default()
+    };
+    for (attr, value) in data {
+        let addr = match value.len() {
+            4 => IpAddr::from(<[u8; 4]>::try_from(value.as_slice()).ok()?),
+            16 => IpAddr::from(<[u8; 16]>::try_from(value.as_slice()).ok()?),
+            _ => continue,
+        };
+        // The kernel reports an unset endpoint as 0.0.0.0
+        let addr = (!addr.is_unspecified()).then_some(addr);
+        if *attr == local_attr {
+            tunnel.local = addr;
+        } else if *attr == remote_attr {
+            tunnel.remote = addr;
+        }
+    }
+    Some(tunnel)
+}
+
 /// Check if interface should be ignored (virtual interfaces, containers, etc.)
-pub fn should_ignore_interface(name: &str) -> bool {
-    const IGNORED_PREFIXES: &[&str] = &[
-        "lo",        // loopback
-        "docker",    // docker networks
-        "veth",      // virtual ethernet (containers)
-        "br-",       // docker bridges
-        "virbr",     // libvirt bridges
-        "vnet",      // libvirt tap devices
-        "tun",       // TUN devices
-        "tap",       // TAP devices
-        "tailscale", // Tailscale VPN
-        "podman",    // Podman container networks
-    ];
-
-    IGNORED_PREFIXES
-        .iter()
-        .any(|prefix| name.starts_with(prefix))
+/// against the configured `ignore_interfaces` patterns.
+pub fn should_ignore_interface(name: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|p| config::interface_matches(p, name))
+}
+
+/// Print every link the kernel reports and whether nmlinkd exports it, with
+/// the reason, for `nmlinkd --dump-state`. Uses a fresh dump, not the state
+/// of a running daemon.
+pub async fn dump_interfaces(config: &Config) -> Result<()> {
+    let (conn, handle, _) = rtnetlink::new_connection()?;
+    tokio::spawn(conn);
+
+    let mut exported = 0;
+    for msg in queries::dump_links(&handle).await? {
+        let name = msg
+            .attributes
+            .iter()
+            .find_map(|attr| match attr {
+                LinkAttribute::IfName(n) => Some(n.as_str()),
+                _ => None,
+            })
+            .unwrap_or("");
+        let (status, reason) = match device_from_link_msg(&msg, config) {
+            None if !is_valid_ifname(name) => ("ignored", "invalid interface name".to_string()),
+            None => {
+                let pattern = Config::matching_pattern(&config.ignore_interfaces, name);
+                (
+                    "ignored",
+                    format!("matches ignore_interfaces \"{}\"", pattern.unwrap_or("?")),
+                )
+            }
+            Some(_) if exported >= config.max_devices => (
+                "skipped",
+                format!("over max_devices ({})", config.max_devices),
+            ),
+            Some(dev) if dev.ignored => {
+                exported += 1;
+                let pattern = Config::matching_pattern(&config.ignore_interfaces, name);
+                (
+                    "unmanaged",
+                    format!(
+                        "matches ignore_interfaces \"{}\", shown as unmanaged",
+                        pattern.unwrap_or("?")
+                    ),
+                )
+            }
+            Some(dev) => {
+                exported += 1;
+                match Config::matching_pattern(&config.unmanaged, &dev.name) {
+                    Some(p) => ("unmanaged", format!("matches unmanaged \"{p}\"")),
+                    None => ("exported", String::new()),
+                }
+            }
+        };
+        let line = format!("{:>5}  {name:<16} {status:<10} {reason}", msg.header.index);
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+/// Open a netlink connection bound to the link/address/route multicast groups,
+/// plus the neighbour group when `neighbors` is set. Events are queued in the
+/// returned stream until the monitor consumes them.
+fn subscribe(neighbors: bool) -> Result<EventStream> {
+    let (mut conn, _handle, messages) = rtnetlink::new_connection().map_err(Error::Subscribe)?;
+
+    let mut mgroup_flags = RTMGRP_LINK
+        | RTMGRP_IPV4_IFADDR
+        | RTMGRP_IPV4_ROUTE
+        | RTMGRP_IPV6_IFADDR
+        | RTMGRP_IPV6_ROUTE;
+    if neighbors {
+        mgroup_flags |= RTMGRP_NEIGH;
+    }
+
+    let addr = netlink_sys::SocketAddr::new(0, mgroup_flags);
+    conn.socket_mut()
+        .socket_mut()
+        .bind(&addr)
+        .map_err(Error::Subscribe)?;
+
+    tokio::spawn(conn);
+
+    debug!(
+        "subscribed to netlink events, groups mask: 0x{:x}",
+        mgroup_flags
+    );
+    Ok(messages)
+}
+
+/// Warn about missing capabilities up front, rather than on the first
+/// failing D-Bus call: CAP_NET_ADMIN to change links (unless read-only),
+/// CAP_NET_RAW to bind connectivity probes to a link.
+fn check_capabilities(config: &Config) {
+    const CAP_NET_ADMIN: u32 = 12;
+    const CAP_NET_RAW: u32 = 13;
+
+    let Some(effective) = std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            let hex = status.lines().find_map(|l| l.strip_prefix("CapEff:"))?;
+            u64::from_str_radix(hex.trim(), 16).ok()
+        })
+    else {
+        return;
+    };
+    let has = |cap: u32| effective & (1 << cap) != 0;
+
+    if !config.read_only && !has(CAP_NET_ADMIN) {
+        warn!("missing CAP_NET_ADMIN: link changes requested over D-Bus will fail");
+    }
+    if config.connectivity.interval != 0 && !has(CAP_NET_RAW) {
+        warn!("missing CAP_NET_RAW: per-device connectivity probes will fail");
+    }
 }
 
 /// Load initial network state from kernel via netlink (no networkd dependency).
-pub async fn load_initial_state(shared: &SharedState) -> Result<()> {
+///
+/// Subscribes to netlink events *before* dumping, so nothing that happens
+/// during the dump is lost. The returned stream replays those buffered events
+/// to the monitor, which reconciles them against the dumped state.
+pub async fn load_initial_state(shared: &SharedState) -> Result<EventStream> {
+    let config = shared.read().await.config.clone();
+    check_capabilities(&config);
+    let events = subscribe(config.track_neighbors)?;
+
     let (conn, handle, _) = rtnetlink::new_connection()?;
     tokio::spawn(conn);
 
     // Store handle in shared state for reuse by all reload/query functions
-    shared.write().await.netlink_handle = Some(handle.clone());
+    {
+        let mut state = shared.write().await;
+        state.netlink_handle = Some(handle.clone());
+        state.profiles = persistence::load();
+    }
 
     // Load all network links
-    let mut links = handle.link().get().execute();
     let mut discovered_devices = Vec::new();
 
-    while let Some(msg) = links.try_next().await? {
-        if let Some(dev) = device_from_link_msg(&msg) {
+    for msg in queries::dump_links(&handle).await? {
+        if let Some(dev) = device_from_link_msg(&msg, &config) {
             info!(ifindex = dev.ifindex, name = %dev.name, "discovered link");
-            discovered_devices.push((dev.ifindex, dev));
+            discovered_devices.push(dev);
         }
     }
 
     // Insert devices into shared state
     {
         let mut state = shared.write().await;
-        for (ifindex, dev) in discovered_devices {
-            state.devices.insert(ifindex, dev);
+        for dev in discovered_devices {
+            if state.device_cap_reached(&dev) {
+                continue;
+            }
+            state.insert_device(dev);
         }
     }
 
     // Load addresses, gateways, DNS
     queries::load_initial_addresses(&handle, shared).await?;
+    if config.track_neighbors {
+        queries::load_neighbors(&handle, shared).await?;
+    }
 
     // Now update device states based on actual IPs
     {
         let mut state = shared.write().await;
         for dev in state.devices.values_mut() {
-            let has_ipv4 = !dev.ipv4_addrs.is_empty();
-            let has_ipv6 = !dev.ipv6_addrs.is_empty();
             // Re-evaluate state with IP info
-            if has_ipv4 || has_ipv6 {
-                if dev.nm_state == mapping::nm_device_state::IP_CONFIG {
-                    dev.nm_state = mapping::nm_device_state::ACTIVATED;
-                }
+            if dev.has_ip_config() && dev.nm_state == mapping::nm_device_state::IP_CONFIG {
+                dev.nm_state = mapping::nm_device_state::ACTIVATED;
             }
         }
 
+        state.update_controller_states();
+
         // Compute global state
-        state.global_state = mapping::deduce_global_state(&state.devices);
-        state.connectivity = mapping::global_state_to_connectivity(state.global_state);
+        state.recompute_global_state();
     }
 
-    Ok(())
+    persistence::reapply(&handle, shared).await;
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use netlink_packet_core::{Emitable, Parseable};
+    use netlink_packet_route::link::{LinkMessageBuffer, State};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// net/core/dev.c:dev_valid_name(), transcribed byte for byte.
+    fn kernel_dev_valid_name(name: &str) -> bool {
+        let bytes = name.as_bytes();
+        if bytes.is_empty() || bytes.len() >= 16 || name == "." || name == ".." {
+            return false;
+        }
+        !bytes
+            .iter()
+            .any(|&b| b == b'/' || b == b':' || b.is_ascii_whitespace() || b == 0x0b)
+    }
+
+    /// Every string of up to `max_len` characters drawn from `alphabet`.
+    fn all_strings(alphabet: &[char], max_len: usize) -> Vec<String> {
+        let mut all = vec![String::new()];
+        let mut previous = vec![String::new()];
+        for _ in 0..max_len {
+            previous = previous
+                .iter()
+                .flat_map(|s| alphabet.iter().map(move |&c| format!("{s}{c}")))
+                .collect();
+            all.extend(previous.iter().cloned());
+        }
+        all
+    }
+
+    #[test]
+    fn ifname_validation_matches_the_kernel() {
+        let alphabet = ['e', '0', '.', '-', '/', ':', ' ', '\t', '\n', '\x0b'];
+        let mut names = all_strings(&alphabet, 3);
+        // Lengths around IFNAMSIZ, bad characters at either end.
+        for len in 13..=17 {
+            let stem = "x".repeat(len);
+            names.push(stem.clone());
+            for bad in ["/", ":", " ", "."] {
+                names.push(format!("{bad}{}", &stem[1..]));
+                names.push(format!("{}{bad}", &stem[1..]));
+            }
+        }
+        for name in &names {
+            assert_eq!(
+                is_valid_ifname(name),
+                kernel_dev_valid_name(name),
+                "{name:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn default_ignore_patterns_need_a_digit_suffix() {
+        let patterns = Config::default().ignore_interfaces;
+        for name in [
+            "lo",
+            "docker0",
+            "virbr1",
+            "tun0",
+            "tap12",
+            "tailscale0",
+            "veth1a2b",
+        ] {
+            assert!(should_ignore_interface(name, &patterns), "{name} exported");
+        }
+        for name in [
+            "loX",
+            "lo0",
+            "tunnelbroker",
+            "tun",
+            "tun0a",
+            "tapir",
+            "dockerd",
+            "virbr",
+            "eth0",
+        ] {
+            assert!(!should_ignore_interface(name, &patterns), "{name} ignored");
+        }
+    }
+
+    #[test]
+    fn listed_vpn_interface_registers_as_a_vpn() {
+        // wireguard-go: a TUN device, not an in-kernel WireGuard link.
+        let mut msg = LinkMessage::default();
+        msg.header.index = 5;
+        msg.header.link_layer_type = LinkLayerType::None;
+        msg.attributes
+            .push(LinkAttribute::IfName("wg0".to_string()));
+        msg.attributes
+            .push(LinkAttribute::LinkInfo(vec![LinkInfo::Kind(InfoKind::Tun)]));
+        let mut config = Config::default();
+        config.ignore_interfaces.push("wg#".to_string());
+
+        assert!(device_from_link_msg(&msg, &config).is_none());
+
+        config.vpn_interfaces = vec!["wg0".to_string()];
+        let dev = device_from_link_msg(&msg, &config).expect("wg0 exported");
+        assert!(dev.vpn);
+        assert!(dev.managed);
+        assert!(!dev.ignored);
+        assert_eq!(dev.device_type, mapping::nm_device_type::WIREGUARD);
+        assert_eq!(
+            mapping::device_type_to_connection_type(dev.device_type),
+            "wireguard"
+        );
+        assert_eq!(dev.dns_priority(), 50);
+    }
+
+    /// A VLAN link as the kernel reports it, serialized: cutting it short
+    /// lands inside nested IFLA_LINKINFO attributes as well as flat ones.
+    fn vlan_link_bytes() -> Vec<u8> {
+        let mut msg = LinkMessage::default();
+        msg.header.index = 7;
+        msg.header.link_layer_type = LinkLayerType::Ether;
+        msg.attributes = vec![
+            LinkAttribute::IfName("eth0.10".to_string()),
+            LinkAttribute::IfAlias("uplink".to_string()),
+            LinkAttribute::Address(vec![0x52, 0x54, 0, 0x12, 0x34, 0x56]),
+            LinkAttribute::Mtu(1500),
+            LinkAttribute::OperState(State::Up),
+            LinkAttribute::Carrier(1),
+            LinkAttribute::Link(2),
+            LinkAttribute::LinkInfo(vec![
+                LinkInfo::Kind(InfoKind::Vlan),
+                LinkInfo::Data(InfoData::Vlan(vec![
+                    InfoVlan::Id(10),
+                    InfoVlan::Flags((1, 1)),
+                ])),
+            ]),
+        ];
+        let mut bytes = vec![0; msg.buffer_len()];
+        msg.emit(&mut bytes);
+        bytes
+    }
+
+    /// Parse `bytes` as an RTM_NEWLINK payload the way the monitor does and
+    /// turn whatever parses into a device. Must never panic, and must never
+    /// export a name the kernel would refuse.
+    fn device_from_bytes(bytes: &[u8]) -> Option<DeviceInfo> {
+        let buf = LinkMessageBuffer::new_checked(&bytes).ok()?;
+        let msg = LinkMessage::parse(&buf).ok()?;
+        let config = Config {
+            show_ignored_as_unmanaged: true,
+            ..Config::default()
+        };
+        let dev = device_from_link_msg(&msg, &config)?;
+        assert!(is_valid_ifname(&dev.name), "exported {:?}", dev.name);
+        Some(dev)
+    }
+
+    #[test]
+    fn sample_vlan_link_parses() {
+        let dev = device_from_bytes(&vlan_link_bytes()).expect("eth0.10 exported");
+        assert_eq!(dev.name, "eth0.10");
+        assert_eq!(dev.vlan.map(|v| (v.id, v.parent)), Some((10, Some(2))));
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_link_buffers_are_handled(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+            device_from_bytes(&bytes);
+        }
+
+        #[test]
+        fn truncated_link_buffers_are_handled(cut in any::<prop::sample::Index>()) {
+            let bytes = vlan_link_bytes();
+            device_from_bytes(&bytes[..cut.index(bytes.len())]);
+        }
+
+        #[test]
+        fn corrupted_link_buffers_are_handled(
+            at in any::<prop::sample::Index>(),
+            byte in any::<u8>(),
+        ) {
+            let mut bytes = vlan_link_bytes();
+            let at = at.index(bytes.len());
+            bytes[at] = byte;
+            device_from_bytes(&bytes);
+        }
+    }
 }
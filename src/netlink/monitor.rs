@@ -1,24 +1,21 @@
 use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
-use futures::stream::StreamExt;
-use netlink_packet_core::NetlinkPayload;
 use netlink_packet_route::RouteNetlinkMessage;
 use netlink_packet_route::link::{LinkAttribute, LinkMessage};
-use netlink_sys::AsyncSocket;
-use rtnetlink::constants::{
-    RTMGRP_IPV4_IFADDR, RTMGRP_IPV4_ROUTE, RTMGRP_IPV6_IFADDR, RTMGRP_IPV6_ROUTE, RTMGRP_LINK,
-};
-use tokio::time::{Instant, sleep_until};
+use tokio::signal::unix::{Signal, SignalKind, signal};
+use tokio::time::{Instant, Interval, MissedTickBehavior, interval_at, sleep_until};
 use tracing::{debug, info, warn};
 use zbus::Connection;
 
 use crate::Result;
+use crate::config;
 use crate::mapping;
 use crate::nm;
-use crate::state::SharedState;
+use crate::state::{AppState, DeviceInfo, NeighborInfo, SharedState, SharedStateExt};
 
 use super::queries;
+use super::source::{EventStream, MessageSource};
 
 const DEBOUNCE_DURATION: Duration = Duration::from_millis(50);
 
@@ -30,9 +27,13 @@ struct PendingEvents {
     /// Whether any NewRoute/DelRoute was received.
     routes_changed: bool,
     /// NewLink messages, keyed by ifindex (last message wins for flag updates).
+    /// Only messages that arrived after the ifindex's last DelLink are kept.
     new_links: HashMap<i32, LinkMessage>,
-    /// DelLink messages, keyed by ifindex.
+    /// DelLink messages, keyed by ifindex. Applied before `new_links`, so a
+    /// remove-then-add of a reused ifindex yields two distinct devices.
     del_links: HashMap<i32, LinkMessage>,
+    /// NewNeighbour/DelNeighbour entries in arrival order (`true` = deleted).
+    neighbors: Vec<(i32, NeighborInfo, bool)>,
 }
 
 impl PendingEvents {
@@ -41,6 +42,7 @@ impl PendingEvents {
             && !self.routes_changed
             && self.new_links.is_empty()
             && self.del_links.is_empty()
+            && self.neighbors.is_empty()
     }
 }
 
@@ -59,22 +61,33 @@ fn accumulate(msg: &RouteNetlinkMessage, pending: &mut PendingEvents) {
         }
         RouteNetlinkMessage::DelLink(link_msg) => {
             let ifindex = link_msg.header.index as i32;
+            // Whatever link was announced under this index is gone now.
+            pending.new_links.remove(&ifindex);
             pending.del_links.insert(ifindex, link_msg.clone());
         }
+        RouteNetlinkMessage::NewNeighbour(neigh_msg) => {
+            if let Some((ifindex, neighbor)) = queries::neighbor_from_msg(neigh_msg) {
+                pending.neighbors.push((ifindex, neighbor, false));
+            }
+        }
+        RouteNetlinkMessage::DelNeighbour(neigh_msg) => {
+            if let Some((ifindex, neighbor)) = queries::neighbor_from_msg(neigh_msg) {
+                pending.neighbors.push((ifindex, neighbor, true));
+            }
+        }
         _ => {}
     }
 }
 
-/// Run the event loop: listen for netlink events.
-pub async fn run(nm_conn: Connection, shared: SharedState) -> Result<()> {
-    let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+/// Run the event loop: process netlink events from the subscription opened
+/// by `load_initial_state`. SIGHUP reloads the config and re-syncs.
+pub async fn run(nm_conn: Connection, shared: SharedState, events: EventStream) -> Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let sighup = signal(SignalKind::hangup())?;
 
     tokio::select! {
-        result = watch_netlink(nm_conn, shared) => {
-            match result {
-                Ok(()) => warn!("netlink watcher exited normally"),
-                Err(e) => warn!("netlink watcher error: {}", e),
-            }
+        () = run_event_loop(&nm_conn, &shared, events, sighup) => {
+            warn!("netlink watcher exited normally");
         }
         _ = tokio::signal::ctrl_c() => {
             info!("received SIGINT, shutting down");
@@ -87,267 +100,1067 @@ pub async fn run(nm_conn: Connection, shared: SharedState) -> Result<()> {
     Ok(())
 }
 
-/// Watch for netlink events (address/route/link changes) with debouncing.
-async fn watch_netlink(nm_conn: Connection, shared: SharedState) -> Result<()> {
-    let (mut conn, _handle, mut messages) = rtnetlink::new_connection()?;
-
-    let mgroup_flags = RTMGRP_LINK
-        | RTMGRP_IPV4_IFADDR
-        | RTMGRP_IPV4_ROUTE
-        | RTMGRP_IPV6_IFADDR
-        | RTMGRP_IPV6_ROUTE;
-
-    let addr = netlink_sys::SocketAddr::new(0, mgroup_flags);
-    conn.socket_mut().socket_mut().bind(&addr)?;
-
-    tokio::spawn(conn);
-
-    debug!("netlink watcher started, groups mask: 0x{:x}", mgroup_flags);
+/// Debounce messages from `source` into batches and process each one.
+/// A `reload` signal and the periodic reconciliation are handled between
+/// batches, never concurrently with one. Returns when the source is exhausted.
+async fn run_event_loop(
+    nm_conn: &Connection,
+    shared: &SharedState,
+    mut source: impl MessageSource,
+    mut reload: Signal,
+) {
+    let interval = shared.read().await.config.reconcile_interval;
+    let mut reconcile_ticker = (interval > 0).then(|| {
+        let period = Duration::from_secs(interval);
+        let mut ticker = interval_at(Instant::now() + period, period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker
+    });
 
     loop {
-        let Some((msg, _)) = messages.next().await else {
-            break;
+        let msg = tokio::select! {
+            msg = source.next_message() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = reload.recv() => {
+                reload_and_resync(nm_conn, shared).await;
+                continue;
+            }
+            () = next_tick(&mut reconcile_ticker) => {
+                reconcile(nm_conn, shared).await;
+                continue;
+            }
         };
-
         let mut pending = PendingEvents::default();
 
-        if let NetlinkPayload::InnerMessage(inner) = msg.payload {
-            debug!("netlink message received: {:?}", inner);
-            accumulate(&inner, &mut pending);
-        }
+        debug!("netlink message received: {:?}", msg);
+        accumulate(&msg, &mut pending);
 
         let deadline = Instant::now() + DEBOUNCE_DURATION;
         loop {
             tokio::select! {
                 biased;
-                Some((msg, _)) = messages.next() => {
-                    if let NetlinkPayload::InnerMessage(inner) = msg.payload {
-                        debug!("netlink message received: {:?}", inner);
-                        accumulate(&inner, &mut pending);
-                    }
+                Some(msg) = source.next_message() => {
+                    debug!("netlink message received: {:?}", msg);
+                    accumulate(&msg, &mut pending);
                 }
                 () = sleep_until(deadline) => break,
             }
         }
 
         if !pending.is_empty() {
-            process_batch(&nm_conn, &shared, pending).await;
+            process_batch(nm_conn, shared, pending).await;
         }
     }
+}
 
-    Ok(())
+/// Wait for the ticker's next tick; never completes without one.
+async fn next_tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Re-read the config file and reconcile every device against a fresh kernel
+/// dump, as one synthetic batch. Interfaces the new config ignores are
+/// unregistered, newly allowed ones registered, and devices whose exported
+/// type or managed flag changed are re-created.
+async fn reload_and_resync(nm_conn: &Connection, shared: &SharedState) {
+    info!("received SIGHUP, reloading configuration");
+    let config = match config::load() {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("config reload failed, keeping the current one: {e}");
+            return;
+        }
+    };
+
+    let (handle, read_only_changed, read_only) = {
+        let mut state = shared.write().await;
+        let read_only_changed = state.config.read_only != config.read_only;
+        let read_only = config.read_only;
+        state.config = config;
+        (state.handle().clone(), read_only_changed, read_only)
+    };
+    if read_only_changed {
+        nm::signals::notify_check_permissions(nm_conn).await;
+        nm::signals::notify_settings_can_modify_changed(nm_conn, !read_only).await;
+    }
+    let links = match queries::dump_links(&handle).await {
+        Ok(links) => links,
+        Err(e) => {
+            warn!("failed to dump links for resync: {e}");
+            return;
+        }
+    };
+
+    let pending = shared.with_state(|s| resync_batch(s, links)).await;
+    process_batch(nm_conn, shared, pending).await;
+}
+
+/// Re-read links, addresses and gateways and apply whatever drifted from
+/// state, for events the kernel dropped (e.g. on a socket overrun). Unlike a
+/// reload, devices whose addresses and gateway still match aren't notified.
+async fn reconcile(nm_conn: &Connection, shared: &SharedState) {
+    let handle = shared.read().await.handle().clone();
+    let links = match queries::dump_links(&handle).await {
+        Ok(links) => links,
+        Err(e) => {
+            warn!("failed to dump links for reconciliation: {e}");
+            return;
+        }
+    };
+    let (gw4, gw6) = match queries::query_default_gateways(&handle).await {
+        Ok(gw) => gw,
+        Err(e) => {
+            warn!("failed to query gateways for reconciliation: {e}");
+            return;
+        }
+    };
+
+    let mut pending = shared.with_state(|s| resync_batch(s, links)).await;
+    for ifindex in std::mem::take(&mut pending.address_changed) {
+        let (ipv4, ipv6) = queries::query_addresses(&handle, ifindex).await;
+        let drifted = shared
+            .with_device(ifindex, |d| d.ipv4_addrs != ipv4 || d.ipv6_addrs != ipv6)
+            .await
+            .unwrap_or(false);
+        if drifted {
+            info!(ifindex, "addresses drifted from the kernel, reconciling");
+            pending.address_changed.insert(ifindex);
+        }
+    }
+    pending.routes_changed = shared
+        .with_state(|s| {
+            s.devices.values().any(|d| {
+                d.gateway4 != gw4.get(&d.ifindex).copied()
+                    || d.gateway6 != gw6.get(&d.ifindex).copied()
+            })
+        })
+        .await;
+    if pending.routes_changed {
+        info!("default gateways drifted from the kernel, reconciling");
+    }
+
+    // Link updates for known devices only emit what actually changed.
+    process_batch(nm_conn, shared, pending).await;
+}
+
+/// A batch reconciling every device against a kernel link dump: devices
+/// missing from it are removed, new links added, and devices whose exported
+/// type or managed flag changed are re-created. Every other device gets its
+/// link re-applied and its addresses re-read. Runs under the state lock, so
+/// links are compared by what their messages carry, without sysfs reads.
+fn resync_batch(state: &AppState, links: Vec<LinkMessage>) -> PendingEvents {
+    let mut pending = PendingEvents {
+        routes_changed: true,
+        ..PendingEvents::default()
+    };
+    let dumped: HashSet<i32> = links.iter().map(|m| m.header.index as i32).collect();
+    for &ifindex in state.devices.keys() {
+        if !dumped.contains(&ifindex) {
+            pending.del_links.insert(ifindex, LinkMessage::default());
+        }
+    }
+    for msg in links {
+        let ifindex = msg.header.index as i32;
+        if let Some(dev) = state.devices.get(&ifindex) {
+            let fresh = super::parse_link_msg(&msg, &state.config);
+            if fresh.is_none_or(|f| {
+                f.device_type != dev.device_type
+                    || f.ignored != dev.ignored
+                    || (!f.ignored && !state.is_unmanaged(&f.name)) != dev.managed
+                    || f.vpn != dev.vpn
+            }) {
+                pending.del_links.insert(ifindex, msg.clone());
+            } else {
+                pending.address_changed.insert(ifindex);
+            }
+        }
+        pending.new_links.insert(ifindex, msg);
+    }
+    pending
+}
+
+/// D-Bus work computed under the state lock, performed once the lock is released.
+#[derive(Default)]
+struct BatchEffects {
+    /// Devices removed from state: (ifindex, device_type, activation_id,
+    /// ignored).
+    removed: Vec<(i32, u32, i32, bool)>,
+    /// Devices inserted into state that need D-Bus objects.
+    added: Vec<i32>,
+    /// Device state transitions: (ifindex, new_state, old_state).
+    state_changes: Vec<(i32, u32, u32)>,
+    /// Interface flag changes: (ifindex, nm_interface_flags).
+    iface_flags_changes: Vec<(i32, u32)>,
+    /// MTU changes: (ifindex, mtu).
+    mtu_changes: Vec<(i32, u32)>,
+    /// Interface alias changes: (ifindex, alias, activation_id, new
+    /// connection id if the alias changed it).
+    alias_changes: Vec<(i32, Option<String>, i32, Option<String>)>,
+    /// Speed or duplex changes: (ifindex, speed, duplex).
+    link_mode_changes: Vec<(i32, u32, String)>,
+    /// Controllers whose set of ports changed.
+    ports_changes: HashSet<i32>,
+    /// Activation ids of ports that joined or left a controller.
+    controller_changes: Vec<i32>,
+    /// Bond failovers: (bond ifindex, new active slave).
+    bond_active_slave_changes: Vec<(i32, Option<i32>)>,
+    /// Per-family connectivity changes, e.g. from a gateway coming or going:
+    /// (ifindex, ip4_connectivity, ip6_connectivity).
+    connectivity_changes: Vec<(i32, u32, u32)>,
+    /// Devices whose IP configuration should be re-read by clients.
+    ip_config_notify: HashSet<i32>,
+    /// Devices whose IPv4 addresses changed.
+    ip4_config_notify: HashSet<i32>,
+    /// Devices whose IP6Config changed (addresses, or the RA-learned gateway).
+    ip6_config_notify: HashSet<i32>,
+    /// Emit the global state notification even if the state value is unchanged
+    /// (link/route changes can move ActiveConnections/PrimaryConnection).
+    force_global_notify: bool,
+    old_global: u32,
+    new_global: u32,
 }
 
 /// Process a batch of accumulated netlink events.
 ///
-/// Order: DelLink → NewLink → Addresses → Routes, then emit D-Bus signals.
-async fn process_batch(nm_conn: &Connection, shared: &SharedState, pending: PendingEvents) {
+/// Runs in three phases:
+/// 1. Kernel queries (addresses, gateways) with no lock held.
+/// 2. A single write lock applies DelLink → NewLink → Addresses → Routes and
+///    records the resulting D-Bus work in a [`BatchEffects`]; see
+///    [`apply_batch`].
+/// 3. The lock is dropped, then objects are (un)registered and signals emitted.
+///
+/// Invariant: the state lock is never held across a D-Bus await, so property
+/// reads from clients are never blocked behind signal emission.
+async fn process_batch(nm_conn: &Connection, shared: &SharedState, mut pending: PendingEvents) {
     debug!(
         del_links = pending.del_links.len(),
         new_links = pending.new_links.len(),
         address_changed = pending.address_changed.len(),
+        neighbors = pending.neighbors.len(),
         routes_changed = pending.routes_changed,
         "processing debounced batch"
     );
 
-    for link_msg in pending.del_links.values() {
-        handle_del_link(nm_conn, shared, link_msg).await;
+    // Phase 1: query the kernel without holding the lock.
+    let (handle, known, config, reused, link_mode_reads) = {
+        let state = shared.read().await;
+        let known: HashSet<i32> = state.devices.keys().copied().collect();
+        // A NewLink for a known ifindex that names a different link means
+        // its DelLink was missed (e.g. lost to a socket overrun) and the
+        // kernel has reused the index. Only the message is parsed here; sysfs
+        // is read for new devices once the lock is released.
+        let reused: Vec<i32> = pending
+            .new_links
+            .iter()
+            .filter(|(ifindex, _)| !pending.del_links.contains_key(ifindex))
+            .filter_map(|(&ifindex, msg)| {
+                let dev = state.devices.get(&ifindex)?;
+                let fresh = super::parse_link_msg(msg, &state.config)?;
+                (!dev.is_same_link(&fresh)).then_some(ifindex)
+            })
+            .collect();
+        // Autonegotiation settles with the carrier, and ethtool only notifies
+        // for link modes set explicitly: re-read speed and duplex of wired
+        // links whose interface flags change.
+        let link_mode_reads: Vec<(i32, String, bool)> = pending
+            .new_links
+            .iter()
+            .filter_map(|(&ifindex, msg)| {
+                let dev = state
+                    .devices
+                    .get(&ifindex)
+                    .filter(|d| d.has_wired_interface())?;
+                let flags = msg.header.flags.bits();
+                let carrier = link_carrier(msg).or(dev.kernel_carrier);
+                let old_iface_flags =
+                    mapping::netlink_flags_to_interface_flags(dev.link_flags, dev.kernel_carrier);
+                let new_iface_flags = mapping::netlink_flags_to_interface_flags(flags, carrier);
+                let name = link_name(msg).unwrap_or(&dev.name).to_string();
+                let carrier = carrier.unwrap_or_else(|| mapping::flags_carrier(flags));
+                (old_iface_flags != new_iface_flags).then_some((ifindex, name, carrier))
+            })
+            .collect();
+        (
+            state.handle().clone(),
+            known,
+            state.config.clone(),
+            reused,
+            link_mode_reads,
+        )
+    };
+    let link_modes: HashMap<i32, (u32, String)> = link_mode_reads
+        .into_iter()
+        .map(|(ifindex, name, carrier)| (ifindex, DeviceInfo::read_link_mode(&name, carrier)))
+        .collect();
+    for ifindex in reused {
+        info!(ifindex, "ifindex reused by a new link");
+        pending.del_links.insert(ifindex, LinkMessage::default());
     }
 
-    for link_msg in pending.new_links.values() {
-        let _ = handle_new_link(nm_conn, shared, link_msg).await;
-    }
+    // A NewLink is a new device if we don't know it, or it was just deleted
+    // in this same batch.
+    let new_devices: Vec<DeviceInfo> = pending
+        .new_links
+        .values()
+        .filter(|msg| {
+            let ifindex = msg.header.index as i32;
+            !known.contains(&ifindex) || pending.del_links.contains_key(&ifindex)
+        })
+        .filter_map(|msg| super::device_from_link_msg(msg, &config))
+        .collect();
+
+    // Address churn on ignored interfaces (containers, veth pairs) and on
+    // links going away in this batch needs no query.
+    let mut addr_queries: HashSet<i32> = pending
+        .address_changed
+        .iter()
+        .copied()
+        .filter(|ifindex| known.contains(ifindex) && !pending.del_links.contains_key(ifindex))
+        .collect();
+    addr_queries.extend(new_devices.iter().map(|d| d.ifindex));
 
-    let mut ip_config_notify: HashSet<i32> = HashSet::new();
+    let mut addresses = HashMap::new();
+    for &ifindex in &addr_queries {
+        addresses.insert(ifindex, queries::query_addresses(&handle, ifindex).await);
+    }
 
-    if !pending.address_changed.is_empty() {
-        let handle = shared.read().await.handle().clone();
-        for &ifindex in &pending.address_changed {
-            queries::reload_addresses_for(&handle, ifindex, shared).await;
+    let gateways = if pending.routes_changed || !new_devices.is_empty() {
+        match queries::query_default_gateways(&handle).await {
+            Ok(gw) => Some(gw),
+            Err(e) => {
+                warn!("failed to reload gateways: {e}");
+                None
+            }
         }
-        queries::reload_nameservers(shared).await;
+    } else {
+        None
+    };
 
-        let (device_changes, old_global, new_global) = {
-            let mut state = shared.write().await;
-            let old_global = state.global_state;
-            let changes: Vec<_> = pending
-                .address_changed
-                .iter()
-                .filter_map(|&ifindex| {
-                    state
-                        .devices
-                        .get_mut(&ifindex)
-                        .and_then(|dev| dev.update_state_on_ip_change())
-                        .map(|(new_state, old_state)| (ifindex, new_state, old_state))
-                })
-                .collect();
-            state.recompute_global_state();
-            (changes, old_global, state.global_state)
-        };
+    // Phase 2: apply everything under a single write lock.
+    let reads = KernelReads {
+        new_devices,
+        addresses,
+        gateways,
+        link_modes,
+    };
+    let effects = apply_batch(&mut *shared.write().await, pending, reads);
 
-        ip_config_notify.extend(&pending.address_changed);
+    // Phase 3: D-Bus object registration and signal emission, lock released.
+    let devices_changed = !effects.removed.is_empty() || !effects.added.is_empty();
+    for (ifindex, device_type, activation_id, ignored) in effects.removed {
+        if let Err(e) =
+            nm::unregister_device(nm_conn, ifindex, device_type, activation_id, ignored).await
+        {
+            warn!(ifindex, "failed to unregister device: {e}");
+        }
+        nm::signals::notify_device_removed(nm_conn, ifindex).await;
+    }
 
-        for (ifindex, new_state, old_state) in device_changes {
-            nm::signals::notify_device_state_changed(
-                nm_conn, shared, ifindex, new_state, old_state,
-            )
-            .await;
+    for ifindex in effects.added {
+        if let Err(e) = nm::register_device(nm_conn, ifindex, shared.clone()).await {
+            warn!(ifindex, "failed to register device: {e}");
+            continue;
         }
+        nm::signals::notify_device_added(nm_conn, ifindex).await;
+    }
 
-        if old_global != new_global {
-            nm::signals::notify_global_state_changed(nm_conn, shared, new_global).await;
+    if devices_changed {
+        nm::signals::notify_devices_changed(nm_conn).await;
+    }
+
+    for (ifindex, iface_flags) in effects.iface_flags_changes {
+        nm::signals::notify_device_interface_flags_changed(nm_conn, ifindex, iface_flags).await;
+    }
+
+    for (ifindex, mtu) in effects.mtu_changes {
+        nm::signals::notify_device_mtu_changed(nm_conn, ifindex, mtu).await;
+    }
+
+    for (ifindex, alias, activation_id, id) in effects.alias_changes {
+        nm::signals::notify_device_alias_changed(nm_conn, ifindex, alias.as_deref()).await;
+        if let Some(id) = id {
+            nm::signals::notify_active_connection_id_changed(nm_conn, activation_id, &id).await;
         }
     }
 
-    if pending.routes_changed {
-        let handle = shared.read().await.handle().clone();
-        queries::reload_gateways(&handle, shared).await;
-        let global_state = {
-            let mut state = shared.write().await;
-            state.recompute_global_state();
-            state.global_state
-        };
-        nm::signals::notify_global_state_changed(nm_conn, shared, global_state).await;
+    for (ifindex, speed, duplex) in effects.link_mode_changes {
+        nm::signals::notify_device_speed_changed(nm_conn, ifindex, speed, &duplex).await;
+    }
 
-        let ifindexes: Vec<i32> = {
-            let st = shared.read().await;
-            st.devices.keys().copied().collect()
-        };
-        ip_config_notify.extend(ifindexes);
+    for ifindex in effects.ports_changes {
+        let is_bond = shared
+            .with_device(ifindex, |d| d.device_type == mapping::nm_device_type::BOND)
+            .await
+            .unwrap_or(false);
+        nm::signals::notify_device_ports_changed(nm_conn, ifindex, is_bond).await;
+    }
+
+    for activation_id in effects.controller_changes {
+        nm::signals::notify_active_connection_controller_changed(nm_conn, activation_id).await;
+    }
+
+    for (ifindex, active_slave) in effects.bond_active_slave_changes {
+        nm::signals::notify_bond_active_slave_changed(nm_conn, ifindex, active_slave).await;
     }
 
-    for ifindex in ip_config_notify {
+    for (ifindex, ip4, ip6) in effects.connectivity_changes {
+        nm::signals::notify_device_connectivity_changed(nm_conn, ifindex, ip4, ip6).await;
+    }
+
+    for (ifindex, new_state, old_state) in effects.state_changes {
+        emit_state_change(nm_conn, shared, ifindex, new_state, old_state).await;
+    }
+
+    if effects.force_global_notify || effects.old_global != effects.new_global {
+        debug!(
+            "global state: {} -> {}",
+            effects.old_global, effects.new_global
+        );
+        nm::signals::notify_global_state_changed(nm_conn, shared).await;
+    }
+
+    for ifindex in effects.ip_config_notify {
         nm::signals::notify_device_ip_config_changed(nm_conn, ifindex).await;
     }
+
+    for ifindex in effects.ip4_config_notify {
+        nm::signals::notify_ip4_config_changed(nm_conn, ifindex).await;
+    }
+
+    for ifindex in effects.ip6_config_notify {
+        nm::signals::notify_ip6_config_changed(nm_conn, ifindex).await;
+    }
 }
 
-/// Handle NewLink: detect new devices (hotplug) or update existing device state.
-///
-/// Returns `Err(())` if the caller should `continue` (skip further processing),
-/// i.e. when the interface is ignored or device registration fails.
-async fn handle_new_link(
-    nm_conn: &Connection,
-    shared: &SharedState,
-    link_msg: &LinkMessage,
-) -> std::result::Result<(), ()> {
-    let ifindex = link_msg.header.index as i32;
-    let flags = link_msg.header.flags.bits();
+/// What phase 1 of [`process_batch`] read from the kernel and sysfs.
+#[derive(Default)]
+struct KernelReads {
+    /// Devices for NewLinks of unknown or reused ifindexes.
+    new_devices: Vec<DeviceInfo>,
+    /// Fresh address dumps, by ifindex.
+    addresses: HashMap<i32, queries::Addresses>,
+    /// Default gateways, when routes changed or devices appeared.
+    gateways: Option<queries::DefaultGateways>,
+    /// Speed and duplex of wired links whose interface flags change.
+    link_modes: HashMap<i32, (u32, String)>,
+}
 
-    let is_new_device = {
-        let state = shared.read().await;
-        !state.devices.contains_key(&ifindex)
+/// Phase 2 of [`process_batch`]: apply DelLink → NewLink → Addresses →
+/// Routes to the state and record the D-Bus work that follows.
+fn apply_batch(state: &mut AppState, pending: PendingEvents, reads: KernelReads) -> BatchEffects {
+    let mut effects = BatchEffects {
+        old_global: state.global_state,
+        ..BatchEffects::default()
     };
+    let old_connectivity: HashMap<i32, (u32, u32)> = state
+        .devices
+        .values()
+        .map(|d| (d.ifindex, (d.ip4_connectivity(), d.ip6_connectivity())))
+        .collect();
+
+    for &ifindex in pending.del_links.keys() {
+        if let Some(dev) = state.remove_device(ifindex) {
+            info!(ifindex, iface = %dev.name, "device removed");
+            effects.ports_changes.extend(dev.controller);
+            effects
+                .removed
+                .push((ifindex, dev.device_type, dev.activation_id, dev.ignored));
+        }
+    }
 
-    if is_new_device {
-        let dev = super::device_from_link_msg(link_msg).ok_or(())?;
+    let new_ifindexes: HashSet<i32> = reads.new_devices.iter().map(|d| d.ifindex).collect();
+    for mut dev in reads.new_devices {
+        if state.device_cap_reached(&dev) {
+            continue;
+        }
+        let ifindex = dev.ifindex;
         info!(ifindex, iface = %dev.name, "new device detected");
+        if let Some((ipv4, ipv6)) = reads.addresses.get(&ifindex) {
+            dev.ipv4_addrs = ipv4.clone();
+            dev.ipv6_addrs = ipv6.clone();
+        }
+        dev.nm_state = mapping::netlink_flags_to_nm_device(
+            dev.link_flags,
+            dev.kernel_carrier,
+            dev.operstate,
+            dev.managed,
+            dev.has_ip_config(),
+        );
+        state.insert_device(dev);
+        effects.added.push(ifindex);
+    }
 
-        {
-            let mut state = shared.write().await;
-            state.devices.insert(ifindex, dev);
+    for (&ifindex, link_msg) in &pending.new_links {
+        if new_ifindexes.contains(&ifindex) {
+            continue;
         }
+        apply_link_update(state, link_msg, &reads.link_modes, &mut effects);
+    }
 
-        let handle = shared.read().await.handle().clone();
-        queries::reload_addresses_for(&handle, ifindex, shared).await;
-        queries::reload_gateways(&handle, shared).await;
-        queries::reload_nameservers(shared).await;
+    for &ifindex in &pending.address_changed {
+        let Some(dev) = state.devices.get_mut(&ifindex) else {
+            continue;
+        };
+        let Some((ipv4, ipv6)) = reads.addresses.get(&ifindex) else {
+            continue;
+        };
+        let change = dev.replace_addresses(ipv4, ipv6);
+        debug!(
+            iface = %dev.name,
+            ipv4_changed = change.ipv4_changed,
+            ipv6_changed = change.ipv6_changed,
+            "reloaded addresses"
+        );
+        if let Some((new_state, old_state)) = change.state_change {
+            effects.state_changes.push((ifindex, new_state, old_state));
+        }
+        if change.ipv4_changed {
+            effects.ip_config_notify.insert(ifindex);
+            effects.ip4_config_notify.insert(ifindex);
+        }
+        if change.ipv6_changed {
+            effects.ip_config_notify.insert(ifindex);
+            effects.ip6_config_notify.insert(ifindex);
+        }
+    }
 
-        {
-            let mut state = shared.write().await;
-            if let Some(dev) = state.devices.get_mut(&ifindex) {
-                let has_ipv4 = !dev.ipv4_addrs.is_empty();
-                let has_ipv6 = !dev.ipv6_addrs.is_empty();
-                dev.nm_state = mapping::netlink_flags_to_nm_device(flags, has_ipv4, has_ipv6);
+    for (ifindex, neighbor, deleted) in pending.neighbors {
+        let Some(dev) = state.devices.get_mut(&ifindex) else {
+            continue;
+        };
+        if deleted {
+            dev.remove_neighbor(neighbor.address);
+        } else {
+            dev.update_neighbor(neighbor);
+        }
+    }
+
+    if let Some((gw4, gw6)) = reads.gateways {
+        for dev in state.devices.values_mut() {
+            let had_ip_config = dev.has_ip_config();
+            dev.gateway4 = gw4.get(&dev.ifindex).copied();
+            let gateway6 = gw6.get(&dev.ifindex).copied();
+            if dev.gateway6 != gateway6 {
+                debug!(iface = %dev.name, ?gateway6, "IPv6 default gateway changed");
+                effects.ip6_config_notify.insert(dev.ifindex);
+            }
+            dev.gateway6 = gateway6;
+            dev.clear_stale_connectivity_checks();
+            if dev.has_ip_config() != had_ip_config
+                && let Some((new_state, old_state)) = dev.update_state_on_ip_change()
+            {
+                effects
+                    .state_changes
+                    .push((dev.ifindex, new_state, old_state));
             }
         }
+    }
 
-        if let Err(e) = nm::register_device(nm_conn, ifindex, shared.clone()).await {
-            warn!(ifindex, "failed to register device: {e}");
-            return Err(());
+    if pending.routes_changed {
+        effects.force_global_notify = true;
+        effects
+            .ip_config_notify
+            .extend(state.devices.keys().copied());
+    }
+
+    for &ifindex in &new_ifindexes {
+        if let Some(controller) = state.devices.get(&ifindex).and_then(|d| d.controller) {
+            effects.ports_changes.insert(controller);
         }
+    }
+    let controller_changes = state.update_controller_states();
+    effects.state_changes.extend(controller_changes);
 
-        nm::signals::notify_device_added(nm_conn, ifindex).await;
-    } else {
-        let mac = link_msg.attributes.iter().find_map(|attr| match attr {
-            LinkAttribute::Address(bytes) => Some(queries::format_mac(bytes)),
-            _ => None,
-        });
+    for dev in state.devices.values() {
+        let connectivity = (dev.ip4_connectivity(), dev.ip6_connectivity());
+        if old_connectivity
+            .get(&dev.ifindex)
+            .is_some_and(|&old| old != connectivity)
+        {
+            let (ip4, ip6) = connectivity;
+            effects.connectivity_changes.push((dev.ifindex, ip4, ip6));
+        }
+    }
 
-        let state_change = {
-            let mut state = shared.write().await;
-            if let Some(dev) = state.devices.get_mut(&ifindex) {
-                if let Some(m) = mac {
-                    dev.hw_address = m;
-                }
+    for &(ifindex, new_state, _) in &effects.state_changes {
+        if new_state == mapping::nm_device_state::ACTIVATED {
+            state.notify_activated(ifindex);
+        }
+    }
 
-                if let Some((new_state, old_state)) = dev.update_state_on_link_change(flags) {
-                    let iface_name = dev.name.clone();
-                    info!(
-                        iface = %iface_name,
-                        old_state,
-                        new_state,
-                        flags,
-                        "link state changed"
-                    );
-
-                    let old_global = state.global_state;
-                    state.recompute_global_state();
-                    Some((new_state, old_state, state.global_state, old_global))
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        };
+    state.recompute_global_state();
+    effects.new_global = state.global_state;
+    effects
+}
 
-        if let Some((new_state, old_state, new_global, old_global)) = state_change {
-            nm::signals::notify_device_state_changed(
-                nm_conn, shared, ifindex, new_state, old_state,
-            )
-            .await;
+/// Announce a device state change unless the link is flapping: a return to
+/// the state announced before the last one, within `state_hysteresis_ms` of
+/// it, is held back, and whatever state the device settles in is announced
+/// once the window has passed. Each device has at most one such
+/// announcement pending.
+async fn emit_state_change(
+    nm_conn: &Connection,
+    shared: &SharedState,
+    ifindex: i32,
+    new_state: u32,
+    old_state: u32,
+) {
+    let (window, last) = {
+        let state = shared.read().await;
+        (
+            Duration::from_millis(state.config.state_hysteresis_ms),
+            state.emitted_states.get(&ifindex).copied(),
+        )
+    };
 
-            if old_global != new_global {
-                debug!("global state changed: {} -> {}", old_global, new_global);
+    // Report transitions relative to what clients last saw, which differs
+    // from `old_state` after a held-back change.
+    let old_state = match last {
+        Some(last) if last.state == new_state => return,
+        Some(last) if last.previous == new_state && last.at.elapsed() < window => {
+            debug!(
+                ifindex,
+                new_state, "device flapping, holding back state change"
+            );
+            let delay = window.saturating_sub(last.at.elapsed());
+            let task = tokio::spawn(announce_settled_state(
+                nm_conn.clone(),
+                shared.clone(),
+                ifindex,
+                delay,
+            ));
+            let mut state = shared.write().await;
+            if !state.devices.contains_key(&ifindex) {
+                task.abort();
+            } else if let Some(previous) = state.settle_tasks.insert(ifindex, task.abort_handle()) {
+                previous.abort();
             }
-            nm::signals::notify_global_state_changed(nm_conn, shared, new_global).await;
+            return;
         }
+        Some(last) => last.state,
+        None => old_state,
+    };
+    nm::signals::notify_device_state_changed(nm_conn, shared, ifindex, new_state, old_state).await;
+}
+
+/// After `delay`, announce the device's state if it differs from the one
+/// clients last saw, then drop this task from `settle_tasks`.
+async fn announce_settled_state(
+    nm_conn: Connection,
+    shared: SharedState,
+    ifindex: i32,
+    delay: Duration,
+) {
+    tokio::time::sleep(delay).await;
+    let change = shared
+        .with_state(|s| {
+            let dev = s.devices.get(&ifindex)?;
+            let last = s.emitted_states.get(&ifindex)?;
+            (dev.nm_state != last.state).then_some((dev.nm_state, last.state))
+        })
+        .await;
+    if let Some((new_state, old_state)) = change {
+        nm::signals::notify_device_state_changed(&nm_conn, &shared, ifindex, new_state, old_state)
+            .await;
+    }
+    // A later flap may have replaced this task with a new one already.
+    let own = tokio::task::id();
+    let mut state = shared.write().await;
+    if state
+        .settle_tasks
+        .get(&ifindex)
+        .is_some_and(|t| t.id() == own)
+    {
+        state.settle_tasks.remove(&ifindex);
     }
+}
 
-    Ok(())
+/// IFLA_IFNAME of a link message.
+fn link_name(link_msg: &LinkMessage) -> Option<&str> {
+    link_msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::IfName(n) => Some(n.as_str()),
+        _ => None,
+    })
+}
+
+/// IFLA_CARRIER of a link message.
+fn link_carrier(link_msg: &LinkMessage) -> Option<bool> {
+    link_msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::Carrier(c) => Some(*c != 0),
+        _ => None,
+    })
 }
 
-/// Handle DelLink: unregister removed devices and update global state.
-async fn handle_del_link(nm_conn: &Connection, shared: &SharedState, link_msg: &LinkMessage) {
+/// Apply a NewLink for an already-known device: rename, MAC, flags and state.
+/// `link_modes` holds the speed and duplex read in phase 1 for links whose
+/// interface flags change.
+fn apply_link_update(
+    state: &mut AppState,
+    link_msg: &LinkMessage,
+    link_modes: &HashMap<i32, (u32, String)>,
+    effects: &mut BatchEffects,
+) {
     let ifindex = link_msg.header.index as i32;
+    let flags = link_msg.header.flags.bits();
 
-    let device_type = {
-        let state = shared.read().await;
-        state.devices.get(&ifindex).map(|d| d.device_type)
-    };
+    let mac = link_msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::Address(bytes) => Some(queries::format_mac(bytes)),
+        _ => None,
+    });
+    let name = link_name(link_msg);
+    let mtu = link_msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::Mtu(m) => Some(*m),
+        _ => None,
+    });
+    let operstate = link_msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::OperState(s) => Some(u8::from(*s)),
+        _ => None,
+    });
+    let tx_queue_len = link_msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::TxQueueLen(q) => Some(*q),
+        _ => None,
+    });
+    let carrier = link_carrier(link_msg);
+    // Absent once the link is released from its bond or bridge.
+    let controller = link_msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::Controller(idx) => Some(*idx as i32),
+        _ => None,
+    });
+    // The kernel leaves IFLA_IFALIAS out of the message once it is cleared.
+    let alias = link_msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::IfAlias(a) => Some(a.clone()).filter(|a| !a.is_empty()),
+        _ => None,
+    });
+
+    if let Some(new_name) = name
+        && let Some(old_name) = state.rename_device(ifindex, new_name)
+    {
+        info!(ifindex, old = %old_name, new = %new_name, "interface renamed");
+    }
+    if let Some((activation_id, id)) = state.set_alias(ifindex, alias.clone()) {
+        info!(ifindex, ?alias, "interface alias changed");
+        effects
+            .alias_changes
+            .push((ifindex, alias, activation_id, id));
+    }
+    let managed = name.map(|n| !state.is_unmanaged(n));
 
-    let Some(device_type) = device_type else {
+    let Some(dev) = state.devices.get_mut(&ifindex) else {
         return;
     };
 
-    info!(ifindex, "device removed");
+    if let Some(managed) = managed {
+        dev.managed = managed && !dev.ignored;
+    }
+
+    if let Some(m) = mac {
+        dev.hw_address = m;
+    }
+
+    if let Some(q) = tx_queue_len {
+        dev.tx_queue_len = q;
+    }
+
+    if controller != dev.controller {
+        info!(iface = %dev.name, ?controller, "controller changed");
+        effects.ports_changes.extend(dev.controller);
+        effects.ports_changes.extend(controller);
+        effects.controller_changes.push(dev.activation_id);
+        dev.controller = controller;
+    }
+
+    if let Some(active_slave) = super::bond_active_slave(link_msg)
+        && active_slave != dev.bond_active_slave
+    {
+        info!(iface = %dev.name, ?active_slave, "bond active slave changed");
+        dev.bond_active_slave = active_slave;
+        effects
+            .bond_active_slave_changes
+            .push((ifindex, active_slave));
+    }
+
+    if let Some(mtu) = mtu
+        && mtu != dev.mtu
+    {
+        debug!(iface = %dev.name, old = dev.mtu, new = mtu, "MTU changed");
+        dev.mtu = mtu;
+        effects.mtu_changes.push((ifindex, mtu));
+    }
+
+    let carrier = carrier.or(dev.kernel_carrier);
+    let old_iface_flags =
+        mapping::netlink_flags_to_interface_flags(dev.link_flags, dev.kernel_carrier);
+    let new_iface_flags = mapping::netlink_flags_to_interface_flags(flags, carrier);
+    if old_iface_flags != new_iface_flags {
+        effects.iface_flags_changes.push((ifindex, new_iface_flags));
+    }
+
+    let operstate = operstate.unwrap_or(dev.operstate);
+    if let Some((new_state, old_state)) = dev.update_state_on_link_change(flags, carrier, operstate)
+    {
+        info!(
+            iface = %dev.name,
+            old_state,
+            new_state,
+            flags,
+            "link state changed"
+        );
+        effects.state_changes.push((ifindex, new_state, old_state));
+        effects.force_global_notify = true;
+    }
+
+    if let Some((speed, duplex)) = link_modes.get(&ifindex)
+        && let Some((speed, duplex)) = dev.set_link_mode(*speed, duplex)
+    {
+        info!(iface = %dev.name, speed, %duplex, "link speed changed");
+        effects.link_mode_changes.push((ifindex, speed, duplex));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use netlink_packet_route::address::AddressMessage;
+    use netlink_packet_route::route::RouteMessage;
+
+    use super::*;
+
+    fn link(ifindex: u32, name: &str) -> LinkMessage {
+        let mut msg = LinkMessage::default();
+        msg.header.index = ifindex;
+        msg.attributes.push(LinkAttribute::IfName(name.to_string()));
+        msg
+    }
 
-    if let Err(e) = nm::unregister_device(nm_conn, ifindex, device_type).await {
-        warn!(ifindex, "failed to unregister device: {e}");
+    fn address(ifindex: u32) -> AddressMessage {
+        let mut msg = AddressMessage::default();
+        msg.header.index = ifindex;
+        msg
     }
 
-    let old_global_state = {
+    /// Drain a scripted source into one batch, as the event loop does within
+    /// a debounce window.
+    async fn replay(mut source: impl MessageSource) -> PendingEvents {
+        let mut pending = PendingEvents::default();
+        while let Some(msg) = source.next_message().await {
+            accumulate(&msg, &mut pending);
+        }
+        pending
+    }
+
+    #[tokio::test]
+    async fn replayed_batch_collects_links_addresses_and_routes() {
+        let source = VecDeque::from([
+            RouteNetlinkMessage::NewLink(link(2, "eth0")),
+            RouteNetlinkMessage::NewAddress(address(2)),
+            RouteNetlinkMessage::NewLink(link(3, "eth1")),
+            RouteNetlinkMessage::DelAddress(address(3)),
+            RouteNetlinkMessage::NewRoute(RouteMessage::default()),
+        ]);
+        let pending = replay(source).await;
+        assert!(!pending.is_empty());
+        assert_eq!(pending.address_changed, HashSet::from([2, 3]));
+        assert!(pending.routes_changed);
+        assert_eq!(pending.new_links.len(), 2);
+        assert!(pending.del_links.is_empty());
+    }
+
+    #[tokio::test]
+    async fn empty_replay_yields_empty_batch() {
+        assert!(replay(VecDeque::new()).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn replayed_batch_is_applied_to_state() {
+        use mapping::netlink_flags::{IFF_LOWER_UP, IFF_RUNNING, IFF_UP};
+        use mapping::{nm_device_state, nm_state};
+        use netlink_packet_route::link::LinkFlags;
+
+        let shared = crate::test_support::fake_state();
         let mut state = shared.write().await;
-        let old_global = state.global_state;
-        state.devices.remove(&ifindex);
-        state.recompute_global_state();
-        old_global
-    };
+        let eth = crate::test_support::ETH_IFINDEX;
+        let wg = crate::test_support::WG_IFINDEX;
 
-    nm::signals::notify_device_removed(nm_conn, ifindex).await;
+        let mut eth_down = link(eth as u32, "nmtest0");
+        eth_down.attributes.push(LinkAttribute::Carrier(0));
+        let mut usb = link(9, "nmtest9");
+        usb.header.flags = LinkFlags::from_bits_retain(IFF_UP | IFF_RUNNING | IFF_LOWER_UP);
+        let pending = replay(VecDeque::from([
+            RouteNetlinkMessage::DelLink(link(wg as u32, "nmtest1")),
+            RouteNetlinkMessage::NewLink(eth_down),
+            RouteNetlinkMessage::NewLink(usb.clone()),
+            RouteNetlinkMessage::DelRoute(RouteMessage::default()),
+        ]))
+        .await;
+        // What phase 1 would have read: the new link, and no default route
+        // left now that the uplink is down.
+        let reads = KernelReads {
+            new_devices: vec![super::super::device_from_link_msg(&usb, &state.config).unwrap()],
+            gateways: Some(Default::default()),
+            ..KernelReads::default()
+        };
+        let effects = apply_batch(&mut state, pending, reads);
+
+        assert!(!state.devices.contains_key(&wg));
+        assert_eq!(effects.removed.len(), 1);
+        assert_eq!(effects.removed[0].0, wg);
+
+        assert_eq!(effects.added, [9]);
+        assert_eq!(state.devices[&9].name, "nmtest9");
+        assert_eq!(state.devices[&9].nm_state, nm_device_state::IP_CONFIG);
+
+        assert_eq!(state.devices[&eth].nm_state, nm_device_state::DISCONNECTED);
+        assert_eq!(state.devices[&eth].gateway4, None);
+        assert!(effects.state_changes.contains(&(
+            eth,
+            nm_device_state::DISCONNECTED,
+            nm_device_state::ACTIVATED
+        )));
+        assert!(effects.force_global_notify);
+        assert_eq!(effects.old_global, nm_state::CONNECTED_GLOBAL);
+        assert_eq!(effects.new_global, state.global_state);
+        assert_eq!(effects.new_global, nm_state::CONNECTED_LOCAL);
+    }
+
+    /// Feed one NewLink for a known device through `apply_link_update`.
+    fn update_link(state: &mut AppState, msg: &LinkMessage) -> BatchEffects {
+        let mut effects = BatchEffects::default();
+        apply_link_update(state, msg, &HashMap::new(), &mut effects);
+        effects
+    }
+
+    #[test]
+    fn operstate_is_tracked_across_link_updates() {
+        use mapping::netlink_flags::{IFF_LOWER_UP, IFF_RUNNING, IFF_UP};
+        use mapping::{if_oper, nm_device_state};
+        use netlink_packet_route::link::{LinkFlags, State};
+
+        let shared = crate::test_support::fake_state();
+        let mut state = shared.try_write().unwrap();
+        let ifindex = crate::test_support::ETH_IFINDEX;
+        let up = |operstate: Option<State>| {
+            let mut msg = link(ifindex as u32, "nmtest0");
+            msg.header.flags = LinkFlags::from_bits_retain(IFF_UP | IFF_RUNNING | IFF_LOWER_UP);
+            msg.attributes
+                .extend(operstate.map(LinkAttribute::OperState));
+            msg
+        };
+
+        let effects = update_link(&mut state, &up(Some(State::Dormant)));
+        assert_eq!(state.devices[&ifindex].operstate, if_oper::DORMANT);
+        assert_eq!(
+            effects.state_changes,
+            [(ifindex, nm_device_state::CONFIG, nm_device_state::ACTIVATED)]
+        );
+
+        // Without IFLA_OPERSTATE the last reported value stays in force.
+        let effects = update_link(&mut state, &up(None));
+        assert_eq!(state.devices[&ifindex].operstate, if_oper::DORMANT);
+        assert!(effects.state_changes.is_empty());
+
+        let effects = update_link(&mut state, &up(Some(State::Up)));
+        assert_eq!(state.devices[&ifindex].operstate, if_oper::UP);
+        assert_eq!(
+            effects.state_changes,
+            [(ifindex, nm_device_state::ACTIVATED, nm_device_state::CONFIG)]
+        );
+
+        // The operstate wins over flags that still claim carrier.
+        let effects = update_link(&mut state, &up(Some(State::LowerLayerDown)));
+        assert_eq!(
+            effects.state_changes,
+            [(
+                ifindex,
+                nm_device_state::UNAVAILABLE,
+                nm_device_state::ACTIVATED
+            )]
+        );
+    }
 
-    let new_global_state = shared.read().await.global_state;
-    if old_global_state != new_global_state {
-        nm::signals::notify_global_state_changed(nm_conn, shared, new_global_state).await;
+    #[tokio::test]
+    async fn dellink_then_newlink_replaces_the_link() {
+        let pending = replay(VecDeque::from([
+            RouteNetlinkMessage::NewLink(link(7, "veth1")),
+            RouteNetlinkMessage::DelLink(link(7, "veth1")),
+            RouteNetlinkMessage::NewLink(link(7, "eth9")),
+        ]))
+        .await;
+        // Processed as a removal followed by an addition under the same index.
+        assert!(pending.del_links.contains_key(&7));
+        assert_eq!(link_name(&pending.new_links[&7]), Some("eth9"));
+
+        let pending = replay(VecDeque::from([
+            RouteNetlinkMessage::NewLink(link(7, "eth9")),
+            RouteNetlinkMessage::DelLink(link(7, "eth9")),
+        ]))
+        .await;
+        assert!(pending.new_links.is_empty());
+        assert!(pending.del_links.contains_key(&7));
+    }
+
+    #[tokio::test]
+    async fn flapping_link_is_announced_once_settled() {
+        use crate::mapping::nm_device_state::{ACTIVATED, UNAVAILABLE};
+        use crate::state::EmittedState;
+
+        let config = crate::config::Config {
+            state_hysteresis_ms: 100,
+            ..crate::config::Config::default()
+        };
+        let shared = crate::state::new_shared_state(config, Default::default());
+        let (server, _client) = crate::test_support::p2p_connections().await;
+        tokio::time::pause();
+        {
+            let mut state = shared.write().await;
+            let mut dev = DeviceInfo::new(2, "eth0".to_string());
+            dev.nm_state = ACTIVATED;
+            state.insert_device(dev);
+            // The link just went down.
+            state.emitted_states.insert(
+                2,
+                EmittedState {
+                    state: UNAVAILABLE,
+                    previous: ACTIVATED,
+                    at: tokio::time::Instant::now(),
+                },
+            );
+        }
+
+        // Back up within the window, three times over: held back, with one
+        // settle task pending.
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            emit_state_change(&server, &shared, 2, ACTIVATED, UNAVAILABLE).await;
+            let state = shared.read().await;
+            assert_eq!(state.emitted_states[&2].state, UNAVAILABLE);
+            assert_eq!(state.settle_tasks.len(), 1);
+            ids.push(state.settle_tasks[&2].id());
+        }
+        ids.dedup();
+        assert_eq!(ids.len(), 3, "each flap replaces the pending task");
+
+        // The clock is paused, so sleeping only moves it forward: still held
+        // back just before the window ends, announced once it has.
+        tokio::time::sleep(Duration::from_millis(99)).await;
+        assert_eq!(shared.read().await.emitted_states[&2].state, UNAVAILABLE);
+        tokio::time::advance(Duration::from_millis(1)).await;
+        for _ in 0..100 {
+            if shared.read().await.settle_tasks.is_empty() {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        let state = shared.read().await;
+        assert_eq!(state.emitted_states[&2].state, ACTIVATED);
+        assert_eq!(state.emitted_states[&2].previous, UNAVAILABLE);
+        assert!(state.settle_tasks.is_empty(), "finished task left behind");
     }
 }
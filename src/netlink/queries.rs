@@ -1,18 +1,34 @@
+use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::SystemTime;
 
 use futures::TryStreamExt;
-use netlink_packet_route::address::AddressAttribute;
-use netlink_packet_route::route::{RouteAddress, RouteAttribute};
+use netlink_packet_route::AddressFamily;
+use netlink_packet_route::address::{
+    AddressAttribute, AddressFlags, AddressHeaderFlags, AddressMessage,
+};
+use netlink_packet_route::link::{LinkAttribute, LinkMessage};
+use netlink_packet_route::neighbour::{NeighbourAddress, NeighbourAttribute, NeighbourMessage};
+use netlink_packet_route::route::{RouteAddress, RouteAttribute, RouteProtocol};
 use rtnetlink::RouteMessageBuilder;
-use tracing::{debug, warn};
+use tracing::debug;
 
 use rtnetlink::LinkUnspec;
 
 use crate::Result;
-use crate::state::{AddrInfo, SharedState};
+use crate::state::{self, AddrInfo, NeighborInfo, SharedState};
 
-/// Format a MAC address from raw bytes (e.g. `[0xAA, 0xBB, ...]` → `"AA:BB:..."`).
+/// Longest hardware address NM reports: INFINIBAND_ALEN.
+const HWADDR_LEN_MAX: usize = 20;
+
+/// Format a hardware address from raw bytes (e.g. `[0xAA, 0xBB, ...]` →
+/// `"AA:BB:..."`), as NM does for any length up to InfiniBand's 20 bytes
+/// (4 for IPv4 tunnels, 8 for EUI-64, ...). Empty for zero-length addresses
+/// and for longer ones, which NM doesn't report either.
 pub fn format_mac(bytes: &[u8]) -> String {
+    if bytes.len() > HWADDR_LEN_MAX {
+        return String::new();
+    }
     bytes
         .iter()
         .map(|b| format!("{b:02X}"))
@@ -20,11 +36,81 @@ pub fn format_mac(bytes: &[u8]) -> String {
         .join(":")
 }
 
-/// Query IP addresses for a single interface from netlink.
-async fn query_addresses(
+/// IPv4 and IPv6 default gateways, keyed by egress ifindex.
+pub type DefaultGateways = (HashMap<i32, Ipv4Addr>, HashMap<i32, Ipv6Addr>);
+
+/// A link's IPv4 and IPv6 addresses.
+pub type Addresses = (Vec<AddrInfo<Ipv4Addr>>, Vec<AddrInfo<Ipv6Addr>>);
+
+/// Dump every network link from the kernel.
+pub async fn dump_links(handle: &rtnetlink::Handle) -> Result<Vec<LinkMessage>> {
+    let mut links = handle.link().get().execute();
+    let mut msgs = Vec::new();
+    while let Some(msg) = links.try_next().await? {
+        msgs.push(msg);
+    }
+    Ok(msgs)
+}
+
+/// Read a link's (rx, tx) byte counters from IFLA_STATS64; None if the
+/// kernel didn't include them.
+pub async fn link_byte_counters(
     handle: &rtnetlink::Handle,
     ifindex: i32,
-) -> (Vec<AddrInfo<Ipv4Addr>>, Vec<AddrInfo<Ipv6Addr>>) {
+) -> Result<Option<(u64, u64)>> {
+    let mut links = handle.link().get().match_index(ifindex as u32).execute();
+    let Some(msg) = links.try_next().await? else {
+        return Ok(None);
+    };
+    Ok(msg.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::Stats64(stats) => Some((stats.rx_bytes, stats.tx_bytes)),
+        _ => None,
+    }))
+}
+
+/// Extract the ifindex and entry from a neighbour message; None for entries
+/// without an IP destination (bridge FDB and the like).
+pub fn neighbor_from_msg(msg: &NeighbourMessage) -> Option<(i32, NeighborInfo)> {
+    let mut address = None;
+    let mut mac = String::new();
+    for attr in &msg.attributes {
+        match attr {
+            NeighbourAttribute::Destination(NeighbourAddress::Inet(v4)) => {
+                address = Some(IpAddr::V4(*v4));
+            }
+            NeighbourAttribute::Destination(NeighbourAddress::Inet6(v6)) => {
+                address = Some(IpAddr::V6(*v6));
+            }
+            NeighbourAttribute::LinkLocalAddress(bytes) => mac = format_mac(bytes),
+            _ => {}
+        }
+    }
+    let neighbor = NeighborInfo {
+        address: address?,
+        mac,
+        state: msg.header.state.into(),
+    };
+    Some((msg.header.ifindex as i32, neighbor))
+}
+
+/// Load the kernel neighbour table into the shared state.
+pub async fn load_neighbors(handle: &rtnetlink::Handle, shared: &SharedState) -> Result<()> {
+    let mut msgs = handle.neighbours().get().execute();
+    let mut entries = Vec::new();
+    while let Some(msg) = msgs.try_next().await? {
+        entries.extend(neighbor_from_msg(&msg));
+    }
+    let mut state = shared.write().await;
+    for (ifindex, neighbor) in entries {
+        if let Some(dev) = state.devices.get_mut(&ifindex) {
+            dev.update_neighbor(neighbor);
+        }
+    }
+    Ok(())
+}
+
+/// Query IP addresses for a single interface from netlink.
+pub async fn query_addresses(handle: &rtnetlink::Handle, ifindex: i32) -> Addresses {
     let mut ipv4 = Vec::new();
     let mut ipv6 = Vec::new();
     let mut addrs = handle
@@ -34,27 +120,53 @@ async fn query_addresses(
         .execute();
     while let Ok(Some(msg)) = addrs.try_next().await {
         let prefix_len = msg.header.prefix_len;
+        let is_permanent = address_is_permanent(&msg);
+        let scope = u8::from(msg.header.scope);
+        let label = msg.attributes.iter().find_map(|attr| match attr {
+            AddressAttribute::Label(l) => Some(l.clone()),
+            _ => None,
+        });
         for attr in &msg.attributes {
             match attr {
                 AddressAttribute::Address(IpAddr::V4(v4)) => {
                     ipv4.push(AddrInfo {
                         address: *v4,
                         prefix_len,
+                        is_permanent,
+                        scope,
+                        label: label.clone(),
                     });
                 }
                 AddressAttribute::Address(IpAddr::V6(v6)) => {
                     ipv6.push(AddrInfo {
                         address: *v6,
                         prefix_len,
+                        is_permanent,
+                        scope,
+                        label: label.clone(),
                     });
                 }
                 _ => {}
             }
         }
     }
+    state::sort_addresses(&mut ipv4);
+    state::sort_addresses(&mut ipv6);
     (ipv4, ipv6)
 }
 
+/// Whether an address carries IFA_F_PERMANENT. The 32-bit IFA_FLAGS attribute,
+/// when present, supersedes the 8-bit flags in the message header.
+fn address_is_permanent(msg: &AddressMessage) -> bool {
+    msg.attributes
+        .iter()
+        .find_map(|attr| match attr {
+            AddressAttribute::Flags(flags) => Some(flags.contains(AddressFlags::Permanent)),
+            _ => None,
+        })
+        .unwrap_or_else(|| msg.header.flags.contains(AddressHeaderFlags::Permanent))
+}
+
 /// Load IP addresses and default gateways into the shared state.
 pub async fn load_initial_addresses(
     handle: &rtnetlink::Handle,
@@ -82,19 +194,40 @@ pub async fn load_initial_addresses(
 
 /// Load default gateways for both IPv4 and IPv6.
 pub async fn load_default_gateways(handle: &rtnetlink::Handle, shared: &SharedState) -> Result<()> {
+    let (gw4, gw6) = query_default_gateways(handle).await?;
+    let mut state = shared.write().await;
+    for (idx, v4) in gw4 {
+        if let Some(dev) = state.devices.get_mut(&idx) {
+            debug!(iface = %dev.name, gateway = %v4, "loaded IPv4 default gateway");
+            dev.gateway4 = Some(v4);
+        }
+    }
+    for (idx, v6) in gw6 {
+        if let Some(dev) = state.devices.get_mut(&idx) {
+            debug!(iface = %dev.name, gateway = %v6, "loaded IPv6 default gateway");
+            dev.gateway6 = Some(v6);
+        }
+    }
+    Ok(())
+}
+
+/// Query default gateways for both IPv4 and IPv6 without touching shared state.
+/// A link with several default routes gets the one [`default_route_rank`]
+/// prefers.
+pub async fn query_default_gateways(handle: &rtnetlink::Handle) -> Result<DefaultGateways> {
+    let mut gw4 = HashMap::new();
+    let mut gw6 = HashMap::new();
+
     let route_msg = RouteMessageBuilder::<Ipv4Addr>::new().build();
     let mut routes = handle.route().get(route_msg).execute();
     while let Some(msg) = routes.try_next().await? {
-        if let Some((gw, idx)) = parse_default_gateway(&msg, |a| match a {
+        let gateways = parse_default_gateways(&msg, |a| match a {
             RouteAddress::Inet(ip) => Some(IpAddr::V4(*ip)),
             _ => None,
-        }) {
-            let mut state = shared.write().await;
-            if let Some(dev) = state.devices.get_mut(&idx)
-                && let IpAddr::V4(v4) = gw
-            {
-                debug!(iface = %dev.name, gateway = %v4, "loaded IPv4 default gateway");
-                dev.gateway4 = Some(v4);
+        });
+        for (gw, idx) in gateways {
+            if let IpAddr::V4(v4) = gw {
+                keep_preferred(&mut gw4, idx, default_route_rank(&msg), v4);
             }
         }
     }
@@ -102,67 +235,78 @@ pub async fn load_default_gateways(handle: &rtnetlink::Handle, shared: &SharedSt
     let route_msg = RouteMessageBuilder::<Ipv6Addr>::new().build();
     let mut routes = handle.route().get(route_msg).execute();
     while let Some(msg) = routes.try_next().await? {
-        if let Some((gw, idx)) = parse_default_gateway(&msg, |a| match a {
+        let gateways = parse_default_gateways(&msg, |a| match a {
             RouteAddress::Inet6(ip) => Some(IpAddr::V6(*ip)),
             _ => None,
-        }) {
-            let mut state = shared.write().await;
-            if let Some(dev) = state.devices.get_mut(&idx)
-                && let IpAddr::V6(v6) = gw
-            {
-                debug!(iface = %dev.name, gateway = %v6, "loaded IPv6 default gateway");
-                dev.gateway6 = Some(v6);
+        });
+        for (gw, idx) in gateways {
+            if let IpAddr::V6(v6) = gw {
+                keep_preferred(&mut gw6, idx, default_route_rank(&msg), v6);
             }
         }
     }
 
-    Ok(())
+    Ok((strip_ranks(gw4), strip_ranks(gw6)))
+}
+
+/// Sort key of a default route among several on one link, lowest first:
+/// router-advertised (RTPROT_RA) routes before any others, then by metric.
+type RouteRank = (bool, u32);
+
+fn default_route_rank(msg: &netlink_packet_route::route::RouteMessage) -> RouteRank {
+    let metric = msg
+        .attributes
+        .iter()
+        .find_map(|attr| match attr {
+            RouteAttribute::Priority(p) => Some(*p),
+            _ => None,
+        })
+        .unwrap_or(0);
+    (msg.header.protocol != RouteProtocol::Ra, metric)
 }
 
-/// Extract (gateway, ifindex) from a default route message (prefix_len == 0).
-fn parse_default_gateway(
+fn strip_ranks<A>(best: HashMap<i32, (RouteRank, A)>) -> HashMap<i32, A> {
+    best.into_iter().map(|(idx, (_, gw))| (idx, gw)).collect()
+}
+
+/// Record `gw` for `idx` unless a better-ranked gateway is already there.
+fn keep_preferred<A>(best: &mut HashMap<i32, (RouteRank, A)>, idx: i32, rank: RouteRank, gw: A) {
+    if best.get(&idx).is_none_or(|(r, _)| rank < *r) {
+        best.insert(idx, (rank, gw));
+    }
+}
+
+/// Extract (gateway, ifindex) pairs from a default route message
+/// (prefix_len == 0): one for a plain route, one per next hop for a
+/// multipath (ECMP) route, which carries no top-level gateway or oif.
+fn parse_default_gateways(
     msg: &netlink_packet_route::route::RouteMessage,
     extract_gw: impl Fn(&RouteAddress) -> Option<IpAddr>,
-) -> Option<(IpAddr, i32)> {
+) -> Vec<(IpAddr, i32)> {
     if msg.header.destination_prefix_length != 0 {
-        return None;
+        return Vec::new();
     }
+    let mut gateways = Vec::new();
     let mut gateway = None;
     let mut oif = None;
     for attr in &msg.attributes {
         match attr {
             RouteAttribute::Gateway(addr) => gateway = extract_gw(addr),
             RouteAttribute::Oif(idx) => oif = Some(*idx as i32),
+            RouteAttribute::MultiPath(hops) => {
+                gateways.extend(hops.iter().filter_map(|hop| {
+                    let gw = hop.attributes.iter().find_map(|attr| match attr {
+                        RouteAttribute::Gateway(addr) => extract_gw(addr),
+                        _ => None,
+                    })?;
+                    Some((gw, hop.interface_index as i32))
+                }));
+            }
             _ => {}
         }
     }
-    gateway.zip(oif)
-}
-
-/// Reload IP addresses for a single interface.
-pub async fn reload_addresses_for(handle: &rtnetlink::Handle, ifindex: i32, shared: &SharedState) {
-    let (ipv4, ipv6) = query_addresses(handle, ifindex).await;
-    let mut state = shared.write().await;
-    if let Some(dev) = state.devices.get_mut(&ifindex) {
-        dev.ipv4_addrs = ipv4;
-        dev.ipv6_addrs = ipv6;
-        debug!(iface = %dev.name, "reloaded addresses");
-    }
-}
-
-/// Reload default gateways for all devices.
-pub async fn reload_gateways(handle: &rtnetlink::Handle, shared: &SharedState) {
-    {
-        let mut state = shared.write().await;
-        for dev in state.devices.values_mut() {
-            dev.gateway4 = None;
-            dev.gateway6 = None;
-        }
-    }
-
-    if let Err(e) = load_default_gateways(handle, shared).await {
-        warn!("failed to reload gateways: {e}");
-    }
+    gateways.extend(gateway.zip(oif));
+    gateways
 }
 
 /// Set a network interface up or down via rtnetlink.
@@ -173,6 +317,8 @@ async fn link_set(handle: &rtnetlink::Handle, ifindex: i32, up: bool) -> Result<
     Ok(())
 }
 
+/// Set a link up for nmlinkd's own purposes. User activations go through
+/// `nm::user_activate`, which also records the user's intent.
 pub async fn link_set_up(handle: &rtnetlink::Handle, ifindex: i32) -> Result<()> {
     link_set(handle, ifindex, true).await
 }
@@ -181,31 +327,369 @@ pub async fn link_set_down(handle: &rtnetlink::Handle, ifindex: i32) -> Result<(
     link_set(handle, ifindex, false).await
 }
 
-/// Parse nameservers from resolv.conf files.
-/// Tries /run/systemd/resolve/resolv.conf first (systemd-resolved upstream DNS),
-/// falls back to /etc/resolv.conf if not available.
-pub async fn reload_nameservers(shared: &SharedState) {
-    let resolv_paths = ["/run/systemd/resolve/resolv.conf", "/etc/resolv.conf"];
+/// Set a network interface's MTU via rtnetlink.
+pub async fn link_set_mtu(handle: &rtnetlink::Handle, ifindex: i32, mtu: u32) -> Result<()> {
+    let msg = rtnetlink::LinkMessageBuilder::<LinkUnspec>::new()
+        .index(ifindex as u32)
+        .mtu(mtu)
+        .build();
+    handle.link().set(msg).execute().await?;
+    Ok(())
+}
+
+/// Metric of the default routes nmlinkd installs on `ifindex`. The kernel
+/// keys IPv4 default routes on table and metric only, so a metric of its
+/// own keeps one link's gateway from colliding with, or replacing, another
+/// link's. Below dhcpcd's 200 + ifindex, so a static gateway wins.
+pub fn default_route_metric(ifindex: i32) -> u32 {
+    100 + ifindex.unsigned_abs()
+}
+
+/// Add an IPv4 default route via `gateway` out of `ifindex`, at
+/// [`default_route_metric`]. Fails with `AlreadyExists` if a default route
+/// with that metric is already in place.
+pub async fn route_add_default(
+    handle: &rtnetlink::Handle,
+    ifindex: i32,
+    gateway: Ipv4Addr,
+) -> Result<()> {
+    let metric = default_route_metric(ifindex);
+    let msg = RouteMessageBuilder::<Ipv4Addr>::new()
+        .output_interface(ifindex as u32)
+        .gateway(gateway)
+        .priority(metric)
+        .build();
+    match handle.route().add(msg).execute().await {
+        Err(rtnetlink::Error::NetlinkError(e))
+            if e.to_io().kind() == std::io::ErrorKind::AlreadyExists =>
+        {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("a default route with metric {metric} already exists"),
+            )
+            .into())
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Like [`route_add_default`], but replaces the link's existing default
+/// route at [`default_route_metric`] instead of failing.
+pub async fn route_replace_default(
+    handle: &rtnetlink::Handle,
+    ifindex: i32,
+    gateway: Ipv4Addr,
+) -> Result<()> {
+    let msg = RouteMessageBuilder::<Ipv4Addr>::new()
+        .output_interface(ifindex as u32)
+        .gateway(gateway)
+        .priority(default_route_metric(ifindex))
+        .build();
+    handle.route().add(msg).replace().execute().await?;
+    Ok(())
+}
+
+/// Assign an address to an interface. An address that is already assigned
+/// (EEXIST) counts as success.
+pub async fn address_add(
+    handle: &rtnetlink::Handle,
+    ifindex: i32,
+    address: IpAddr,
+    prefix_len: u8,
+) -> Result<()> {
+    match handle
+        .address()
+        .add(ifindex as u32, address, prefix_len)
+        .execute()
+        .await
+    {
+        Err(rtnetlink::Error::NetlinkError(e))
+            if e.to_io().kind() == std::io::ErrorKind::AlreadyExists =>
+        {
+            debug!(ifindex, %address, "address already assigned");
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Remove an address from an interface.
+pub async fn address_del(
+    handle: &rtnetlink::Handle,
+    ifindex: i32,
+    address: IpAddr,
+    prefix_len: u8,
+) -> Result<()> {
+    let mut msg = AddressMessage::default();
+    msg.header.family = match address {
+        IpAddr::V4(_) => AddressFamily::Inet,
+        IpAddr::V6(_) => AddressFamily::Inet6,
+    };
+    msg.header.prefix_len = prefix_len;
+    msg.header.index = ifindex as u32;
+    msg.attributes.push(AddressAttribute::Local(address));
+    msg.attributes.push(AddressAttribute::Address(address));
+    handle.address().del(msg).execute().await?;
+    Ok(())
+}
+
+/// Delete every address assigned to an interface via rtnetlink.
+pub async fn flush_addresses(handle: &rtnetlink::Handle, ifindex: i32) -> Result<()> {
+    let mut addrs = handle
+        .address()
+        .get()
+        .set_link_index_filter(ifindex as u32)
+        .execute();
+    let mut msgs = Vec::new();
+    while let Some(msg) = addrs.try_next().await? {
+        msgs.push(msg);
+    }
+
+    for msg in msgs {
+        handle.address().del(msg).execute().await?;
+    }
+    debug!(ifindex, "flushed addresses");
+    Ok(())
+}
+
+/// Nameservers and search domains parsed from a resolv.conf file.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvConf {
+    pub nameservers: Vec<String>,
+    pub domains: Vec<String>,
+    pub options: Vec<String>,
+}
+
+/// Identity of the resolv.conf last parsed (path, mtime, size), used to skip
+/// re-reading and re-parsing a file that hasn't changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvConfStamp {
+    pub path: String,
+    modified: SystemTime,
+    len: u64,
+}
+
+/// Parse nameservers and search domains from resolv.conf into the shared state.
+/// Returns true if the file changed since the last reload.
+pub async fn reload_nameservers(shared: &SharedState) -> bool {
+    let (paths, last) = {
+        let state = shared.read().await;
+        (
+            state.config.resolv_conf_path.clone(),
+            state.resolv_conf_stamp.clone(),
+        )
+    };
+    let Some((stamp, conf)) = read_resolv_conf_if_changed(&paths, last.as_ref()).await else {
+        return false;
+    };
+    let mut state = shared.write().await;
+    state.nameservers = conf.nameservers;
+    state.search_domains = conf.domains;
+    state.resolv_conf_options = conf.options;
+    state.resolv_conf_stamp = Some(stamp);
+    true
+}
+
+/// Parse nameservers and search domains from the first of `paths` that lists
+/// any, unless that file is unchanged since `last` (then returns None).
+/// By default /run/systemd/resolve/resolv.conf (systemd-resolved upstream
+/// DNS) is tried first, then /etc/resolv.conf.
+pub async fn read_resolv_conf_if_changed(
+    paths: &[String],
+    last: Option<&ResolvConfStamp>,
+) -> Option<(ResolvConfStamp, ResolvConf)> {
+    for path in paths {
+        let Ok(meta) = tokio::fs::metadata(path).await else {
+            continue;
+        };
+        let stamp = ResolvConfStamp {
+            path: path.clone(),
+            modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            len: meta.len(),
+        };
+        if last == Some(&stamp) {
+            return None;
+        }
 
-    for path in &resolv_paths {
         if let Ok(contents) = tokio::fs::read_to_string(path).await {
-            let servers: Vec<String> = contents
-                .lines()
-                .filter_map(|line| {
-                    let line = line.trim();
-                    if line.starts_with("nameserver") {
-                        line.split_whitespace().nth(1).map(String::from)
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-
-            if !servers.is_empty() {
-                debug!(path, count = servers.len(), "loaded nameservers");
-                shared.write().await.nameservers = servers;
-                return;
+            let conf = parse_resolv_conf(&contents);
+            if !conf.nameservers.is_empty() {
+                debug!(
+                    path,
+                    count = conf.nameservers.len(),
+                    domains = conf.domains.len(),
+                    "loaded nameservers"
+                );
+                return Some((stamp, conf));
+            }
+        }
+    }
+    None
+}
+
+/// Parse `nameserver` and `search`/`domain` lines. The last `search` or
+/// `domain` line wins, as with glibc.
+fn parse_resolv_conf(contents: &str) -> ResolvConf {
+    let mut conf = ResolvConf::default();
+    for line in contents.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("nameserver") => conf.nameservers.extend(fields.next().map(String::from)),
+            Some("search") | Some("domain") => conf.domains = fields.map(String::from).collect(),
+            Some("options") => conf.options.extend(fields.map(String::from)),
+            _ => {}
+        }
+    }
+    conf
+}
+
+#[cfg(test)]
+mod tests {
+    use netlink_packet_route::route::RouteMessage;
+
+    use super::*;
+
+    fn default_route(protocol: RouteProtocol, attributes: Vec<RouteAttribute>) -> RouteMessage {
+        let mut msg = RouteMessage::default();
+        msg.header.address_family = AddressFamily::Inet6;
+        msg.header.protocol = protocol;
+        msg.attributes = attributes;
+        msg
+    }
+
+    fn inet6(gw: &str) -> RouteAddress {
+        RouteAddress::Inet6(gw.parse().unwrap())
+    }
+
+    /// The IPv6 half of `query_default_gateways`, over a scripted dump.
+    fn ipv6_gateways(routes: &[RouteMessage]) -> HashMap<i32, Ipv6Addr> {
+        let mut best = HashMap::new();
+        for msg in routes {
+            let gateways = parse_default_gateways(msg, |a| match a {
+                RouteAddress::Inet6(ip) => Some(IpAddr::V6(*ip)),
+                _ => None,
+            });
+            for (gw, idx) in gateways {
+                if let IpAddr::V6(v6) = gw {
+                    keep_preferred(&mut best, idx, default_route_rank(msg), v6);
+                }
             }
         }
+        strip_ranks(best)
+    }
+
+    #[test]
+    fn router_advertised_default_route_wins_over_metric() {
+        let ra = default_route(
+            RouteProtocol::Ra,
+            vec![
+                RouteAttribute::Gateway(inet6("fe80::1")),
+                RouteAttribute::Oif(2),
+                RouteAttribute::Priority(1024),
+            ],
+        );
+        let fixed = default_route(
+            RouteProtocol::Static,
+            vec![
+                RouteAttribute::Gateway(inet6("fe80::2")),
+                RouteAttribute::Oif(2),
+                RouteAttribute::Priority(100),
+            ],
+        );
+        let want = HashMap::from([(2, "fe80::1".parse().unwrap())]);
+        assert_eq!(ipv6_gateways(&[ra.clone(), fixed.clone()]), want);
+        assert_eq!(ipv6_gateways(&[fixed, ra]), want);
+    }
+
+    #[test]
+    fn lowest_metric_default_route_wins() {
+        let route = |gw: &str, metric: u32| {
+            default_route(
+                RouteProtocol::Static,
+                vec![
+                    RouteAttribute::Gateway(inet6(gw)),
+                    RouteAttribute::Oif(2),
+                    RouteAttribute::Priority(metric),
+                ],
+            )
+        };
+        let want = HashMap::from([(2, "fe80::2".parse().unwrap())]);
+        assert_eq!(
+            ipv6_gateways(&[route("fe80::1", 1024), route("fe80::2", 100)]),
+            want
+        );
+        assert_eq!(
+            ipv6_gateways(&[route("fe80::2", 100), route("fe80::1", 1024)]),
+            want
+        );
+        // A route without RTA_PRIORITY has metric 0.
+        let unset = default_route(
+            RouteProtocol::Static,
+            vec![
+                RouteAttribute::Gateway(inet6("fe80::3")),
+                RouteAttribute::Oif(2),
+            ],
+        );
+        assert_eq!(
+            ipv6_gateways(&[route("fe80::2", 100), unset])[&2],
+            "fe80::3".parse::<Ipv6Addr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn ipv6_multipath_default_route_yields_each_hop() {
+        use netlink_packet_route::route::RouteNextHop;
+
+        let hop = |gw: &str, ifindex: u32| {
+            let mut hop = RouteNextHop::default();
+            hop.interface_index = ifindex;
+            hop.attributes = vec![RouteAttribute::Gateway(inet6(gw))];
+            hop
+        };
+        // ECMP: no top-level RTA_GATEWAY or RTA_OIF, only RTA_MULTIPATH.
+        let ecmp = default_route(
+            RouteProtocol::Static,
+            vec![
+                RouteAttribute::Priority(1024),
+                RouteAttribute::MultiPath(vec![hop("fe80::1", 2), hop("fe80::2", 3)]),
+            ],
+        );
+        assert_eq!(
+            ipv6_gateways(&[ecmp]),
+            HashMap::from([
+                (2, "fe80::1".parse().unwrap()),
+                (3, "fe80::2".parse().unwrap()),
+            ])
+        );
+
+        // A hop without a gateway (a device route) names no gateway.
+        let mut device_hop = RouteNextHop::default();
+        device_hop.interface_index = 4;
+        let partial = default_route(
+            RouteProtocol::Static,
+            vec![RouteAttribute::MultiPath(vec![
+                hop("fe80::1", 2),
+                device_hop,
+            ])],
+        );
+        assert_eq!(
+            ipv6_gateways(&[partial]),
+            HashMap::from([(2, "fe80::1".parse().unwrap())])
+        );
+    }
+
+    #[test]
+    fn mac_addresses_are_formatted_up_to_infiniband_length() {
+        assert_eq!(
+            format_mac(&[0x52, 0x54, 0x00, 0xab, 0xcd, 0xef]),
+            "52:54:00:AB:CD:EF"
+        );
+        let infiniband: Vec<u8> = (0..20).collect();
+        assert_eq!(
+            format_mac(&infiniband),
+            "00:01:02:03:04:05:06:07:08:09:0A:0B:0C:0D:0E:0F:10:11:12:13"
+        );
+        assert_eq!(format_mac(&[]), "");
+        assert_eq!(format_mac(&[0; 21]), "");
     }
 }
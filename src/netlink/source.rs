@@ -0,0 +1,38 @@
+use std::collections::VecDeque;
+
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::stream::StreamExt;
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload};
+use netlink_packet_route::RouteNetlinkMessage;
+use netlink_sys::SocketAddr;
+
+/// Receiver of multicast messages from a subscribed rtnetlink connection.
+pub type EventStream = UnboundedReceiver<(NetlinkMessage<RouteNetlinkMessage>, SocketAddr)>;
+
+/// A stream of route netlink messages driving the monitor event loop.
+///
+/// The daemon reads from the multicast socket; a scripted `VecDeque` lets the
+/// batching and state logic run without a kernel socket.
+pub trait MessageSource {
+    /// Next message, or None once the source is exhausted.
+    async fn next_message(&mut self) -> Option<RouteNetlinkMessage>;
+}
+
+/// The receiver half returned by `rtnetlink::new_connection()`.
+impl MessageSource for EventStream {
+    async fn next_message(&mut self) -> Option<RouteNetlinkMessage> {
+        while let Some((msg, _)) = self.next().await {
+            if let NetlinkPayload::InnerMessage(inner) = msg.payload {
+                return Some(inner);
+            }
+        }
+        None
+    }
+}
+
+/// A scripted, pre-recorded message sequence.
+impl MessageSource for VecDeque<RouteNetlinkMessage> {
+    async fn next_message(&mut self) -> Option<RouteNetlinkMessage> {
+        self.pop_front()
+    }
+}
@@ -1,108 +1,154 @@
 use zbus::object_server::SignalEmitter;
 use zbus::zvariant::OwnedObjectPath;
 
-use crate::mapping::{self, nm_active_connection_state, nm_device_state};
+use crate::mapping::{self, nm_active_connection_state};
 use crate::state::{self, SharedState, SharedStateExt};
 
+use super::PropertyAudit;
+
 pub struct NmActiveConnection {
     pub ifindex: i32,
     pub state: SharedState,
 }
 
-#[zbus::interface(name = "org.freedesktop.NetworkManager.Connection.Active")]
+impl PropertyAudit for NmActiveConnection {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Connection.Active";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
 impl NmActiveConnection {
-    #[zbus(property(emits_changed_signal = "false"))]
-    async fn state(&self) -> u32 {
+    async fn controller_path(&self) -> OwnedObjectPath {
         self.state
-            .with_device(self.ifindex, |d| {
-                if d.nm_state >= nm_device_state::ACTIVATED {
-                    nm_active_connection_state::ACTIVATED
-                } else {
-                    nm_active_connection_state::DEACTIVATED
-                }
+            .with_state(|s| {
+                let controller = s.devices.get(&self.ifindex)?.controller?;
+                s.devices.get(&controller).map(|d| d.activation_id)
             })
             .await
-            .unwrap_or(nm_active_connection_state::UNKNOWN)
+            .map_or_else(state::root_path, state::active_connection_path)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Connection.Active")]
+impl NmActiveConnection {
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn state(&self) -> u32 {
+        self.audit(
+            "State",
+            self.state
+                .with_device(self.ifindex, |d| {
+                    mapping::device_state_to_ac_state(d.nm_state)
+                })
+                .await
+                .unwrap_or(nm_active_connection_state::UNKNOWN),
+        )
     }
 
     #[zbus(property)]
     async fn default(&self) -> bool {
-        self.state
-            .with_device(self.ifindex, |d| d.gateway4.is_some())
-            .await
-            .unwrap_or(false)
+        self.audit(
+            "Default",
+            self.state
+                .with_device(self.ifindex, |d| d.gateway4.is_some())
+                .await
+                .unwrap_or(false),
+        )
     }
 
     #[zbus(property)]
     async fn default6(&self) -> bool {
-        self.state
-            .with_device(self.ifindex, |d| d.gateway6.is_some())
-            .await
-            .unwrap_or(false)
+        self.audit(
+            "Default6",
+            self.state
+                .with_device(self.ifindex, |d| d.gateway6.is_some())
+                .await
+                .unwrap_or(false),
+        )
     }
 
     #[zbus(property)]
     async fn r#type(&self) -> String {
-        self.state
-            .with_device(self.ifindex, |d| {
-                mapping::device_type_to_connection_type(d.device_type).to_string()
-            })
-            .await
-            .unwrap_or_else(|| "802-3-ethernet".to_string())
+        self.audit(
+            "Type",
+            self.state
+                .with_device(self.ifindex, |d| {
+                    mapping::device_type_to_connection_type(d.device_type).to_string()
+                })
+                .await
+                .unwrap_or_else(|| "802-3-ethernet".to_string()),
+        )
     }
 
     #[zbus(property)]
     async fn id(&self) -> String {
-        self.state
-            .with_device(self.ifindex, |d| d.name.clone())
-            .await
-            .unwrap_or_default()
+        self.audit(
+            "Id",
+            self.state
+                .with_state(|s| s.devices.get(&self.ifindex).map(|d| s.connection_id(d)))
+                .await
+                .unwrap_or_default(),
+        )
     }
 
     #[zbus(property)]
     async fn uuid(&self) -> String {
-        let name = self.id().await;
-        state::connection_uuid(&name)
+        let name = self
+            .state
+            .with_device(self.ifindex, |d| d.name.clone())
+            .await
+            .unwrap_or_default();
+        self.audit("Uuid", state::connection_uuid(&name))
     }
 
     #[zbus(property)]
     async fn devices(&self) -> Vec<OwnedObjectPath> {
-        vec![state::device_path(self.ifindex)]
+        self.audit("Devices", vec![state::device_path(self.ifindex)])
     }
 
     #[zbus(property)]
     fn state_flags(&self) -> u32 {
-        0
+        self.audit("StateFlags", 0)
     }
 
     #[zbus(property)]
-    fn vpn(&self) -> bool {
-        false
+    async fn vpn(&self) -> bool {
+        self.audit(
+            "Vpn",
+            self.state
+                .with_device(self.ifindex, |d| d.vpn)
+                .await
+                .unwrap_or(false),
+        )
     }
 
+    /// For a bond or bridge port, the controller's active connection;
+    /// "/" otherwise.
     #[zbus(property)]
-    fn controller(&self) -> OwnedObjectPath {
-        state::root_path()
+    async fn controller(&self) -> OwnedObjectPath {
+        self.audit("Controller", self.controller_path().await)
     }
 
+    /// Deprecated name of Controller.
     #[zbus(property)]
-    fn master(&self) -> OwnedObjectPath {
-        state::root_path()
+    async fn master(&self) -> OwnedObjectPath {
+        self.audit("Master", self.controller_path().await)
     }
 
     #[zbus(property)]
     fn ip4_config(&self) -> OwnedObjectPath {
-        state::ip4_config_path(self.ifindex)
+        self.audit("Ip4Config", state::ip4_config_path(self.ifindex))
     }
 
     #[zbus(property)]
     fn ip6_config(&self) -> OwnedObjectPath {
-        state::ip6_config_path(self.ifindex)
+        self.audit("Ip6Config", state::ip6_config_path(self.ifindex))
     }
 
     #[zbus(property)]
     fn connection(&self) -> OwnedObjectPath {
-        state::settings_path(self.ifindex)
+        self.audit("Connection", state::settings_path(self.ifindex))
     }
 
     #[zbus(signal)]
@@ -1,50 +1,501 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use tracing::warn;
+use zbus::Connection;
+use zbus::message::Header;
 use zbus::object_server::SignalEmitter;
-use zbus::zvariant::OwnedObjectPath;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
 
-use crate::mapping::{nm_device_state, nm_device_type};
+use crate::mapping::{self, nm_connectivity, nm_device_type};
 use crate::netlink::queries;
-use crate::state::{self, SharedState, SharedStateExt};
+use crate::state::{self, HardwareInfo, SharedState, SharedStateExt};
+use crate::state_file;
+
+use super::PropertyAudit;
 
 pub struct NmDevice {
     pub ifindex: i32,
     pub state: SharedState,
 }
 
+impl PropertyAudit for NmDevice {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Device";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
+impl NmDevice {
+    /// The device's driver/firmware details, read on first use and cached.
+    /// The read (an ethtool ioctl and sysfs) runs on the blocking pool.
+    async fn hardware_info(&self) -> HardwareInfo {
+        let cached = self
+            .state
+            .with_device(self.ifindex, |d| (d.name.clone(), d.hardware.clone()))
+            .await;
+        let name = match cached {
+            Some((_, Some(info))) => return info,
+            Some((name, None)) => name,
+            None => return HardwareInfo::default(),
+        };
+        let info = tokio::task::spawn_blocking(move || HardwareInfo::read(&name))
+            .await
+            .unwrap_or_else(|e| {
+                warn!(ifindex = self.ifindex, "reading hardware info failed: {e}");
+                HardwareInfo::default()
+            });
+        if let Some(dev) = self.state.write().await.devices.get_mut(&self.ifindex) {
+            dev.hardware = Some(info.clone());
+        }
+        info
+    }
+}
+
 pub struct NmDeviceWired {
     pub ifindex: i32,
     pub state: SharedState,
 }
 
+impl PropertyAudit for NmDeviceWired {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Device.Wired";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
 #[zbus::interface(name = "org.freedesktop.NetworkManager.Device.Wired")]
 impl NmDeviceWired {
     #[zbus(property)]
     async fn hw_address(&self) -> String {
-        self.state
-            .with_device(self.ifindex, |d| d.hw_address.clone())
-            .await
-            .unwrap_or_default()
+        self.audit(
+            "HwAddress",
+            self.state
+                .with_device(self.ifindex, |d| d.hw_address.clone())
+                .await
+                .unwrap_or_default(),
+        )
     }
 
+    /// The burned-in MAC, falling back to the current one when unknown.
     #[zbus(property)]
     async fn perm_hw_address(&self) -> String {
-        self.hw_address().await
+        self.audit(
+            "PermHwAddress",
+            self.state
+                .with_device(self.ifindex, |d| {
+                    d.perm_hw_address
+                        .clone()
+                        .unwrap_or_else(|| d.hw_address.clone())
+                })
+                .await
+                .unwrap_or_default(),
+        )
     }
 
     #[zbus(property)]
     async fn speed(&self) -> u32 {
-        self.state
-            .with_device(self.ifindex, |d| d.speed())
-            .await
-            .unwrap_or(0)
+        self.audit(
+            "Speed",
+            self.state
+                .with_device(self.ifindex, |d| d.link_speed)
+                .await
+                .unwrap_or(0),
+        )
+    }
+
+    /// Non-standard: "full" or "half", empty when unknown or without carrier.
+    #[zbus(property)]
+    async fn duplex(&self) -> String {
+        self.audit(
+            "Duplex",
+            self.state
+                .with_device(self.ifindex, |d| d.duplex.clone())
+                .await
+                .unwrap_or_default(),
+        )
     }
 
     #[zbus(property)]
     async fn carrier(&self) -> bool {
-        self.state
-            .with_device(self.ifindex, |d| d.carrier())
-            .await
-            .unwrap_or(false)
+        self.audit(
+            "Carrier",
+            self.state
+                .with_device(self.ifindex, |d| d.carrier())
+                .await
+                .unwrap_or(false),
+        )
+    }
+}
+
+pub struct NmDeviceVlan {
+    pub ifindex: i32,
+    pub state: SharedState,
+}
+
+impl PropertyAudit for NmDeviceVlan {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Device.Vlan";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Device.Vlan")]
+impl NmDeviceVlan {
+    #[zbus(property)]
+    async fn hw_address(&self) -> String {
+        self.audit(
+            "HwAddress",
+            self.state
+                .with_device(self.ifindex, |d| d.hw_address.clone())
+                .await
+                .unwrap_or_default(),
+        )
+    }
+
+    #[zbus(property)]
+    async fn carrier(&self) -> bool {
+        self.audit(
+            "Carrier",
+            self.state
+                .with_device(self.ifindex, |d| d.carrier())
+                .await
+                .unwrap_or(false),
+        )
+    }
+
+    #[zbus(property)]
+    async fn parent(&self) -> OwnedObjectPath {
+        self.audit(
+            "Parent",
+            self.state
+                .with_state(|s| {
+                    s.devices
+                        .get(&self.ifindex)
+                        .and_then(|d| d.vlan.as_ref())
+                        .and_then(|v| v.parent)
+                        .filter(|parent| s.devices.contains_key(parent))
+                        .map(state::device_path)
+                })
+                .await
+                .unwrap_or_else(state::root_path),
+        )
+    }
+
+    #[zbus(property)]
+    async fn vlan_id(&self) -> u32 {
+        self.audit(
+            "VlanId",
+            self.state
+                .with_device(self.ifindex, |d| d.vlan.as_ref().map(|v| v.id as u32))
+                .await
+                .flatten()
+                .unwrap_or(0),
+        )
+    }
+
+    /// Non-standard: raw VLAN_FLAG_* bits (REORDER_HDR, GVRP, LOOSE_BINDING, MVRP).
+    #[zbus(property)]
+    async fn flags(&self) -> u32 {
+        self.audit(
+            "Flags",
+            self.state
+                .with_device(self.ifindex, |d| d.vlan.as_ref().map(|v| v.flags))
+                .await
+                .flatten()
+                .unwrap_or(0),
+        )
+    }
+}
+
+/// Served for bonds in addition to Device.Wired.
+pub struct NmDeviceBond {
+    pub ifindex: i32,
+    pub state: SharedState,
+}
+
+impl PropertyAudit for NmDeviceBond {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Device.Bond";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Device.Bond")]
+impl NmDeviceBond {
+    #[zbus(property)]
+    async fn hw_address(&self) -> String {
+        self.audit(
+            "HwAddress",
+            self.state
+                .with_device(self.ifindex, |d| d.hw_address.clone())
+                .await
+                .unwrap_or_default(),
+        )
+    }
+
+    #[zbus(property)]
+    async fn carrier(&self) -> bool {
+        self.audit(
+            "Carrier",
+            self.state
+                .with_device(self.ifindex, |d| d.carrier())
+                .await
+                .unwrap_or(false),
+        )
+    }
+
+    /// Non-standard: the slave currently carrying traffic, or "/" when none
+    /// is (or the bond's mode has no single active slave).
+    #[zbus(property)]
+    async fn active_slave(&self) -> OwnedObjectPath {
+        self.audit(
+            "ActiveSlave",
+            self.state
+                .with_device(self.ifindex, |d| d.bond_active_slave)
+                .await
+                .flatten()
+                .map_or_else(state::root_path, state::device_path),
+        )
+    }
+
+    /// Deprecated in favour of Device.Ports, which lists the same devices.
+    #[zbus(property)]
+    async fn slaves(&self) -> Vec<OwnedObjectPath> {
+        self.audit(
+            "Slaves",
+            self.state
+                .with_state(|s| s.ports(self.ifindex))
+                .await
+                .into_iter()
+                .map(state::device_path)
+                .collect(),
+        )
+    }
+}
+
+/// Served for devices with no more specific interface (InfiniBand, PPP, ...).
+pub struct NmDeviceGeneric {
+    pub ifindex: i32,
+    pub state: SharedState,
+}
+
+impl PropertyAudit for NmDeviceGeneric {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Device.Generic";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Device.Generic")]
+impl NmDeviceGeneric {
+    #[zbus(property)]
+    async fn hw_address(&self) -> String {
+        self.audit(
+            "HwAddress",
+            self.state
+                .with_device(self.ifindex, |d| d.hw_address.clone())
+                .await
+                .unwrap_or_default(),
+        )
+    }
+
+    #[zbus(property)]
+    async fn type_description(&self) -> String {
+        self.audit(
+            "TypeDescription",
+            self.state
+                .with_device(self.ifindex, |d| d.type_description.clone())
+                .await
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Byte counters, refreshed only while a client has set a refresh rate.
+pub struct NmDeviceStatistics {
+    pub ifindex: i32,
+    pub state: SharedState,
+}
+
+impl PropertyAudit for NmDeviceStatistics {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Device.Statistics";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Device.Statistics")]
+impl NmDeviceStatistics {
+    #[zbus(property)]
+    async fn refresh_rate_ms(&self) -> u32 {
+        self.audit(
+            "RefreshRateMs",
+            self.state
+                .with_device(self.ifindex, |d| d.stats_refresh_ms)
+                .await
+                .unwrap_or(0),
+        )
+    }
+
+    /// Starts polling the counters at this period; 0 stops it.
+    #[zbus(property)]
+    async fn set_refresh_rate_ms(
+        &mut self,
+        refresh_rate_ms: u32,
+        #[zbus(connection)] conn: &Connection,
+    ) -> zbus::fdo::Result<()> {
+        let mut state = self.state.write().await;
+        let Some(dev) = state.devices.get_mut(&self.ifindex) else {
+            return Err(zbus::fdo::Error::UnknownObject(format!(
+                "No device for ifindex {}",
+                self.ifindex
+            )));
+        };
+        if dev.stats_refresh_ms == refresh_rate_ms {
+            return Ok(());
+        }
+        dev.stats_refresh_ms = refresh_rate_ms;
+        if let Some(task) = state.stats_refreshers.remove(&self.ifindex) {
+            task.abort();
+        }
+        if refresh_rate_ms > 0 {
+            let task = tokio::spawn(super::refresh_statistics(
+                conn.clone(),
+                self.state.clone(),
+                self.ifindex,
+                Duration::from_millis(refresh_rate_ms.into()),
+            ));
+            state
+                .stats_refreshers
+                .insert(self.ifindex, task.abort_handle());
+        }
+        Ok(())
+    }
+
+    #[zbus(property)]
+    async fn tx_bytes(&self) -> u64 {
+        self.audit(
+            "TxBytes",
+            self.state
+                .with_device(self.ifindex, |d| d.tx_bytes)
+                .await
+                .unwrap_or(0),
+        )
+    }
+
+    #[zbus(property)]
+    async fn rx_bytes(&self) -> u64 {
+        self.audit(
+            "RxBytes",
+            self.state
+                .with_device(self.ifindex, |d| d.rx_bytes)
+                .await
+                .unwrap_or(0),
+        )
+    }
+}
+
+/// Served for GRE, SIT and IPIP tunnels.
+pub struct NmDeviceIpTunnel {
+    pub ifindex: i32,
+    pub state: SharedState,
+}
+
+impl PropertyAudit for NmDeviceIpTunnel {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Device.IPTunnel";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Device.IPTunnel")]
+impl NmDeviceIpTunnel {
+    #[zbus(property)]
+    async fn mode(&self) -> u32 {
+        self.audit(
+            "Mode",
+            self.state
+                .with_device(self.ifindex, |d| d.tunnel.as_ref().map(|t| t.mode))
+                .await
+                .flatten()
+                .unwrap_or(0),
+        )
+    }
+
+    #[zbus(property)]
+    async fn local(&self) -> String {
+        self.audit(
+            "Local",
+            self.state
+                .with_device(self.ifindex, |d| d.tunnel.as_ref()?.local)
+                .await
+                .flatten()
+                .map(|a| a.to_string())
+                .unwrap_or_default(),
+        )
+    }
+
+    #[zbus(property)]
+    async fn remote(&self) -> String {
+        self.audit(
+            "Remote",
+            self.state
+                .with_device(self.ifindex, |d| d.tunnel.as_ref()?.remote)
+                .await
+                .flatten()
+                .map(|a| a.to_string())
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Minimal stand-in for ModemManager-backed devices, enough for desktops to
+/// show a cellular device. Capabilities are unknown without ModemManager.
+pub struct NmDeviceModem {
+    pub ifindex: i32,
+}
+
+impl PropertyAudit for NmDeviceModem {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Device.Modem";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Device.Modem")]
+impl NmDeviceModem {
+    #[zbus(property)]
+    fn modem_capabilities(&self) -> u32 {
+        self.audit("ModemCapabilities", 0) // NM_DEVICE_MODEM_CAPABILITY_NONE
+    }
+
+    #[zbus(property)]
+    fn current_capabilities(&self) -> u32 {
+        self.audit("CurrentCapabilities", 0)
+    }
+
+    #[zbus(property)]
+    fn device_id(&self) -> String {
+        self.audit("DeviceId", String::new())
+    }
+
+    #[zbus(property)]
+    fn operator_code(&self) -> String {
+        self.audit("OperatorCode", String::new())
+    }
+
+    #[zbus(property)]
+    fn apn(&self) -> String {
+        self.audit("Apn", String::new())
     }
 }
 
@@ -52,21 +503,25 @@ impl NmDeviceWired {
 /// Properties left empty: not useful for a read-only bridge.
 pub struct NmDeviceWireGuard;
 
+impl PropertyAudit for NmDeviceWireGuard {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Device.WireGuard";
+}
+
 #[zbus::interface(name = "org.freedesktop.NetworkManager.Device.WireGuard")]
 impl NmDeviceWireGuard {
     #[zbus(property)]
     fn public_key(&self) -> Vec<u8> {
-        Vec::new()
+        self.audit("PublicKey", Vec::new())
     }
 
     #[zbus(property)]
     fn listen_port(&self) -> u16 {
-        0
+        self.audit("ListenPort", 0)
     }
 
     #[zbus(property)]
     fn fw_mark(&self) -> u32 {
-        0
+        self.audit("FwMark", 0)
     }
 }
 
@@ -74,106 +529,470 @@ impl NmDeviceWireGuard {
 impl NmDevice {
     #[zbus(property(emits_changed_signal = "false"))]
     async fn state(&self) -> u32 {
-        self.state
-            .with_device(self.ifindex, |d| d.nm_state)
-            .await
-            .unwrap_or(0)
+        self.audit(
+            "State",
+            self.state
+                .with_device(self.ifindex, |d| d.nm_state)
+                .await
+                .unwrap_or(0),
+        )
     }
 
     #[zbus(property)]
     async fn state_reason(&self) -> (u32, u32) {
         let nm_state = self.state().await;
-        (nm_state, 0) // reason 0 = NM_DEVICE_STATE_REASON_NONE
+        self.audit("StateReason", (nm_state, 0)) // reason 0 = NM_DEVICE_STATE_REASON_NONE
     }
 
     #[zbus(property)]
     async fn hw_address(&self) -> String {
-        self.state
-            .with_device(self.ifindex, |d| d.hw_address.clone())
-            .await
-            .unwrap_or_default()
+        self.audit(
+            "HwAddress",
+            self.state
+                .with_device(self.ifindex, |d| d.hw_address.clone())
+                .await
+                .unwrap_or_default(),
+        )
     }
 
     #[zbus(property)]
     async fn interface(&self) -> String {
-        self.state
-            .with_device(self.ifindex, |d| d.name.clone())
-            .await
-            .unwrap_or_default()
+        self.audit(
+            "Interface",
+            self.state
+                .with_device(self.ifindex, |d| d.name.clone())
+                .await
+                .unwrap_or_default(),
+        )
     }
 
     #[zbus(property)]
     async fn ip_interface(&self) -> String {
-        self.interface().await
+        self.audit("IpInterface", self.interface().await)
+    }
+
+    /// Non-standard: ARPHRD_* hardware type of the link (1 = Ethernet,
+    /// 32 = InfiniBand, 512 = PPP, ...).
+    #[zbus(property)]
+    async fn arp_type(&self) -> u32 {
+        self.audit(
+            "ArpType",
+            self.state
+                .with_device(self.ifindex, |d| u32::from(d.arp_type))
+                .await
+                .unwrap_or(0),
+        )
+    }
+
+    /// Non-standard: the link's transmit queue length (`ip link`'s qlen).
+    /// Not signalled when it changes.
+    #[zbus(property)]
+    async fn tx_queue_length(&self) -> u32 {
+        self.audit(
+            "TxQueueLength",
+            self.state
+                .with_device(self.ifindex, |d| d.tx_queue_len)
+                .await
+                .unwrap_or(0),
+        )
+    }
+
+    /// Non-standard: the interface alias (`ip link set alias`), empty when
+    /// none is set.
+    #[zbus(property)]
+    async fn alias(&self) -> String {
+        self.audit(
+            "Alias",
+            self.state
+                .with_device(self.ifindex, |d| d.alias.clone())
+                .await
+                .flatten()
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Non-standard: number of transmit queues of the link.
+    #[zbus(property)]
+    async fn num_tx_queues(&self) -> u32 {
+        self.audit(
+            "NumTxQueues",
+            self.state
+                .with_device(self.ifindex, |d| d.num_tx_queues)
+                .await
+                .unwrap_or(0),
+        )
+    }
+
+    #[zbus(property)]
+    async fn driver(&self) -> String {
+        self.audit(
+            "Driver",
+            self.state
+                .with_device(self.ifindex, |d| d.driver.clone())
+                .await
+                .unwrap_or_default(),
+        )
+    }
+
+    #[zbus(property)]
+    async fn driver_version(&self) -> String {
+        self.audit("DriverVersion", self.hardware_info().await.driver_version)
+    }
+
+    /// Empty for interfaces whose driver doesn't report one (virtual links).
+    #[zbus(property)]
+    async fn firmware_version(&self) -> String {
+        self.audit(
+            "FirmwareVersion",
+            self.hardware_info().await.firmware_version,
+        )
+    }
+
+    /// Non-standard: the bus vendor id of the underlying device (sysfs
+    /// `device/vendor`, e.g. "0x8086"); empty for virtual links.
+    #[zbus(property)]
+    async fn device_vendor(&self) -> String {
+        self.audit("DeviceVendor", self.hardware_info().await.vendor)
     }
 
     #[zbus(property)]
     async fn device_type(&self) -> u32 {
-        self.state
-            .with_device(self.ifindex, |d| d.device_type)
-            .await
-            .unwrap_or(nm_device_type::ETHERNET)
+        self.audit(
+            "DeviceType",
+            self.state
+                .with_device(self.ifindex, |d| d.device_type)
+                .await
+                .unwrap_or(nm_device_type::UNKNOWN),
+        )
+    }
+
+    #[zbus(property)]
+    async fn ip4_connectivity(&self) -> u32 {
+        self.audit(
+            "Ip4Connectivity",
+            self.state
+                .with_device(self.ifindex, |d| d.ip4_connectivity())
+                .await
+                .unwrap_or(nm_connectivity::UNKNOWN),
+        )
+    }
+
+    #[zbus(property)]
+    async fn ip6_connectivity(&self) -> u32 {
+        self.audit(
+            "Ip6Connectivity",
+            self.state
+                .with_device(self.ifindex, |d| d.ip6_connectivity())
+                .await
+                .unwrap_or(nm_connectivity::UNKNOWN),
+        )
+    }
+
+    /// nmlinkd runs no LLDP agent: always empty, but present so clients can
+    /// read it.
+    #[zbus(property)]
+    fn lldp_neighbors(&self) -> Vec<HashMap<String, OwnedValue>> {
+        self.audit("LldpNeighbors", Vec::new())
+    }
+
+    #[zbus(property)]
+    async fn mtu(&self) -> u32 {
+        self.audit(
+            "Mtu",
+            self.state
+                .with_device(self.ifindex, |d| d.mtu)
+                .await
+                .unwrap_or(0),
+        )
+    }
+
+    #[zbus(property)]
+    async fn interface_flags(&self) -> u32 {
+        self.audit(
+            "InterfaceFlags",
+            self.state
+                .with_device(self.ifindex, |d| {
+                    mapping::netlink_flags_to_interface_flags(d.link_flags, d.kernel_carrier)
+                })
+                .await
+                .unwrap_or(0),
+        )
+    }
+
+    /// The device's ports, if it's a bond or bridge; empty otherwise.
+    #[zbus(property)]
+    async fn ports(&self) -> Vec<OwnedObjectPath> {
+        self.audit(
+            "Ports",
+            self.state
+                .with_state(|s| s.ports(self.ifindex))
+                .await
+                .into_iter()
+                .map(state::device_path)
+                .collect(),
+        )
     }
 
     #[zbus(property)]
     async fn managed(&self) -> bool {
-        true
+        self.audit(
+            "Managed",
+            self.state
+                .with_device(self.ifindex, |d| d.managed)
+                .await
+                .unwrap_or(true),
+        )
+    }
+
+    /// Persisted per interface name. An unmanaged device reports UNMANAGED
+    /// whatever its link does, and methods acting on it are refused;
+    /// managing it again re-derives its state from the link and addresses,
+    /// which are tracked throughout. Restricted to root and nmlinkd's own
+    /// user.
+    #[zbus(property)]
+    async fn set_managed(
+        &mut self,
+        managed: bool,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<()> {
+        // No header: set from within nmlinkd, not over D-Bus.
+        if let Some(header) = &header {
+            super::ensure_privileged_caller(conn, header).await?;
+        }
+        let (change, persistent) = {
+            let mut state = self.state.write().await;
+            let Some(name) = state.devices.get(&self.ifindex).map(|d| d.name.clone()) else {
+                return Err(zbus::fdo::Error::UnknownObject(format!(
+                    "No device for ifindex {}",
+                    self.ifindex
+                )));
+            };
+            state.ensure_writable()?;
+            state.ensure_not_ignored(self.ifindex)?;
+            if managed && state.config.is_unmanaged(&name) {
+                return Err(zbus::fdo::Error::NotSupported(format!(
+                    "{name} is unmanaged by configuration"
+                )));
+            }
+            let changed = if managed {
+                state.persistent.unmanaged.remove(&name)
+            } else {
+                state.persistent.unmanaged.insert(name)
+            };
+            if !changed {
+                return Ok(());
+            }
+            let Some(dev) = state.devices.get_mut(&self.ifindex) else {
+                return Ok(());
+            };
+            dev.managed = managed;
+            let change =
+                dev.update_state_on_link_change(dev.link_flags, dev.kernel_carrier, dev.operstate);
+            if let Some((new_state, _)) = change
+                && new_state == mapping::nm_device_state::ACTIVATED
+            {
+                state.notify_activated(self.ifindex);
+            }
+            state.recompute_global_state();
+            (change, state.persistent.clone())
+        };
+
+        if let Some((new_state, old_state)) = change {
+            super::signals::notify_device_state_changed(
+                conn,
+                &self.state,
+                self.ifindex,
+                new_state,
+                old_state,
+            )
+            .await;
+            super::signals::notify_global_state_changed(conn, &self.state).await;
+        }
+        if let Err(e) = state_file::save(&persistent).await {
+            warn!(ifindex = self.ifindex, "failed to persist managed: {e}");
+        }
+        Ok(())
     }
 
     #[zbus(property)]
     async fn real(&self) -> bool {
-        true
+        self.audit("Real", true)
     }
 
     #[zbus(property)]
     async fn autoconnect(&self) -> bool {
-        true
+        self.audit(
+            "Autoconnect",
+            self.state
+                .with_state(|s| {
+                    s.devices
+                        .get(&self.ifindex)
+                        .is_none_or(|d| s.autoconnect(d))
+                })
+                .await,
+        )
+    }
+
+    /// Persisted per interface name, so it survives restarts and re-plugging.
+    /// With it off, the stored profile is not re-applied at startup.
+    /// Restricted to root and nmlinkd's own user, and refused for ignored
+    /// devices.
+    #[zbus(property)]
+    async fn set_autoconnect(
+        &mut self,
+        autoconnect: bool,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<()> {
+        // No header: set from within nmlinkd, not over D-Bus.
+        if let Some(header) = &header {
+            super::ensure_privileged_caller(conn, header).await?;
+        }
+        let persistent = {
+            let mut state = self.state.write().await;
+            state.ensure_writable()?;
+            state.ensure_not_ignored(self.ifindex)?;
+            let Some(name) = state.devices.get(&self.ifindex).map(|d| d.name.clone()) else {
+                return Err(zbus::fdo::Error::UnknownObject(format!(
+                    "No device for ifindex {}",
+                    self.ifindex
+                )));
+            };
+            let disabled = &mut state.persistent.autoconnect_disabled;
+            let changed = if autoconnect {
+                disabled.remove(&name)
+            } else {
+                disabled.insert(name)
+            };
+            if !changed {
+                return Ok(());
+            }
+            state.persistent.clone()
+        };
+        if let Err(e) = state_file::save(&persistent).await {
+            warn!(ifindex = self.ifindex, "failed to persist autoconnect: {e}");
+        }
+        Ok(())
     }
 
+    /// Each interface has exactly one connection profile (stored user
+    /// settings or the synthetic default), served at its settings path.
     #[zbus(property)]
-    fn available_connections(&self) -> Vec<OwnedObjectPath> {
-        vec![state::settings_path(self.ifindex)]
+    async fn available_connections(&self) -> Vec<OwnedObjectPath> {
+        self.audit(
+            "AvailableConnections",
+            self.state
+                .with_device(self.ifindex, |d| {
+                    (!d.ignored)
+                        .then(|| state::settings_path(d.ifindex))
+                        .into_iter()
+                        .collect()
+                })
+                .await
+                .unwrap_or_default(),
+        )
     }
 
     #[zbus(property)]
     async fn active_connection(&self) -> OwnedObjectPath {
-        let is_activated = self
+        let path = self
             .state
-            .with_device(self.ifindex, |d| d.nm_state >= nm_device_state::ACTIVATED)
+            .with_device(self.ifindex, |d| {
+                mapping::has_active_connection(d.nm_state)
+                    .then(|| state::active_connection_path(d.activation_id))
+            })
             .await
-            .unwrap_or(false);
-        if is_activated {
-            state::active_connection_path(self.ifindex)
-        } else {
-            state::root_path()
-        }
+            .flatten()
+            .unwrap_or_else(state::root_path);
+        self.audit("ActiveConnection", path)
+    }
+
+    /// Deprecated in NetworkManager: the primary global IPv4 address (the
+    /// first in address order) as a u32 in network byte order, 0 if none.
+    #[zbus(property)]
+    async fn ip4_address(&self) -> u32 {
+        self.audit(
+            "Ip4Address",
+            self.state
+                .with_device(self.ifindex, |d| {
+                    d.ipv4_addrs
+                        .iter()
+                        .find(|a| a.scope == 0)
+                        .map_or(0, |a| u32::from_ne_bytes(a.address.octets()))
+                })
+                .await
+                .unwrap_or(0),
+        )
     }
 
     #[zbus(property)]
     async fn ip4_config(&self) -> OwnedObjectPath {
-        state::ip4_config_path(self.ifindex)
+        self.audit("Ip4Config", state::ip4_config_path(self.ifindex))
     }
 
     #[zbus(property)]
     async fn ip6_config(&self) -> OwnedObjectPath {
-        state::ip6_config_path(self.ifindex)
+        self.audit("Ip6Config", state::ip6_config_path(self.ifindex))
+    }
+
+    async fn disconnect(&self, #[zbus(connection)] conn: &Connection) -> zbus::fdo::Result<()> {
+        super::user_deactivate(conn, &self.state, self.ifindex).await
     }
 
-    async fn disconnect(&self) -> zbus::fdo::Result<()> {
+    /// Apply connection settings to the live device. Only the ethernet MTU
+    /// and static IPv4 addresses and gateway are supported; an empty `connection`
+    /// (reapply the stored profile) is a no-op.
+    async fn reapply(
+        &self,
+        connection: HashMap<String, HashMap<String, Value<'_>>>,
+        _version_id: u64,
+        _flags: u32,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let settings = super::LiveSettings::from_settings(&connection)?;
+        super::ensure_privileged_caller(conn, &header).await?;
+        super::apply_live_settings(conn, &self.state, self.ifindex, &settings).await
+    }
+
+    /// Non-standard: remove every address on the interface, e.g. a stale
+    /// DHCP lease the lease daemon failed to clean up.
+    async fn flush_addresses(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<()> {
+        super::ensure_privileged_caller(conn, &header).await?;
         let handle = {
-            let mut state = self.state.write().await;
-            state.user_disconnect_pending.insert(self.ifindex);
-            state.handle().clone()
+            let state = self.state.read().await;
+            state.ensure_writable()?;
+            state.ensure_controllable(self.ifindex)?;
+            state.try_handle().ok_or_else(super::netlink_not_ready)?
         };
-        if let Err(e) = queries::link_set_down(&handle, self.ifindex).await {
-            warn!(ifindex = self.ifindex, "disconnect failed: {e}");
+        if let Err(e) = queries::flush_addresses(&handle, self.ifindex).await {
+            warn!(ifindex = self.ifindex, "flush addresses failed: {e}");
             return Err(zbus::fdo::Error::Failed(format!(
-                "Failed to disconnect: {e}"
+                "Failed to flush addresses: {e}"
             )));
         }
         Ok(())
     }
 
+    /// Non-standard: the link's ARP/NDP neighbour cache as
+    /// `(ip, mac, NUD_* state)`. Empty unless `track_neighbors` is enabled.
+    async fn get_neighbors(&self) -> Vec<(String, String, u32)> {
+        self.state
+            .with_device(self.ifindex, |d| {
+                d.neighbors
+                    .iter()
+                    .map(|n| (n.address.to_string(), n.mac.clone(), u32::from(n.state)))
+                    .collect()
+            })
+            .await
+            .unwrap_or_default()
+    }
+
     #[zbus(signal)]
     pub async fn state_changed(
         emitter: &SignalEmitter<'_>,
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::state::{AppState, SharedState, SharedStateExt};
+
+use super::PropertyAudit;
+
+/// Addresses systemd-resolved's stub listener answers on.
+const RESOLVED_STUB_ADDRESSES: &[&str] = &["127.0.0.53", "127.0.0.54"];
+
+/// Whether name resolution goes through systemd-resolved: the resolv.conf in
+/// use is resolved's own, or it points at resolved's stub listener.
+fn uses_resolved(state: &AppState) -> bool {
+    state
+        .resolv_conf_stamp
+        .as_ref()
+        .is_some_and(|s| s.path.starts_with("/run/systemd/resolve/"))
+        || state
+            .nameservers
+            .iter()
+            .any(|ns| RESOLVED_STUB_ADDRESSES.contains(&ns.as_str()))
+}
+
+pub struct NmDnsManager {
+    pub state: SharedState,
+}
+
+impl PropertyAudit for NmDnsManager {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.DnsManager";
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.DnsManager")]
+impl NmDnsManager {
+    /// "systemd-resolved" when resolved handles lookups, "default" for a
+    /// plain resolv.conf.
+    #[zbus(property)]
+    async fn mode(&self) -> String {
+        let resolved = self.state.with_state(uses_resolved).await;
+        self.audit(
+            "Mode",
+            if resolved {
+                "systemd-resolved"
+            } else {
+                "default"
+            }
+            .to_string(),
+        )
+    }
+
+    /// How /etc/resolv.conf is maintained, guessed the way NM's "auto" does:
+    /// "symlink" when it links elsewhere (as resolved sets it up), "file"
+    /// when it's a regular file. nmlinkd never writes it.
+    #[zbus(property)]
+    async fn rc_manager(&self) -> String {
+        let rc_manager = match tokio::fs::symlink_metadata("/etc/resolv.conf").await {
+            Ok(meta) if meta.file_type().is_symlink() => "symlink",
+            Ok(_) => "file",
+            Err(_) => "unmanaged",
+        };
+        self.audit("RcManager", rc_manager.to_string())
+    }
+
+    /// The resolv.conf servers and domains, then each link's own servers.
+    #[zbus(property)]
+    async fn configuration(&self) -> Vec<HashMap<String, OwnedValue>> {
+        let configuration = self
+            .state
+            .with_state(|s| {
+                let mut entries = Vec::new();
+                if !s.nameservers.is_empty() {
+                    let mut map = HashMap::new();
+                    map.insert(
+                        "nameservers".to_string(),
+                        Value::from(s.nameservers.clone()).try_into().unwrap(),
+                    );
+                    map.insert(
+                        "domains".to_string(),
+                        Value::from(s.search_domains.clone()).try_into().unwrap(),
+                    );
+                    map.insert(
+                        "priority".to_string(),
+                        Value::from(0i32).try_into().unwrap(),
+                    );
+                    entries.push(map);
+                }
+                let mut devices: Vec<_> = s
+                    .devices
+                    .values()
+                    .filter(|d| !d.nameservers.is_empty())
+                    .collect();
+                devices.sort_by_key(|d| d.ifindex);
+                for dev in devices {
+                    let mut map = HashMap::new();
+                    map.insert(
+                        "nameservers".to_string(),
+                        Value::from(dev.nameservers.clone()).try_into().unwrap(),
+                    );
+                    map.insert(
+                        "interface".to_string(),
+                        Value::from(dev.name.clone()).try_into().unwrap(),
+                    );
+                    map.insert(
+                        "priority".to_string(),
+                        Value::from(dev.dns_priority()).try_into().unwrap(),
+                    );
+                    map.insert("vpn".to_string(), Value::from(dev.vpn).try_into().unwrap());
+                    entries.push(map);
+                }
+                entries
+            })
+            .await;
+        self.audit("Configuration", configuration)
+    }
+
+    /// Non-standard: the resolv.conf nameservers were read from; empty if
+    /// none of the configured paths listed any.
+    #[zbus(property)]
+    async fn resolv_conf_path(&self) -> String {
+        self.audit(
+            "ResolvConfPath",
+            self.state
+                .with_state(|s| s.resolv_conf_stamp.as_ref().map(|st| st.path.clone()))
+                .await
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Non-standard: its `options` lines (e.g. "edns0", "ndots:2"), in order.
+    #[zbus(property)]
+    async fn resolv_conf_options(&self) -> Vec<String> {
+        self.audit(
+            "ResolvConfOptions",
+            self.state
+                .with_state(|s| s.resolv_conf_options.clone())
+                .await,
+        )
+    }
+}
@@ -6,6 +6,8 @@ use zbus::zvariant::{OwnedValue, Str, Value};
 
 use crate::state::{AddrInfo, SharedState, SharedStateExt};
 
+use super::PropertyAudit;
+
 fn address_data_from<A: Display>(addrs: &[AddrInfo<A>]) -> Vec<HashMap<String, OwnedValue>> {
     addrs
         .iter()
@@ -21,6 +23,17 @@ fn address_data_from<A: Display>(addrs: &[AddrInfo<A>]) -> Vec<HashMap<String, O
                 "prefix".to_string(),
                 Value::from(a.prefix_len as u32).try_into().unwrap(),
             );
+            // Non-standard: RT_SCOPE_* value of the address
+            map.insert(
+                "scope".to_string(),
+                Value::from(a.scope as u32).try_into().unwrap(),
+            );
+            if let Some(label) = &a.label {
+                map.insert(
+                    "label".to_string(),
+                    Value::from(Str::from(label.as_str())).try_into().unwrap(),
+                );
+            }
             map
         })
         .collect()
@@ -39,25 +52,50 @@ macro_rules! define_ip_config {
             pub state: SharedState,
         }
 
+        impl PropertyAudit for $struct_name {
+            const IFACE: &'static str = $iface;
+
+            fn audit_ifindex(&self) -> Option<i32> {
+                Some(self.ifindex)
+            }
+        }
+
         #[zbus::interface(name = $iface)]
         impl $struct_name {
             #[zbus(property)]
             async fn address_data(&self) -> Vec<HashMap<String, OwnedValue>> {
-                self.state
-                    .with_device(self.ifindex, |d| address_data_from(&d.$addrs_field))
-                    .await
-                    .unwrap_or_default()
+                self.audit(
+                    "AddressData",
+                    self.state
+                        .with_device(self.ifindex, |d| address_data_from(&d.$addrs_field))
+                        .await
+                        .unwrap_or_default()
+                )
             }
 
             #[zbus(property)]
             async fn gateway(&self) -> String {
-                self.state
-                    .with_device(self.ifindex, |d| {
-                        d.$gateway_field.map(|g| g.to_string())
-                    })
+                self.audit(
+                    "Gateway",
+                    self.state
+                        .with_device(self.ifindex, |d| {
+                            d.$gateway_field.map(|g| g.to_string())
+                        })
+                        .await
+                        .flatten()
+                        .unwrap_or_default()
+                )
+            }
+
+            /// See [`DeviceInfo::dns_priority`](crate::state::DeviceInfo::dns_priority).
+            #[zbus(property)]
+            async fn dns_priority(&self) -> i32 {
+                let priority = self
+                    .state
+                    .with_device(self.ifindex, |d| d.dns_priority())
                     .await
-                    .flatten()
-                    .unwrap_or_default()
+                    .unwrap_or(100);
+                self.audit("DnsPriority", priority)
             }
 
             $($ns_body)*
@@ -73,22 +111,42 @@ define_ip_config!(
     nameserver_property: {
         #[zbus(property)]
         async fn nameserver_data(&self) -> Vec<HashMap<String, OwnedValue>> {
-            self.state
-                .with_state(|s| {
-                    s.nameservers
-                        .iter()
-                        .filter(|ns| ns.parse::<Ipv4Addr>().is_ok())
-                        .map(|ns| {
-                            let mut map = HashMap::new();
-                            map.insert(
-                                "address".to_string(),
-                                Value::from(Str::from(ns.as_str())).try_into().unwrap(),
-                            );
-                            map
-                        })
-                        .collect()
-                })
-                .await
+            self.audit(
+                "NameserverData",
+                self.state
+                    .with_state(|s| {
+                        let Some(dev) = s.devices.get(&self.ifindex) else {
+                            return Vec::new();
+                        };
+                        s.device_nameservers(dev)
+                            .iter()
+                            .filter(|ns| ns.parse::<Ipv4Addr>().is_ok())
+                            .map(|ns| {
+                                let mut map = HashMap::new();
+                                map.insert(
+                                    "address".to_string(),
+                                    Value::from(Str::from(ns.as_str())).try_into().unwrap(),
+                                );
+                                map
+                            })
+                            .collect()
+                    })
+                    .await
+            )
+        }
+
+        #[zbus(property)]
+        async fn domains(&self) -> Vec<String> {
+            self.audit(
+                "Domains",
+                self.state.with_state(|s| s.search_domains.clone()).await
+            )
+        }
+
+        /// WINS servers are never known; present (empty) for NM's shape.
+        #[zbus(property)]
+        async fn wins_server_data(&self) -> Vec<HashMap<String, OwnedValue>> {
+            self.audit("WinsServerData", Vec::new())
         }
     }
 );
@@ -99,17 +157,59 @@ define_ip_config!(
     addrs: ipv6_addrs,
     gateway: gateway6,
     nameserver_property: {
+        #[zbus(property)]
+        async fn domains(&self) -> Vec<String> {
+            self.audit(
+                "Domains",
+                self.state.with_state(|s| s.search_domains.clone()).await
+            )
+        }
+
+        /// Non-standard on IP6Config: the IPv6 servers in IP4Config's
+        /// NameserverData shape.
+        #[zbus(property)]
+        async fn nameserver_data(&self) -> Vec<HashMap<String, OwnedValue>> {
+            self.audit(
+                "NameserverData",
+                self.state
+                    .with_state(|s| {
+                        let Some(dev) = s.devices.get(&self.ifindex) else {
+                            return Vec::new();
+                        };
+                        s.device_nameservers(dev)
+                            .iter()
+                            .filter(|ns| ns.parse::<Ipv6Addr>().is_ok())
+                            .map(|ns| {
+                                let mut map = HashMap::new();
+                                map.insert(
+                                    "address".to_string(),
+                                    Value::from(Str::from(ns.as_str())).try_into().unwrap(),
+                                );
+                                map
+                            })
+                            .collect()
+                    })
+                    .await
+            )
+        }
+
         #[zbus(property)]
         async fn nameservers(&self) -> Vec<Vec<u8>> {
-            self.state
-                .with_state(|s| {
-                    s.nameservers
-                        .iter()
-                        .filter_map(|ns| ns.parse::<Ipv6Addr>().ok())
-                        .map(|ip| ip.octets().to_vec())
-                        .collect()
-                })
-                .await
+            self.audit(
+                "Nameservers",
+                self.state
+                    .with_state(|s| {
+                        let Some(dev) = s.devices.get(&self.ifindex) else {
+                            return Vec::new();
+                        };
+                        s.device_nameservers(dev)
+                            .iter()
+                            .filter_map(|ns| ns.parse::<Ipv6Addr>().ok())
+                            .map(|ip| ip.octets().to_vec())
+                            .collect()
+                    })
+                    .await
+            )
         }
     }
 );
@@ -1,74 +1,152 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
-use tracing::warn;
+use tokio::sync::oneshot;
+use zbus::Connection;
+use zbus::message::Header;
 use zbus::object_server::SignalEmitter;
 use zbus::zvariant::OwnedObjectPath;
 
-use crate::mapping::{self, nm_device_state};
-use crate::netlink::queries;
-use crate::state::{self, SharedState};
+use crate::connectivity;
+use crate::logging;
+use crate::mapping;
+use crate::state::{self, PathKind, SharedState};
+
+use super::PropertyAudit;
+
+/// NetworkManager version reported in `Version`; clients gate features on it.
+const NM_COMPAT_VERSION: &str = "1.52.0";
 
 pub struct NmManager {
     pub state: SharedState,
 }
 
+impl PropertyAudit for NmManager {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager";
+}
+
 #[zbus::interface(name = "org.freedesktop.NetworkManager")]
 impl NmManager {
     #[zbus(property(emits_changed_signal = "false"))]
     async fn state(&self) -> u32 {
-        self.state.read().await.global_state
+        self.audit("State", self.state.read().await.global_state)
     }
 
     #[zbus(property)]
     async fn connectivity(&self) -> u32 {
-        self.state.read().await.connectivity
+        self.audit("Connectivity", self.state.read().await.connectivity)
+    }
+
+    #[zbus(property)]
+    async fn connectivity_check_available(&self) -> bool {
+        self.audit("ConnectivityCheckAvailable", true)
+    }
+
+    #[zbus(property)]
+    async fn connectivity_check_enabled(&self) -> bool {
+        self.audit(
+            "ConnectivityCheckEnabled",
+            self.state.read().await.config.connectivity.interval != 0,
+        )
+    }
+
+    #[zbus(property)]
+    async fn connectivity_check_uri(&self) -> String {
+        self.audit(
+            "ConnectivityCheckUri",
+            self.state.read().await.config.connectivity.uri.clone(),
+        )
     }
 
     #[zbus(property)]
     async fn version(&self) -> String {
-        "1.52.0".to_owned()
+        self.audit("Version", NM_COMPAT_VERSION.to_owned())
+    }
+
+    /// Non-standard: the version of the nmlinkd daemon actually running.
+    #[zbus(property)]
+    async fn nmlinkd_version(&self) -> String {
+        self.audit("NmlinkdVersion", env!("CARGO_PKG_VERSION").to_owned())
     }
 
     #[zbus(property)]
     async fn networking_enabled(&self) -> bool {
-        true
+        self.audit("NetworkingEnabled", true)
     }
 
     #[zbus(property)]
     async fn devices(&self) -> Vec<OwnedObjectPath> {
-        self.device_paths().await
+        self.audit("Devices", self.device_paths().await)
+    }
+
+    /// Same as Devices: nmlinkd has no software devices that exist before
+    /// they're realized.
+    #[zbus(property)]
+    async fn all_devices(&self) -> Vec<OwnedObjectPath> {
+        self.audit("AllDevices", self.device_paths().await)
     }
 
     #[zbus(property)]
     async fn active_connections(&self) -> Vec<OwnedObjectPath> {
-        self.active_connection_paths().await
+        self.audit("ActiveConnections", self.active_connection_paths().await)
     }
 
     #[zbus(property)]
     async fn primary_connection(&self) -> OwnedObjectPath {
         let state = self.state.read().await;
-        for dev in state.devices.values() {
-            if dev.nm_state >= nm_device_state::ACTIVATED && dev.has_gateway() {
-                return state::active_connection_path(dev.ifindex);
-            }
-        }
-        state::root_path()
+        let path = state
+            .primary_device()
+            .map(|dev| state::active_connection_path(dev.activation_id))
+            .unwrap_or_else(state::root_path);
+        self.audit("PrimaryConnection", path)
     }
 
     #[zbus(property)]
     async fn primary_connection_type(&self) -> String {
         let state = self.state.read().await;
-        state
-            .devices
-            .values()
-            .find(|dev| dev.nm_state >= nm_device_state::ACTIVATED && dev.has_gateway())
+        let conn_type = state
+            .primary_device()
             .map(|dev| mapping::device_type_to_connection_type(dev.device_type).to_string())
-            .unwrap_or_default()
+            .unwrap_or_default();
+        self.audit("PrimaryConnectionType", conn_type)
     }
 
     #[zbus(property)]
     async fn metered(&self) -> u32 {
-        4 // NM_METERED_GUESS_NO
+        self.audit(
+            "Metered", 4, // NM_METERED_GUESS_NO
+        )
+    }
+
+    async fn check_connectivity(&self, #[zbus(connection)] conn: &Connection) -> u32 {
+        connectivity::check(conn, &self.state).await
+    }
+
+    /// Logging level and domains; see [`logging::get`].
+    fn get_logging(&self) -> (String, String) {
+        logging::get()
+    }
+
+    /// Map NM logging domains onto nmlinkd's modules; see [`logging::set`].
+    /// Restricted to root and nmlinkd's own user.
+    async fn set_logging(
+        &self,
+        level: &str,
+        domains: &str,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> zbus::fdo::Result<()> {
+        super::ensure_privileged_caller(conn, &header).await?;
+        logging::set(level, domains)
+    }
+
+    /// Non-standard: the daemon's state (devices with their addresses and
+    /// gateways, nameservers, global state, stored profiles) as pretty JSON,
+    /// for bug reports.
+    async fn dump_state(&self) -> zbus::fdo::Result<String> {
+        let state = self.state.read().await;
+        serde_json::to_string_pretty(&state.dump())
+            .map_err(|e| zbus::fdo::Error::Failed(format!("Failed to serialize state: {e}")))
     }
 
     async fn get_devices(&self) -> Vec<OwnedObjectPath> {
@@ -79,11 +157,24 @@ impl NmManager {
         self.device_paths().await
     }
 
+    /// Non-standard: paths of devices whose NMDeviceType equals `device_type`.
+    async fn get_devices_by_type(&self, device_type: u32) -> Vec<OwnedObjectPath> {
+        let state = self.state.read().await;
+        state
+            .devices
+            .values()
+            .filter(|d| d.device_type == device_type)
+            .map(|d| state::device_path(d.ifindex))
+            .collect()
+    }
+
+    /// Only network-control is granted, and not in read-only mode.
     async fn get_permissions(&self) -> HashMap<String, String> {
+        let read_only = self.state.read().await.config.read_only;
         let mut perms = HashMap::new();
         perms.insert(
             "org.freedesktop.NetworkManager.network-control".to_string(),
-            "yes".to_string(),
+            if read_only { "no" } else { "yes" }.to_string(),
         );
         for key in [
             "org.freedesktop.NetworkManager.checkpoint-rollback",
@@ -115,16 +206,20 @@ impl NmManager {
         _specific_object: OwnedObjectPath,
     ) -> zbus::fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
         let ifindex = self.resolve_device_ifindex(&device).await?;
-        let handle = self.state.read().await.handle().clone();
+        let (handle, activation_id) = {
+            let state = self.state.read().await;
+            state.ensure_writable()?;
+            state.ensure_controllable(ifindex)?;
+            let handle = state.try_handle().ok_or_else(super::netlink_not_ready)?;
+            let activation_id = state.devices.get(&ifindex).map_or(0, |d| d.activation_id);
+            (handle, activation_id)
+        };
 
-        if let Err(e) = queries::link_set_up(&handle, ifindex).await {
-            warn!(ifindex, "add_and_activate failed: {e}");
-            return Err(zbus::fdo::Error::Failed(format!("Failed to activate: {e}")));
-        }
+        super::user_activate(&self.state, &handle, ifindex).await?;
 
         Ok((
             state::settings_path(ifindex),
-            state::active_connection_path(ifindex),
+            state::active_connection_path(activation_id),
         ))
     }
 
@@ -133,59 +228,82 @@ impl NmManager {
         connection: OwnedObjectPath,
         device: OwnedObjectPath,
         _specific_object: OwnedObjectPath,
+        #[zbus(connection)] conn: &Connection,
     ) -> zbus::fdo::Result<OwnedObjectPath> {
         // For VPNs, GNOME passes device="/", resolve via connection path instead
         let ifindex = if device.as_str() == "/" {
-            self.resolve_ifindex_from_path(&connection).await?
+            self.resolve_ifindex_from_path(&connection, PathKind::Settings)
+                .await?
         } else {
             self.resolve_device_ifindex(&device).await?
         };
-        let handle = self.state.read().await.handle().clone();
+        let (handle, activation_id, timeout, activated, global_changed) = {
+            let mut state = self.state.write().await;
+            state.ensure_writable()?;
+            state.ensure_controllable(ifindex)?;
+            let handle = state.try_handle().ok_or_else(super::netlink_not_ready)?;
+            let (activation_id, nm_state) = state
+                .devices
+                .get(&ifindex)
+                .map_or((0, 0), |d| (d.activation_id, d.nm_state));
+            let timeout = Duration::from_secs(state.config.activation_timeout);
+            // Registered before the link goes up, so ACTIVATED can't be missed.
+            let activated = (!timeout.is_zero() && nm_state != mapping::nm_device_state::ACTIVATED)
+                .then(|| {
+                    let (tx, rx) = oneshot::channel();
+                    state
+                        .activation_waiters
+                        .entry(ifindex)
+                        .or_default()
+                        .push(tx);
+                    rx
+                });
+            // A device resting in IP_CONFIG now counts as CONNECTING.
+            let old_global = state.global_state;
+            state.recompute_global_state();
+            let global_changed = state.global_state != old_global;
+            (handle, activation_id, timeout, activated, global_changed)
+        };
+        if global_changed {
+            super::signals::notify_global_state_changed(conn, &self.state).await;
+        }
 
-        if let Err(e) = queries::link_set_up(&handle, ifindex).await {
-            warn!(ifindex, "activate connection failed: {e}");
-            return Err(zbus::fdo::Error::Failed(format!("Failed to activate: {e}")));
+        super::user_activate(&self.state, &handle, ifindex).await?;
+
+        if let Some(activated) = activated {
+            super::await_activation(conn, &self.state, ifindex, timeout, activated).await?;
         }
 
-        Ok(state::active_connection_path(ifindex))
+        Ok(state::active_connection_path(activation_id))
     }
 
     async fn deactivate_connection(
         &self,
         active_connection: OwnedObjectPath,
+        #[zbus(connection)] conn: &Connection,
     ) -> zbus::fdo::Result<()> {
-        let ifindex = self.resolve_ifindex_from_path(&active_connection).await?;
-        let handle = {
-            let mut state = self.state.write().await;
-            state.user_disconnect_pending.insert(ifindex);
-            state.handle().clone()
-        };
-
-        if let Err(e) = queries::link_set_down(&handle, ifindex).await {
-            warn!(ifindex, "deactivate connection failed: {e}");
-            return Err(zbus::fdo::Error::Failed(format!(
-                "Failed to deactivate: {e}"
-            )));
-        }
-
-        Ok(())
+        let ifindex = self
+            .resolve_ifindex_from_path(&active_connection, PathKind::ActiveConnection)
+            .await?;
+        super::user_deactivate(conn, &self.state, ifindex).await
     }
 
     async fn get_device_by_ip_iface(&self, iface: &str) -> zbus::fdo::Result<OwnedObjectPath> {
         let state = self.state.read().await;
-        for dev in state.devices.values() {
-            if dev.name == iface {
-                return Ok(state::device_path(dev.ifindex));
-            }
-        }
-        Err(zbus::fdo::Error::UnknownObject(format!(
-            "No device for interface {iface}"
-        )))
+        state
+            .ifindex_by_name(iface)
+            .map(state::device_path)
+            .ok_or_else(|| {
+                zbus::fdo::Error::UnknownObject(format!("No device for interface {iface}"))
+            })
     }
 
     #[zbus(signal)]
     pub async fn state_changed(emitter: &SignalEmitter<'_>, state: u32) -> zbus::Result<()>;
 
+    #[zbus(signal)]
+    pub async fn check_permissions(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
     #[zbus(signal)]
     pub async fn device_added(
         emitter: &SignalEmitter<'_>,
@@ -200,26 +318,34 @@ impl NmManager {
 }
 
 impl NmManager {
-    /// Parse ifindex from a D-Bus path like /org/.../Devices/{ifindex} and validate the device exists.
+    /// Resolve a Devices/{ifindex} path and validate the device exists.
     async fn resolve_device_ifindex(&self, device: &OwnedObjectPath) -> zbus::fdo::Result<i32> {
-        self.resolve_ifindex_from_path(device).await
+        self.resolve_ifindex_from_path(device, PathKind::Device)
+            .await
     }
 
-    /// Parse ifindex from any NM object path (Devices, ActiveConnection, Settings, etc.).
-    async fn resolve_ifindex_from_path(&self, path: &OwnedObjectPath) -> zbus::fdo::Result<i32> {
-        let ifindex: i32 = path
-            .rsplit('/')
-            .next()
-            .and_then(|s| s.parse().ok())
-            .ok_or_else(|| zbus::fdo::Error::UnknownObject(format!("Invalid path {path}")))?;
+    /// Resolve an NM object path of the `expected` kind (Devices, ActiveConnection,
+    /// Settings, ...) to the ifindex of an existing device.
+    async fn resolve_ifindex_from_path(
+        &self,
+        path: &OwnedObjectPath,
+        expected: PathKind,
+    ) -> zbus::fdo::Result<i32> {
+        let index = match state::parse_nm_path(path) {
+            Some((kind, index)) if kind == expected => index,
+            _ => {
+                return Err(zbus::fdo::Error::UnknownObject(format!(
+                    "Invalid {expected:?} path {path}: expected {}",
+                    expected.template()
+                )));
+            }
+        };
         let state = self.state.read().await;
-        if state.devices.contains_key(&ifindex) {
-            Ok(ifindex)
-        } else {
-            Err(zbus::fdo::Error::UnknownObject(format!(
-                "No device for path {path}"
-            )))
-        }
+        let ifindex = match expected {
+            PathKind::ActiveConnection => state.ifindex_by_activation_id(index),
+            _ => state.devices.contains_key(&index).then_some(index),
+        };
+        ifindex.ok_or_else(|| zbus::fdo::Error::UnknownObject(format!("No device for path {path}")))
     }
 
     async fn device_paths(&self) -> Vec<OwnedObjectPath> {
@@ -232,12 +358,113 @@ impl NmManager {
     }
 
     async fn active_connection_paths(&self) -> Vec<OwnedObjectPath> {
-        let state = self.state.read().await;
-        state
-            .devices
-            .values()
-            .filter(|d| d.nm_state >= nm_device_state::ACTIVATED)
-            .map(|d| state::active_connection_path(d.ifindex))
-            .collect()
+        self.state.read().await.active_connection_paths()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, ETH_IFINDEX, WG_IFINDEX};
+
+    fn manager() -> NmManager {
+        NmManager {
+            state: test_support::fake_state(),
+        }
+    }
+
+    fn path(path: &str) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn paths_of_the_expected_kind_resolve() {
+        let manager = manager();
+        let activation_id = manager.state.read().await.devices[&WG_IFINDEX].activation_id;
+        for (object, kind, ifindex) in [
+            (
+                state::device_path(ETH_IFINDEX),
+                PathKind::Device,
+                ETH_IFINDEX,
+            ),
+            (
+                state::settings_path(WG_IFINDEX),
+                PathKind::Settings,
+                WG_IFINDEX,
+            ),
+            (
+                state::active_connection_path(activation_id),
+                PathKind::ActiveConnection,
+                WG_IFINDEX,
+            ),
+        ] {
+            assert_eq!(
+                manager
+                    .resolve_ifindex_from_path(&object, kind)
+                    .await
+                    .unwrap(),
+                ifindex,
+                "{object}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_paths_are_unknown_objects() {
+        let manager = manager();
+        for (object, kind) in [
+            // Right index, wrong kind.
+            (state::device_path(ETH_IFINDEX), PathKind::Settings),
+            (state::settings_path(ETH_IFINDEX), PathKind::Device),
+            (state::ip4_config_path(ETH_IFINDEX), PathKind::Device),
+            // Well-formed, but nothing behind it.
+            (state::device_path(99), PathKind::Device),
+            (
+                state::active_connection_path(99),
+                PathKind::ActiveConnection,
+            ),
+            // Malformed.
+            (
+                path("/org/freedesktop/NetworkManager/Devices/02"),
+                PathKind::Device,
+            ),
+            (
+                path("/org/freedesktop/NetworkManager/Devices/2/x"),
+                PathKind::Device,
+            ),
+            (path("/"), PathKind::Device),
+        ] {
+            assert!(
+                matches!(
+                    manager.resolve_ifindex_from_path(&object, kind).await,
+                    Err(zbus::fdo::Error::UnknownObject(_))
+                ),
+                "{object} accepted as {kind:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn devices_are_listed_by_type() {
+        let manager = manager();
+        assert_eq!(
+            manager
+                .get_devices_by_type(mapping::nm_device_type::ETHERNET)
+                .await,
+            [state::device_path(ETH_IFINDEX)]
+        );
+        assert_eq!(
+            manager
+                .get_devices_by_type(mapping::nm_device_type::WIREGUARD)
+                .await,
+            [state::device_path(WG_IFINDEX)]
+        );
+        assert!(
+            manager
+                .get_devices_by_type(mapping::nm_device_type::BOND)
+                .await
+                .is_empty()
+        );
+        assert!(manager.get_devices_by_type(u32::MAX).await.is_empty());
     }
 }
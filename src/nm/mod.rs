@@ -1,22 +1,34 @@
 pub mod active_connection;
 pub mod device;
+pub mod dns_manager;
 pub mod ip_config;
 pub mod manager;
 pub mod settings;
 pub mod settings_connection;
 pub mod signals;
 
-use tracing::{error, info};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+use tracing::{debug, error, info, trace, warn};
 use zbus::Connection;
-use zbus::connection::Builder;
-use zbus::zvariant::OwnedObjectPath;
+use zbus::message::Header;
+use zbus::names::BusName;
+use zbus::zvariant::{OwnedObjectPath, Value};
 
 use crate::Result;
-use crate::mapping::nm_device_type;
-use crate::state::{self, SharedState};
+use crate::mapping::{self, nm_device_state, nm_device_type};
+use crate::netlink::queries;
+use crate::state::{self, AddrInfo, SharedState, SharedStateExt};
 
 use active_connection::NmActiveConnection;
-use device::{NmDevice, NmDeviceWireGuard, NmDeviceWired};
+use device::{
+    NmDevice, NmDeviceBond, NmDeviceGeneric, NmDeviceIpTunnel, NmDeviceModem, NmDeviceStatistics,
+    NmDeviceVlan, NmDeviceWireGuard, NmDeviceWired,
+};
+use dns_manager::NmDnsManager;
 use ip_config::{NmIp4Config, NmIp6Config};
 use manager::NmManager;
 use settings::NmSettings;
@@ -31,123 +43,682 @@ struct DevicePaths {
 }
 
 impl DevicePaths {
-    fn new(ifindex: i32) -> Self {
+    fn new(ifindex: i32, activation_id: i32) -> Self {
         Self {
             dev: state::device_path(ifindex),
             ip4: state::ip4_config_path(ifindex),
             ip6: state::ip6_config_path(ifindex),
-            active: state::active_connection_path(ifindex),
+            active: state::active_connection_path(activation_id),
             settings: state::settings_path(ifindex),
         }
     }
 }
 
-/// Build the NM D-Bus server: register all interfaces and claim the bus name.
-pub async fn serve(shared: SharedState) -> Result<Connection> {
-    let state = shared.read().await;
-    let devices: Vec<(i32, u32)> = state
-        .devices
-        .values()
-        .map(|d| (d.ifindex, d.device_type))
-        .collect();
-    drop(state);
+/// Property-read audit log for diagnosing stale client caches. Every property
+/// getter passes its result through [`PropertyAudit::audit`]; run with
+/// `RUST_LOG=nmlinkd::props=trace` to log each value handed to a client and
+/// diff it against the PropertiesChanged signals we emit.
+pub trait PropertyAudit {
+    /// D-Bus interface the properties belong to.
+    const IFACE: &'static str;
+
+    /// ifindex of the device the object belongs to; None for singletons.
+    fn audit_ifindex(&self) -> Option<i32> {
+        None
+    }
+
+    fn audit<T: std::fmt::Debug>(&self, property: &str, value: T) -> T {
+        trace!(
+            target: "nmlinkd::props",
+            iface = Self::IFACE,
+            ifindex = ?self.audit_ifindex(),
+            property,
+            ?value,
+            "property read"
+        );
+        value
+    }
+}
 
-    let device_paths: Vec<(i32, u32, DevicePaths)> = devices
+/// Error returned by method handlers called before the netlink handle is set.
+pub fn netlink_not_ready() -> zbus::fdo::Error {
+    zbus::fdo::Error::Failed("netlink connection not initialized yet".to_string())
+}
+
+/// Reject a call from anyone but root or the user nmlinkd runs as, for the
+/// methods NetworkManager guards with polkit. Peer-to-peer connections carry
+/// no sender and are trusted.
+pub async fn ensure_privileged_caller(
+    conn: &Connection,
+    header: &Header<'_>,
+) -> zbus::fdo::Result<()> {
+    let Some(sender) = header.sender() else {
+        return Ok(());
+    };
+    let dbus = zbus::fdo::DBusProxy::new(conn).await?;
+    let uid = dbus
+        .get_connection_unix_user(BusName::Unique(sender.clone()))
+        .await?;
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let own_uid = unsafe { libc::geteuid() };
+    if uid != 0 && uid != own_uid {
+        return Err(zbus::fdo::Error::AccessDenied(format!(
+            "{sender} (uid {uid}) is not allowed to change network configuration"
+        )));
+    }
+    Ok(())
+}
+
+/// MTU requested by a connection settings dict, from `802-3-ethernet.mtu`
+/// or its `ethernet` alias. 0 means "leave unchanged", as in NM.
+pub fn settings_mtu(settings: &HashMap<String, HashMap<String, Value<'_>>>) -> Option<u32> {
+    ["802-3-ethernet", "ethernet"]
         .iter()
-        .map(|&(idx, dt)| (idx, dt, DevicePaths::new(idx)))
-        .collect();
-
-    let mut builder = Builder::system()?
-        .name("org.freedesktop.NetworkManager")?
-        .serve_at("/org/freedesktop", zbus::fdo::ObjectManager)?
-        .serve_at(
-            "/org/freedesktop/NetworkManager",
-            NmManager {
-                state: shared.clone(),
-            },
-        )?
-        .serve_at(
-            "/org/freedesktop/NetworkManager/Settings",
-            NmSettings {
-                state: shared.clone(),
-            },
-        )?;
-
-    for (ifindex, device_type, p) in &device_paths {
-        info!(ifindex, path = %p.dev, "registering device");
-
-        builder = builder.serve_at(
-            &p.dev,
-            NmDevice {
-                ifindex: *ifindex,
-                state: shared.clone(),
-            },
-        )?;
-
-        if *device_type == nm_device_type::WIREGUARD {
-            builder = builder.serve_at(&p.dev, NmDeviceWireGuard)?;
-        } else {
-            builder = builder.serve_at(
-                &p.dev,
-                NmDeviceWired {
-                    ifindex: *ifindex,
-                    state: shared.clone(),
-                },
-            )?;
+        .filter_map(|section| settings.get(*section)?.get("mtu"))
+        .find_map(|v| u32::try_from(v).ok())
+        .filter(|&mtu| mtu != 0)
+}
+
+/// `ipv4.method` of a connection settings dict, if it sets one.
+pub fn settings_ipv4_method<'a>(
+    settings: &'a HashMap<String, HashMap<String, Value<'_>>>,
+) -> Option<&'a str> {
+    let method = settings.get("ipv4")?.get("method")?;
+    <&str>::try_from(method).ok()
+}
+
+/// Static IPv4 addresses requested by a connection settings dict: the
+/// `ipv4.address-data` entries, when `ipv4.method` is "manual". None when
+/// the dict requests no static addresses (no `address-data`); an entry that
+/// is not a valid address with a prefix of at most 32 rejects the whole dict.
+pub fn settings_ipv4_addresses(
+    settings: &HashMap<String, HashMap<String, Value<'_>>>,
+) -> zbus::fdo::Result<Option<Vec<(Ipv4Addr, u8)>>> {
+    let Some(ipv4) = settings.get("ipv4") else {
+        return Ok(None);
+    };
+    if settings_ipv4_method(settings) != Some("manual") {
+        return Ok(None);
+    }
+    let Some(data) = ipv4.get("address-data") else {
+        return Ok(None);
+    };
+    let Value::Array(entries) = data else {
+        return Err(zbus::fdo::Error::InvalidArgs(
+            "ipv4.address-data must be an array of dicts".to_string(),
+        ));
+    };
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            parse_address_data_entry(entry).ok_or_else(|| {
+                zbus::fdo::Error::InvalidArgs(format!(
+                    "ipv4.address-data[{i}] is not a valid IPv4 address and prefix (0-32)"
+                ))
+            })
+        })
+        .collect::<zbus::fdo::Result<Vec<_>>>()
+        .map(Some)
+}
+
+/// One `address-data` entry: a dict with an IPv4 `address` string and a
+/// `prefix` of at most 32.
+fn parse_address_data_entry(entry: &Value<'_>) -> Option<(Ipv4Addr, u8)> {
+    let Value::Dict(dict) = entry else {
+        return None;
+    };
+    let address: &str = dict.get(&"address").ok()??;
+    let prefix: u32 = dict.get(&"prefix").ok()??;
+    let prefix = u8::try_from(prefix).ok().filter(|&p| p <= 32)?;
+    Some((address.parse().ok()?, prefix))
+}
+
+/// Live settings requested by `Device.Reapply` or `Settings.Connection.Update`.
+#[derive(Debug, Default)]
+pub struct LiveSettings {
+    pub mtu: Option<u32>,
+    pub ipv4_addresses: Option<Vec<(Ipv4Addr, u8)>>,
+    pub ipv4_gateway: Option<Ipv4Addr>,
+}
+
+impl LiveSettings {
+    /// The parts of a connection settings dict nmlinkd applies live.
+    pub fn from_settings(
+        settings: &HashMap<String, HashMap<String, Value<'_>>>,
+    ) -> zbus::fdo::Result<Self> {
+        Ok(Self {
+            mtu: settings_mtu(settings),
+            ipv4_addresses: settings_ipv4_addresses(settings)?,
+            ipv4_gateway: settings_ipv4_gateway(settings),
+        })
+    }
+}
+
+/// Apply `settings` to the device: MTU, then addresses, then gateway; empty
+/// settings are a no-op. Read-only mode, an uncontrollable device, a
+/// missing netlink handle and a gateway off the resulting subnets are all
+/// refused before the first netlink request; a step the kernel rejects
+/// afterwards fails the call with the steps already applied named in the
+/// error. The caller must have checked [`ensure_privileged_caller`].
+pub async fn apply_live_settings(
+    conn: &Connection,
+    shared: &SharedState,
+    ifindex: i32,
+    settings: &LiveSettings,
+) -> zbus::fdo::Result<()> {
+    if settings.mtu.is_none()
+        && settings.ipv4_addresses.is_none()
+        && settings.ipv4_gateway.is_none()
+    {
+        return Ok(());
+    }
+    {
+        let state = shared.read().await;
+        state.ensure_writable()?;
+        state.ensure_controllable(ifindex)?;
+        if let Some(gateway) = settings.ipv4_gateway {
+            let reachable = match &settings.ipv4_addresses {
+                Some(addrs) => addrs
+                    .iter()
+                    .any(|&(address, prefix_len)| in_subnet(gateway, address, prefix_len)),
+                None => state.devices.get(&ifindex).is_some_and(|d| {
+                    d.ipv4_addrs
+                        .iter()
+                        .any(|a| in_subnet(gateway, a.address, a.prefix_len))
+                }),
+            };
+            if !reachable {
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "Gateway {gateway} is not on any of the interface's subnets"
+                )));
+            }
         }
+        state.try_handle().ok_or_else(netlink_not_ready)?;
+    }
 
-        builder = builder
-            .serve_at(
-                &p.ip4,
-                NmIp4Config {
-                    ifindex: *ifindex,
-                    state: shared.clone(),
-                },
-            )?
-            .serve_at(
-                &p.ip6,
-                NmIp6Config {
-                    ifindex: *ifindex,
-                    state: shared.clone(),
-                },
-            )?
-            .serve_at(
-                &p.active,
-                NmActiveConnection {
-                    ifindex: *ifindex,
-                    state: shared.clone(),
-                },
-            )?
-            .serve_at(
-                &p.settings,
-                NmSettingsConnection {
-                    ifindex: *ifindex,
-                    state: shared.clone(),
-                },
-            )?;
+    let mut applied = Vec::new();
+    let partial = |applied: &[&str], e| match e {
+        zbus::fdo::Error::Failed(msg) if !applied.is_empty() => {
+            zbus::fdo::Error::Failed(format!("{msg} (already applied: {})", applied.join(", ")))
+        }
+        e => e,
+    };
+    if let Some(mtu) = settings.mtu {
+        apply_mtu(conn, shared, ifindex, mtu)
+            .await
+            .map_err(|e| partial(&applied, e))?;
+        applied.push("MTU");
+    }
+    if let Some(addrs) = &settings.ipv4_addresses {
+        apply_ipv4_addresses(conn, shared, ifindex, addrs)
+            .await
+            .map_err(|e| partial(&applied, e))?;
+        applied.push("IPv4 addresses");
     }
+    if let Some(gateway) = settings.ipv4_gateway {
+        apply_ipv4_gateway(conn, shared, ifindex, gateway)
+            .await
+            .map_err(|e| partial(&applied, e))?;
+    }
+    Ok(())
+}
 
-    let conn = builder.build().await.inspect_err(|_| {
-        error!(
-            "failed to claim org.freedesktop.NetworkManager bus name — is NetworkManager running?"
-        );
-    })?;
+/// Make `addrs` the device's static IPv4 addresses: add the missing ones,
+/// remove other static ones, and reflect the result in state and on D-Bus
+/// right away instead of waiting for the kernel's notification.
+async fn apply_ipv4_addresses(
+    conn: &Connection,
+    shared: &SharedState,
+    ifindex: i32,
+    addrs: &[(Ipv4Addr, u8)],
+) -> zbus::fdo::Result<()> {
+    let (handle, current) = {
+        let state = shared.read().await;
+        state.ensure_writable()?;
+        state.ensure_controllable(ifindex)?;
+        let handle = state.try_handle().ok_or_else(netlink_not_ready)?;
+        let current: Vec<(Ipv4Addr, u8)> = state
+            .devices
+            .get(&ifindex)
+            .map(|d| {
+                d.ipv4_addrs
+                    .iter()
+                    .filter(|a| a.is_permanent)
+                    .map(|a| (a.address, a.prefix_len))
+                    .collect()
+            })
+            .unwrap_or_default();
+        (handle, current)
+    };
+
+    let stale: Vec<_> = current.iter().filter(|a| !addrs.contains(a)).collect();
+    for &&(address, prefix_len) in &stale {
+        if let Err(e) = queries::address_del(&handle, ifindex, address.into(), prefix_len).await {
+            warn!(ifindex, %address, "remove address failed: {e}");
+            return Err(zbus::fdo::Error::Failed(format!(
+                "Failed to remove {address}/{prefix_len}: {e}"
+            )));
+        }
+    }
+    for &(address, prefix_len) in addrs {
+        if let Err(e) = queries::address_add(&handle, ifindex, address.into(), prefix_len).await {
+            warn!(ifindex, %address, "add address failed: {e}");
+            return Err(zbus::fdo::Error::Failed(format!(
+                "Failed to add {address}/{prefix_len}: {e}"
+            )));
+        }
+    }
+
+    let state_change = {
+        let mut state = shared.write().await;
+        let Some(dev) = state.devices.get_mut(&ifindex) else {
+            return Ok(());
+        };
+        dev.ipv4_addrs
+            .retain(|a| !stale.contains(&&(a.address, a.prefix_len)));
+        for &(address, prefix_len) in addrs {
+            if !dev.ipv4_addrs.iter().any(|a| a.address == address) {
+                dev.ipv4_addrs.push(AddrInfo {
+                    address,
+                    prefix_len,
+                    is_permanent: true,
+                    scope: 0,
+                    label: Some(dev.name.clone()),
+                });
+            }
+        }
+        state::sort_addresses(&mut dev.ipv4_addrs);
+        // The global state is left to the monitor, which notifies on it.
+        dev.update_state_on_ip_change()
+    };
+
+    if let Some((new_state, old_state)) = state_change {
+        signals::notify_device_state_changed(conn, shared, ifindex, new_state, old_state).await;
+    }
+    signals::notify_device_ip_config_changed(conn, ifindex).await;
+    signals::notify_ip4_config_changed(conn, ifindex).await;
+    Ok(())
+}
+
+/// IPv4 default gateway requested by a connection settings dict
+/// (`ipv4.gateway`).
+pub fn settings_ipv4_gateway(
+    settings: &HashMap<String, HashMap<String, Value<'_>>>,
+) -> Option<Ipv4Addr> {
+    let gateway = settings.get("ipv4")?.get("gateway")?;
+    <&str>::try_from(gateway).ok()?.parse().ok()
+}
+
+/// Whether `addr` lies in `network`/`prefix_len`.
+fn in_subnet(addr: Ipv4Addr, network: Ipv4Addr, prefix_len: u8) -> bool {
+    let mask = u32::MAX
+        .checked_shl(32 - u32::from(prefix_len))
+        .unwrap_or(0);
+    u32::from(addr) & mask == u32::from(network) & mask
+}
+
+/// Route IPv4 traffic through `gateway` on the device, replacing the default
+/// route nmlinkd installed there if it has one; see
+/// [`queries::default_route_metric`].
+async fn apply_ipv4_gateway(
+    conn: &Connection,
+    shared: &SharedState,
+    ifindex: i32,
+    gateway: Ipv4Addr,
+) -> zbus::fdo::Result<()> {
+    let (handle, replace) = {
+        let state = shared.read().await;
+        state.ensure_writable()?;
+        state.ensure_controllable(ifindex)?;
+        let handle = state.try_handle().ok_or_else(netlink_not_ready)?;
+        let replace = state
+            .devices
+            .get(&ifindex)
+            .is_some_and(|d| d.gateway4.is_some());
+        (handle, replace)
+    };
+
+    let result = if replace {
+        queries::route_replace_default(&handle, ifindex, gateway).await
+    } else {
+        queries::route_add_default(&handle, ifindex, gateway).await
+    };
+    if let Err(e) = result {
+        warn!(ifindex, %gateway, "set default route failed: {e}");
+        return Err(zbus::fdo::Error::Failed(format!(
+            "Failed to set gateway {gateway}: {e}"
+        )));
+    }
+
+    // The global state is left to the monitor, which sees the route change.
+    if let Some(dev) = shared.write().await.devices.get_mut(&ifindex) {
+        dev.gateway4 = Some(gateway);
+    }
+    signals::notify_device_ip_config_changed(conn, ifindex).await;
+    Ok(())
+}
+
+/// Set a device's MTU in the kernel, then record it and emit `Device.Mtu`.
+async fn apply_mtu(
+    conn: &Connection,
+    shared: &SharedState,
+    ifindex: i32,
+    mtu: u32,
+) -> zbus::fdo::Result<()> {
+    let handle = {
+        let state = shared.read().await;
+        state.ensure_writable()?;
+        state.ensure_controllable(ifindex)?;
+        state.try_handle().ok_or_else(netlink_not_ready)?
+    };
+    if let Err(e) = queries::link_set_mtu(&handle, ifindex, mtu).await {
+        warn!(ifindex, mtu, "set MTU failed: {e}");
+        return Err(zbus::fdo::Error::Failed(format!(
+            "Failed to set MTU {mtu}: {e}"
+        )));
+    }
+
+    // Recording it here keeps the monitor from notifying a second time.
+    let changed = {
+        let mut state = shared.write().await;
+        match state.devices.get_mut(&ifindex) {
+            Some(dev) if dev.mtu != mtu => {
+                dev.mtu = mtu;
+                true
+            }
+            _ => false,
+        }
+    };
+    if changed {
+        signals::notify_device_mtu_changed(conn, ifindex, mtu).await;
+    }
+    Ok(())
+}
+
+/// Wait for an activation to complete. A device not ACTIVATED within
+/// `timeout` is put in FAILED (reason IP_CONFIG_UNAVAILABLE) and the
+/// activation reported as failed.
+pub async fn await_activation(
+    conn: &Connection,
+    shared: &SharedState,
+    ifindex: i32,
+    timeout: Duration,
+    activated: oneshot::Receiver<()>,
+) -> zbus::fdo::Result<()> {
+    match tokio::time::timeout(timeout, activated).await {
+        Ok(Ok(())) => return Ok(()),
+        Ok(Err(_)) => {
+            return Err(zbus::fdo::Error::UnknownObject(
+                "Device went away during activation".to_string(),
+            ));
+        }
+        Err(_) => {}
+    }
+
+    let old_state = {
+        let mut state = shared.write().await;
+        if let Some(waiters) = state.activation_waiters.get_mut(&ifindex) {
+            waiters.retain(|w| !w.is_closed());
+        }
+        let Some(dev) = state.devices.get_mut(&ifindex) else {
+            return Err(zbus::fdo::Error::UnknownObject(
+                "Device went away during activation".to_string(),
+            ));
+        };
+        if dev.nm_state == nm_device_state::ACTIVATED {
+            return Ok(());
+        }
+        let old_state = std::mem::replace(&mut dev.nm_state, nm_device_state::FAILED);
+        state.activation_failed_pending.insert(ifindex);
+        state.recompute_global_state();
+        old_state
+    };
+
+    warn!(ifindex, "activation timed out after {}s", timeout.as_secs());
+    signals::notify_device_state_changed(conn, shared, ifindex, nm_device_state::FAILED, old_state)
+        .await;
+    signals::notify_global_state_changed(conn, shared).await;
+    Err(zbus::fdo::Error::Failed(format!(
+        "Device not activated within {}s",
+        timeout.as_secs()
+    )))
+}
+
+/// Bring a device up on user request (ActivateConnection,
+/// AddAndActivateConnection).
+///
+/// Unlike a bare `queries::link_set_up`, which is for nmlinkd's own
+/// housekeeping, this records the user's intent: a disconnect still pending
+/// from an earlier `user_deactivate` is dropped, so the next time the link
+/// goes down is not reported as USER_REQUESTED.
+pub async fn user_activate(
+    shared: &SharedState,
+    handle: &rtnetlink::Handle,
+    ifindex: i32,
+) -> zbus::fdo::Result<()> {
+    shared
+        .write()
+        .await
+        .user_disconnect_pending
+        .remove(&ifindex);
+    queries::link_set_up(handle, ifindex).await.map_err(|e| {
+        warn!(ifindex, "activate connection failed: {e}");
+        zbus::fdo::Error::Failed(format!("Failed to activate: {e}"))
+    })
+}
+
+/// Take a device down on user request (Disconnect / DeactivateConnection).
+///
+/// Announces DEACTIVATING, and the global state it leads to, before setting
+/// the link down; the monitor then sees the link drop and completes the
+/// transition to DISCONNECTED with reason USER_REQUESTED. If the kernel
+/// refuses, the previous state is restored.
+pub async fn user_deactivate(
+    conn: &Connection,
+    shared: &SharedState,
+    ifindex: i32,
+) -> zbus::fdo::Result<()> {
+    let (handle, old_state, global_changed) = {
+        let mut state = shared.write().await;
+        state.ensure_writable()?;
+        state.ensure_controllable(ifindex)?;
+        let handle = state.try_handle().ok_or_else(netlink_not_ready)?;
+        state.user_disconnect_pending.insert(ifindex);
+        let old_state = state
+            .devices
+            .get_mut(&ifindex)
+            .filter(|dev| dev.nm_state > nm_device_state::DISCONNECTED)
+            .map(|dev| std::mem::replace(&mut dev.nm_state, nm_device_state::DEACTIVATING));
+        let old_global = state.global_state;
+        state.recompute_global_state();
+        (handle, old_state, state.global_state != old_global)
+    };
+
+    if let Some(old) = old_state {
+        signals::notify_device_state_changed(
+            conn,
+            shared,
+            ifindex,
+            nm_device_state::DEACTIVATING,
+            old,
+        )
+        .await;
+    }
+    if global_changed {
+        signals::notify_global_state_changed(conn, shared).await;
+    }
+
+    let Err(e) = queries::link_set_down(&handle, ifindex).await else {
+        return Ok(());
+    };
+    warn!(ifindex, "set link down failed: {e}");
+
+    let (reverted, global_changed) = {
+        let mut state = shared.write().await;
+        state.user_disconnect_pending.remove(&ifindex);
+        let reverted = match (old_state, state.devices.get_mut(&ifindex)) {
+            (Some(old), Some(dev)) if dev.nm_state == nm_device_state::DEACTIVATING => {
+                dev.nm_state = old;
+                Some(old)
+            }
+            _ => None,
+        };
+        let old_global = state.global_state;
+        state.recompute_global_state();
+        (reverted, state.global_state != old_global)
+    };
+    if let Some(old) = reverted {
+        signals::notify_device_state_changed(
+            conn,
+            shared,
+            ifindex,
+            old,
+            nm_device_state::DEACTIVATING,
+        )
+        .await;
+    }
+    if global_changed {
+        signals::notify_global_state_changed(conn, shared).await;
+    }
+    Err(zbus::fdo::Error::Failed(format!(
+        "Failed to deactivate: {e}"
+    )))
+}
+
+/// First delay between system bus connection attempts, doubled each retry.
+const BUS_RETRY_INITIAL: Duration = Duration::from_millis(250);
+const BUS_RETRY_MAX_DELAY: Duration = Duration::from_secs(5);
+/// Give up (and exit) if the system bus hasn't appeared after this long.
+const BUS_MAX_WAIT: Duration = Duration::from_secs(90);
+
+/// Message bus to serve the NM API on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus {
+    System,
+    /// The user's session bus, for development: no root needed, and no
+    /// clash with a NetworkManager running on the system bus.
+    Session,
+}
+
+impl std::fmt::Display for Bus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Bus::System => "system bus",
+            Bus::Session => "session bus",
+        })
+    }
+}
+
+/// Connect to `bus`, retrying with exponential backoff so nmlinkd can be
+/// started before dbus is ready (early boot, containers).
+async fn connect_bus(bus: Bus) -> Result<Connection> {
+    let started = Instant::now();
+    let mut delay = BUS_RETRY_INITIAL;
+    loop {
+        let conn = match bus {
+            Bus::System => Connection::system().await,
+            Bus::Session => Connection::session().await,
+        };
+        match conn {
+            Ok(conn) => return Ok(conn),
+            Err(e) if started.elapsed() + delay <= BUS_MAX_WAIT => {
+                info!("{bus} unavailable ({e}), retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(BUS_RETRY_MAX_DELAY);
+            }
+            Err(e) => {
+                error!("{bus} still unavailable after {BUS_MAX_WAIT:?}, giving up");
+                return Err(e.into());
+            }
+        }
+    }
+}
+
+/// Build the NM D-Bus server: connect to `bus` (waiting for it if needed),
+/// register all interfaces and claim the bus name.
+///
+/// Every initial device object is exported before the name is requested, so
+/// clients that activate us never see a partially-populated tree. `DeviceAdded`
+/// is only emitted for hotplug; clients are expected to call `GetDevices` on
+/// startup. With `announce_initial_devices` set, the initial set is also
+/// announced via `DeviceAdded` (in ifindex order) right after the name is claimed.
+pub async fn serve(shared: SharedState, bus: Bus) -> Result<Connection> {
+    let (mut ifindexes, announce, bus_name) = {
+        let state = shared.read().await;
+        let ifindexes: Vec<i32> = state.devices.keys().copied().collect();
+        (
+            ifindexes,
+            state.config.announce_initial_devices,
+            state.config.bus_name.clone(),
+        )
+    };
+    ifindexes.sort_unstable();
+
+    let conn = connect_bus(bus).await?;
+    register_objects(&conn, &shared, &ifindexes).await?;
+
+    conn.request_name(bus_name.as_str())
+        .await
+        .inspect_err(|_| {
+            error!("failed to claim {bus_name} bus name — is NetworkManager running?");
+        })?;
+    info!("claimed {bus_name} on {bus}");
+
+    if announce {
+        for ifindex in ifindexes {
+            signals::notify_device_added(&conn, ifindex).await;
+        }
+    }
 
     Ok(conn)
 }
 
-/// Register all D-Bus interfaces for a single device (hotplug support).
-pub async fn register_device(conn: &Connection, ifindex: i32, state: SharedState) -> Result<()> {
-    let p = DevicePaths::new(ifindex);
+/// Export the singleton objects (Manager, Settings, DnsManager and the
+/// ObjectManager) and the devices in `ifindexes` on `conn`.
+pub async fn register_objects(
+    conn: &Connection,
+    shared: &SharedState,
+    ifindexes: &[i32],
+) -> Result<()> {
     let obj = conn.object_server();
+    obj.at("/org/freedesktop", zbus::fdo::ObjectManager).await?;
+    obj.at(
+        "/org/freedesktop/NetworkManager",
+        NmManager {
+            state: shared.clone(),
+        },
+    )
+    .await?;
+    obj.at(
+        "/org/freedesktop/NetworkManager/Settings",
+        NmSettings {
+            state: shared.clone(),
+        },
+    )
+    .await?;
+    obj.at(
+        "/org/freedesktop/NetworkManager/DnsManager",
+        NmDnsManager {
+            state: shared.clone(),
+        },
+    )
+    .await?;
 
-    let device_type = state
-        .read()
+    for &ifindex in ifindexes {
+        register_device(conn, ifindex, shared.clone()).await?;
+    }
+    Ok(())
+}
+
+/// Register all D-Bus interfaces for a single device.
+pub async fn register_device(conn: &Connection, ifindex: i32, state: SharedState) -> Result<()> {
+    let (device_type, activation_id, ignored) = state
+        .with_device(ifindex, |d| (d.device_type, d.activation_id, d.ignored))
         .await
-        .devices
-        .get(&ifindex)
-        .map(|d| d.device_type)
-        .unwrap_or(nm_device_type::ETHERNET);
+        .unwrap_or((nm_device_type::ETHERNET, 0, false));
+    let p = DevicePaths::new(ifindex, activation_id);
+    let obj = conn.object_server();
 
     info!(ifindex, path = %p.dev, "registering device");
 
@@ -160,18 +731,72 @@ pub async fn register_device(conn: &Connection, ifindex: i32, state: SharedState
     )
     .await?;
 
-    if device_type == nm_device_type::WIREGUARD {
-        obj.at(&p.dev, NmDeviceWireGuard).await?;
-    } else {
-        obj.at(
-            &p.dev,
-            NmDeviceWired {
-                ifindex,
-                state: state.clone(),
-            },
-        )
-        .await?;
-    }
+    // Ignored interfaces are bare devices: no type-specific interface, and
+    // no connection.
+    match device_type {
+        _ if ignored => false,
+        nm_device_type::WIREGUARD => obj.at(&p.dev, NmDeviceWireGuard).await?,
+        nm_device_type::MODEM => obj.at(&p.dev, NmDeviceModem { ifindex }).await?,
+        nm_device_type::VLAN => {
+            obj.at(
+                &p.dev,
+                NmDeviceVlan {
+                    ifindex,
+                    state: state.clone(),
+                },
+            )
+            .await?
+        }
+        nm_device_type::IP_TUNNEL => {
+            obj.at(
+                &p.dev,
+                NmDeviceIpTunnel {
+                    ifindex,
+                    state: state.clone(),
+                },
+            )
+            .await?
+        }
+        t if mapping::is_ethernet_like(t) => {
+            if t == nm_device_type::BOND {
+                obj.at(
+                    &p.dev,
+                    NmDeviceBond {
+                        ifindex,
+                        state: state.clone(),
+                    },
+                )
+                .await?;
+            }
+            obj.at(
+                &p.dev,
+                NmDeviceWired {
+                    ifindex,
+                    state: state.clone(),
+                },
+            )
+            .await?
+        }
+        _ => {
+            obj.at(
+                &p.dev,
+                NmDeviceGeneric {
+                    ifindex,
+                    state: state.clone(),
+                },
+            )
+            .await?
+        }
+    };
+
+    obj.at(
+        &p.dev,
+        NmDeviceStatistics {
+            ifindex,
+            state: state.clone(),
+        },
+    )
+    .await?;
 
     obj.at(
         &p.ip4,
@@ -189,6 +814,9 @@ pub async fn register_device(conn: &Connection, ifindex: i32, state: SharedState
         },
     )
     .await?;
+    if ignored {
+        return Ok(());
+    }
     obj.at(
         &p.active,
         NmActiveConnection {
@@ -204,22 +832,624 @@ pub async fn register_device(conn: &Connection, ifindex: i32, state: SharedState
 }
 
 /// Unregister all D-Bus interfaces for a device (hotplug removal).
-pub async fn unregister_device(conn: &Connection, ifindex: i32, device_type: u32) -> Result<()> {
-    let p = DevicePaths::new(ifindex);
+pub async fn unregister_device(
+    conn: &Connection,
+    ifindex: i32,
+    device_type: u32,
+    activation_id: i32,
+    ignored: bool,
+) -> Result<()> {
+    let p = DevicePaths::new(ifindex, activation_id);
     let obj = conn.object_server();
 
     info!(ifindex, path = %p.dev, "unregistering device");
 
     obj.remove::<NmDevice, _>(&p.dev).await?;
-    if device_type == nm_device_type::WIREGUARD {
-        obj.remove::<NmDeviceWireGuard, _>(&p.dev).await?;
-    } else {
-        obj.remove::<NmDeviceWired, _>(&p.dev).await?;
-    }
+    match device_type {
+        _ if ignored => false,
+        nm_device_type::WIREGUARD => obj.remove::<NmDeviceWireGuard, _>(&p.dev).await?,
+        nm_device_type::MODEM => obj.remove::<NmDeviceModem, _>(&p.dev).await?,
+        nm_device_type::VLAN => obj.remove::<NmDeviceVlan, _>(&p.dev).await?,
+        nm_device_type::IP_TUNNEL => obj.remove::<NmDeviceIpTunnel, _>(&p.dev).await?,
+        t if mapping::is_ethernet_like(t) => {
+            if t == nm_device_type::BOND {
+                obj.remove::<NmDeviceBond, _>(&p.dev).await?;
+            }
+            obj.remove::<NmDeviceWired, _>(&p.dev).await?
+        }
+        _ => obj.remove::<NmDeviceGeneric, _>(&p.dev).await?,
+    };
+    obj.remove::<NmDeviceStatistics, _>(&p.dev).await?;
     obj.remove::<NmIp4Config, _>(&p.ip4).await?;
     obj.remove::<NmIp6Config, _>(&p.ip6).await?;
+    if ignored {
+        return Ok(());
+    }
     obj.remove::<NmActiveConnection, _>(&p.active).await?;
     obj.remove::<NmSettingsConnection, _>(&p.settings).await?;
 
     Ok(())
 }
+
+/// Poll a device's byte counters every `period`, notifying clients when they
+/// move. Runs until aborted: on a RefreshRateMs change or device removal.
+pub async fn refresh_statistics(
+    conn: Connection,
+    shared: SharedState,
+    ifindex: i32,
+    period: Duration,
+) {
+    let mut ticker = tokio::time::interval(period);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let Some(handle) = shared.read().await.try_handle() else {
+            continue;
+        };
+        let (rx_bytes, tx_bytes) = match queries::link_byte_counters(&handle, ifindex).await {
+            Ok(Some(counters)) => counters,
+            Ok(None) => continue,
+            Err(e) => {
+                debug!(ifindex, "failed to read link statistics: {e}");
+                continue;
+            }
+        };
+        let changed = {
+            let mut state = shared.write().await;
+            let Some(dev) = state.devices.get_mut(&ifindex) else {
+                return;
+            };
+            let changed = (dev.rx_bytes, dev.tx_bytes) != (rx_bytes, tx_bytes);
+            dev.rx_bytes = rx_bytes;
+            dev.tx_bytes = tx_bytes;
+            changed
+        };
+        if changed {
+            signals::notify_device_statistics_changed(&conn, ifindex, rx_bytes, tx_bytes).await;
+        }
+    }
+}
+
+/// Retire a device's active connection once it has deactivated: the next
+/// activation gets a new id, and the object moves to the matching path.
+pub async fn renew_active_connection(conn: &Connection, shared: &SharedState, ifindex: i32) {
+    let ids = {
+        let mut state = shared.write().await;
+        let new = state.next_activation_id();
+        state
+            .devices
+            .get_mut(&ifindex)
+            .map(|dev| (std::mem::replace(&mut dev.activation_id, new), new))
+    };
+    let Some((old, new)) = ids else {
+        return;
+    };
+
+    let obj = conn.object_server();
+    let old_path = state::active_connection_path(old);
+    let new_path = state::active_connection_path(new);
+    debug!(ifindex, old = %old_path, new = %new_path, "renewing active connection");
+    if let Err(e) = obj.remove::<NmActiveConnection, _>(&old_path).await {
+        warn!(
+            ifindex,
+            "failed to remove active connection {old_path}: {e}"
+        );
+    }
+    let ac = NmActiveConnection {
+        ifindex,
+        state: shared.clone(),
+    };
+    if let Err(e) = obj.at(&new_path, ac).await {
+        warn!(
+            ifindex,
+            "failed to register active connection {new_path}: {e}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fmt::Write;
+
+    use super::*;
+    use crate::test_support;
+
+    const GOLDEN_PROPERTIES: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/nm/testdata/properties.golden"
+    );
+
+    /// Every exported property as `path interface.Property = value`, sorted.
+    /// Connection UUIDs (from /etc/machine-id), the hostname and the version
+    /// are masked; object path lists are sorted.
+    async fn property_snapshot(client: &Connection) -> String {
+        let manager = zbus::fdo::ObjectManagerProxy::builder(client)
+            .destination("org.freedesktop.NetworkManager")
+            .unwrap()
+            .path("/org/freedesktop")
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let objects: BTreeMap<_, _> = manager
+            .get_managed_objects()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(path, interfaces)| (path.to_string(), interfaces))
+            .collect();
+        let mut snapshot = String::new();
+        for (path, interfaces) in objects {
+            let interfaces: BTreeMap<_, _> = interfaces
+                .into_iter()
+                .map(|(iface, properties)| (iface.to_string(), properties))
+                .collect();
+            for (iface, properties) in interfaces {
+                let properties: BTreeMap<_, _> = properties.into_iter().collect();
+                for (name, value) in properties {
+                    let value = match &*name {
+                        "Hostname" => "<hostname>".to_string(),
+                        "NmlinkdVersion" => "<version>".to_string(),
+                        // Path lists follow HashMap order; compare them sorted.
+                        _ => match Vec::<OwnedObjectPath>::try_from(value.try_clone().unwrap()) {
+                            Ok(mut paths) if !paths.is_empty() => {
+                                paths.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+                                let paths: Vec<_> = paths.iter().map(|p| p.as_str()).collect();
+                                format!("ao {paths:?}")
+                            }
+                            _ => value.to_string(),
+                        },
+                    };
+                    writeln!(snapshot, "{path} {iface}.{name} = {value}").unwrap();
+                }
+            }
+        }
+        for name in ["nmtest0", "nmtest1"] {
+            snapshot = snapshot.replace(&state::connection_uuid(name), &format!("<uuid:{name}>"));
+        }
+        snapshot
+    }
+
+    /// Lock down the property surface: any change to an exported value shows
+    /// up as a diff against the golden file. Regenerate it with
+    /// `UPDATE_GOLDEN=1 cargo test` after an intended change.
+    #[tokio::test]
+    async fn properties_match_golden_snapshot() {
+        let (_shared, _server, client) = test_support::served_fake_state().await;
+        let snapshot = property_snapshot(&client).await;
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(GOLDEN_PROPERTIES, &snapshot).unwrap();
+            return;
+        }
+        let golden = std::fs::read_to_string(GOLDEN_PROPERTIES).unwrap();
+        for (line, (got, want)) in snapshot.lines().zip(golden.lines()).enumerate() {
+            assert_eq!(got, want, "properties differ at line {}", line + 1);
+        }
+        assert_eq!(snapshot.lines().count(), golden.lines().count());
+    }
+
+    async fn introspect(client: &Connection, path: &str) -> String {
+        zbus::fdo::IntrospectableProxy::builder(client)
+            .destination("org.freedesktop.NetworkManager")
+            .unwrap()
+            .path(path.to_string())
+            .unwrap()
+            .build()
+            .await
+            .unwrap()
+            .introspect()
+            .await
+            .unwrap()
+    }
+
+    /// The members nmcli and desktop applets can't work without, per object.
+    #[tokio::test]
+    async fn critical_members_are_introspectable() {
+        const NM: &str = "/org/freedesktop/NetworkManager";
+        let expected: &[(&str, &str, &[&str], &[&str])] = &[
+            (
+                NM,
+                "org.freedesktop.NetworkManager",
+                &[
+                    "State",
+                    "Connectivity",
+                    "Devices",
+                    "AllDevices",
+                    "ActiveConnections",
+                    "PrimaryConnection",
+                    "NetworkingEnabled",
+                    "Version",
+                ],
+                &[
+                    "GetDevices",
+                    "GetAllDevices",
+                    "GetDeviceByIpIface",
+                    "GetPermissions",
+                    "CheckConnectivity",
+                    "ActivateConnection",
+                    "DeactivateConnection",
+                ],
+            ),
+            (
+                "/org/freedesktop/NetworkManager/Settings",
+                "org.freedesktop.NetworkManager.Settings",
+                &["Connections", "Hostname", "CanModify"],
+                &["ListConnections"],
+            ),
+            (
+                "/org/freedesktop/NetworkManager/Settings/2",
+                "org.freedesktop.NetworkManager.Settings.Connection",
+                &["Unsaved", "Flags", "Filename"],
+                &["GetSettings", "GetSecrets", "Update"],
+            ),
+            (
+                "/org/freedesktop/NetworkManager/DnsManager",
+                "org.freedesktop.NetworkManager.DnsManager",
+                &["Mode", "RcManager", "Configuration"],
+                &[],
+            ),
+            (
+                "/org/freedesktop/NetworkManager/Devices/2",
+                "org.freedesktop.NetworkManager.Device",
+                &[
+                    "Interface",
+                    "IpInterface",
+                    "DeviceType",
+                    "State",
+                    "StateReason",
+                    "Managed",
+                    "Ip4Config",
+                    "Ip6Config",
+                    "ActiveConnection",
+                    "AvailableConnections",
+                ],
+                &["Disconnect", "Reapply"],
+            ),
+            (
+                "/org/freedesktop/NetworkManager/Devices/2",
+                "org.freedesktop.NetworkManager.Device.Wired",
+                &["Carrier", "HwAddress", "Speed"],
+                &[],
+            ),
+            (
+                "/org/freedesktop/NetworkManager/Devices/3",
+                "org.freedesktop.NetworkManager.Device.WireGuard",
+                &["PublicKey", "ListenPort", "FwMark"],
+                &[],
+            ),
+            (
+                "/org/freedesktop/NetworkManager/ActiveConnection/1",
+                "org.freedesktop.NetworkManager.Connection.Active",
+                &[
+                    "Id",
+                    "Uuid",
+                    "Type",
+                    "State",
+                    "Devices",
+                    "Default",
+                    "Default6",
+                    "Vpn",
+                    "Connection",
+                ],
+                &[],
+            ),
+            (
+                "/org/freedesktop/NetworkManager/IP4Config/2",
+                "org.freedesktop.NetworkManager.IP4Config",
+                &["AddressData", "Gateway", "NameserverData"],
+                &[],
+            ),
+            (
+                "/org/freedesktop/NetworkManager/IP6Config/2",
+                "org.freedesktop.NetworkManager.IP6Config",
+                &["AddressData", "Gateway", "Nameservers"],
+                &[],
+            ),
+        ];
+
+        let (_shared, _server, client) = test_support::served_fake_state().await;
+        for (path, iface, properties, methods) in expected {
+            let xml = introspect(&client, path).await;
+            let start = xml
+                .find(&format!("<interface name=\"{iface}\">"))
+                .unwrap_or_else(|| panic!("{path} does not implement {iface}"));
+            let end = start + xml[start..].find("</interface>").unwrap();
+            let interface = &xml[start..end];
+            for name in *properties {
+                assert!(
+                    interface.contains(&format!("<property name=\"{name}\"")),
+                    "{path} {iface} lacks property {name}"
+                );
+            }
+            for name in *methods {
+                assert!(
+                    interface.contains(&format!("<method name=\"{name}\"")),
+                    "{path} {iface} lacks method {name}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn user_deactivate_reports_disconnecting() {
+        let shared = state::new_shared_state(
+            crate::config::Config::default(),
+            crate::state_file::PersistentState::default(),
+        );
+        // The connection half is never polled: requests queue up and are
+        // neither sent to the kernel nor answered.
+        let (_netlink, handle, _) = rtnetlink::new_connection().unwrap();
+        {
+            let mut state = shared.try_write().unwrap();
+            let mut dev = state::DeviceInfo::new(2, "eth0".to_string());
+            dev.nm_state = nm_device_state::ACTIVATED;
+            dev.ipv4_addrs.push(state::AddrInfo {
+                address: Ipv4Addr::new(192, 0, 2, 10),
+                prefix_len: 24,
+                is_permanent: true,
+                scope: 0,
+                label: None,
+            });
+            state.insert_device(dev);
+            state.netlink_handle = Some(handle);
+            state.recompute_global_state();
+            assert_eq!(state.global_state, mapping::nm_state::CONNECTED_LOCAL);
+        }
+        let (server, client) = test_support::p2p_connections().await;
+        register_objects(&server, &shared, &[2]).await.unwrap();
+
+        let deactivate = tokio::spawn({
+            let shared = shared.clone();
+            async move { user_deactivate(&server, &shared, 2).await }
+        });
+        let properties = zbus::fdo::PropertiesProxy::builder(&client)
+            .destination("org.freedesktop.NetworkManager")
+            .unwrap()
+            .path("/org/freedesktop/NetworkManager")
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let manager_state = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let manager_state = properties
+                    .get(
+                        "org.freedesktop.NetworkManager".try_into().unwrap(),
+                        "State",
+                    )
+                    .await
+                    .unwrap();
+                let manager_state = u32::try_from(manager_state).unwrap();
+                if manager_state != mapping::nm_state::CONNECTED_LOCAL {
+                    return manager_state;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(manager_state, mapping::nm_state::DISCONNECTING);
+        assert_eq!(
+            shared.read().await.devices[&2].nm_state,
+            nm_device_state::DEACTIVATING
+        );
+        deactivate.abort();
+    }
+
+    #[tokio::test]
+    async fn timed_out_activation_leaves_connecting() {
+        let shared = state::new_shared_state(
+            crate::config::Config::default(),
+            crate::state_file::PersistentState::default(),
+        );
+        let (activated, waiter) = oneshot::channel();
+        {
+            let mut state = shared.try_write().unwrap();
+            let mut dev = state::DeviceInfo::new(2, "eth0".to_string());
+            dev.nm_state = nm_device_state::IP_CONFIG;
+            state.insert_device(dev);
+            state.activation_waiters.insert(2, vec![activated]);
+            state.recompute_global_state();
+            assert_eq!(state.global_state, mapping::nm_state::CONNECTING);
+        }
+        let (server, client) = test_support::p2p_connections().await;
+        register_objects(&server, &shared, &[2]).await.unwrap();
+
+        let result = await_activation(&server, &shared, 2, Duration::from_millis(10), waiter).await;
+        assert!(matches!(result, Err(zbus::fdo::Error::Failed(_))));
+
+        {
+            let state = shared.read().await;
+            assert_eq!(state.devices[&2].nm_state, nm_device_state::FAILED);
+            assert_eq!(state.global_state, mapping::nm_state::DISCONNECTED);
+        }
+        let properties = zbus::fdo::PropertiesProxy::builder(&client)
+            .destination("org.freedesktop.NetworkManager")
+            .unwrap()
+            .path("/org/freedesktop/NetworkManager")
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let manager_state = properties
+            .get(
+                "org.freedesktop.NetworkManager".try_into().unwrap(),
+                "State",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            u32::try_from(manager_state).unwrap(),
+            mapping::nm_state::DISCONNECTED
+        );
+    }
+
+    #[tokio::test]
+    async fn read_only_refuses_connection_update() {
+        let (shared, _server, client) = test_support::served_fake_state().await;
+        shared.write().await.config.read_only = true;
+
+        let settings = HashMap::from([(
+            "connection".to_string(),
+            HashMap::from([("id".to_string(), Value::new("uplink"))]),
+        )]);
+        let result = client
+            .call_method(
+                Some("org.freedesktop.NetworkManager"),
+                "/org/freedesktop/NetworkManager/Settings/2",
+                Some("org.freedesktop.NetworkManager.Settings.Connection"),
+                "Update",
+                &(settings,),
+            )
+            .await;
+        assert!(matches!(
+            result.map_err(zbus::fdo::Error::from),
+            Err(zbus::fdo::Error::AccessDenied(_))
+        ));
+        assert!(shared.read().await.profiles.is_empty());
+
+        let properties = zbus::fdo::PropertiesProxy::builder(&client)
+            .destination("org.freedesktop.NetworkManager")
+            .unwrap()
+            .path("/org/freedesktop/NetworkManager/Settings")
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let can_modify = properties
+            .get(
+                "org.freedesktop.NetworkManager.Settings"
+                    .try_into()
+                    .unwrap(),
+                "CanModify",
+            )
+            .await
+            .unwrap();
+        assert!(!bool::try_from(can_modify).unwrap());
+    }
+
+    fn ipv4_settings(
+        method: &str,
+        address_data: Option<Vec<(&str, u32)>>,
+    ) -> HashMap<String, HashMap<String, Value<'static>>> {
+        let mut ipv4 = HashMap::new();
+        ipv4.insert("method".to_string(), Value::new(method.to_string()));
+        if let Some(entries) = address_data {
+            let entries: Vec<HashMap<String, Value<'static>>> = entries
+                .into_iter()
+                .map(|(address, prefix)| {
+                    HashMap::from([
+                        ("address".to_string(), Value::new(address.to_string())),
+                        ("prefix".to_string(), Value::new(prefix)),
+                    ])
+                })
+                .collect();
+            ipv4.insert("address-data".to_string(), Value::new(entries));
+        }
+        HashMap::from([("ipv4".to_string(), ipv4)])
+    }
+
+    #[tokio::test]
+    async fn ignored_device_refuses_autoconnect_change() {
+        let (shared, _server, client) = test_support::served_fake_state().await;
+        shared.write().await.devices.get_mut(&2).unwrap().ignored = true;
+
+        let properties = zbus::fdo::PropertiesProxy::builder(&client)
+            .destination("org.freedesktop.NetworkManager")
+            .unwrap()
+            .path("/org/freedesktop/NetworkManager/Devices/2")
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let result = properties
+            .set(
+                "org.freedesktop.NetworkManager.Device".try_into().unwrap(),
+                "Autoconnect",
+                Value::new(false),
+            )
+            .await;
+        assert!(matches!(result, Err(zbus::fdo::Error::NotSupported(_))));
+        assert!(
+            shared
+                .read()
+                .await
+                .persistent
+                .autoconnect_disabled
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn reapply_checks_gateway_against_requested_addresses() {
+        let (shared, _server, client) = test_support::served_fake_state().await;
+
+        // 192.0.2.1 is on the current subnet but not on the requested one.
+        let mut settings = ipv4_settings("manual", Some(vec![("198.51.100.10", 24)]));
+        settings
+            .get_mut("ipv4")
+            .unwrap()
+            .insert("gateway".to_string(), Value::new("192.0.2.1"));
+        settings.insert(
+            "802-3-ethernet".to_string(),
+            HashMap::from([("mtu".to_string(), Value::new(9000u32))]),
+        );
+        let result = client
+            .call_method(
+                Some("org.freedesktop.NetworkManager"),
+                "/org/freedesktop/NetworkManager/Devices/2",
+                Some("org.freedesktop.NetworkManager.Device"),
+                "Reapply",
+                &(settings, 0u64, 0u32),
+            )
+            .await;
+        assert!(matches!(
+            result.map_err(zbus::fdo::Error::from),
+            Err(zbus::fdo::Error::InvalidArgs(_))
+        ));
+        assert_eq!(shared.read().await.devices[&2].mtu, 1500);
+    }
+
+    #[test]
+    fn manual_addresses_are_parsed() {
+        let settings = ipv4_settings(
+            "manual",
+            Some(vec![("192.0.2.10", 24), ("198.51.100.1", 32)]),
+        );
+        assert_eq!(
+            settings_ipv4_addresses(&settings).unwrap(),
+            Some(vec![
+                (Ipv4Addr::new(192, 0, 2, 10), 24),
+                (Ipv4Addr::new(198, 51, 100, 1), 32),
+            ])
+        );
+    }
+
+    #[test]
+    fn manual_without_address_data_requests_nothing() {
+        let settings = ipv4_settings("manual", None);
+        assert_eq!(settings_ipv4_addresses(&settings).unwrap(), None);
+    }
+
+    #[test]
+    fn non_manual_method_requests_nothing() {
+        let settings = ipv4_settings("auto", Some(vec![("192.0.2.10", 24)]));
+        assert_eq!(settings_ipv4_addresses(&settings).unwrap(), None);
+        assert_eq!(settings_ipv4_addresses(&HashMap::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn bad_address_data_entry_rejects_everything() {
+        for bad in [("192.0.2.300", 24), ("2001:db8::1", 64), ("192.0.2.10", 33)] {
+            let settings = ipv4_settings("manual", Some(vec![("192.0.2.10", 24), bad]));
+            assert!(
+                matches!(
+                    settings_ipv4_addresses(&settings),
+                    Err(zbus::fdo::Error::InvalidArgs(_))
+                ),
+                "{bad:?} accepted"
+            );
+        }
+    }
+}
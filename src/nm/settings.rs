@@ -2,18 +2,25 @@ use zbus::zvariant::OwnedObjectPath;
 
 use crate::state;
 
+use super::PropertyAudit;
+
 pub struct NmSettings {
     pub state: state::SharedState,
 }
 
+impl PropertyAudit for NmSettings {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Settings";
+}
+
 #[zbus::interface(name = "org.freedesktop.NetworkManager.Settings")]
 impl NmSettings {
     async fn list_connections(&self) -> Vec<OwnedObjectPath> {
         let state = self.state.read().await;
         state
             .devices
-            .keys()
-            .map(|&idx| self::state::settings_path(idx))
+            .values()
+            .filter(|d| !d.ignored)
+            .map(|d| self::state::settings_path(d.ifindex))
             .collect()
     }
 
@@ -23,19 +30,25 @@ impl NmSettings {
 
     #[zbus(property)]
     async fn connections(&self) -> Vec<OwnedObjectPath> {
-        self.list_connections().await
+        self.audit("Connections", self.list_connections().await)
     }
 
+    /// Connections can be edited with `Settings.Connection.Update` unless
+    /// nmlinkd runs in read-only mode.
     #[zbus(property)]
-    fn can_modify(&self) -> bool {
-        false
+    async fn can_modify(&self) -> bool {
+        let read_only = self.state.read().await.config.read_only;
+        self.audit("CanModify", !read_only)
     }
 
     #[zbus(property)]
     async fn hostname(&self) -> String {
-        tokio::fs::read_to_string("/etc/hostname")
-            .await
-            .map(|s| s.trim().to_string())
-            .unwrap_or_default()
+        self.audit(
+            "Hostname",
+            tokio::fs::read_to_string("/etc/hostname")
+                .await
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default(),
+        )
     }
 }
@@ -1,57 +1,314 @@
 use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+use tracing::warn;
+use zbus::Connection;
+use zbus::message::Header;
+use zbus::object_server::SignalEmitter;
 use zbus::zvariant::Value;
 
 use crate::mapping::{self, nm_device_type};
-use crate::state::{self, SharedState, SharedStateExt};
+use crate::nm::signals;
+use crate::persistence;
+use crate::state::{self, AddrInfo, SharedState, SharedStateExt, TunnelInfo};
+
+use super::PropertyAudit;
+
+/// "manual" when every IPv4 address is permanent (static), "auto" otherwise.
+fn ipv4_method(addrs: &[AddrInfo<Ipv4Addr>]) -> &'static str {
+    if !addrs.is_empty() && addrs.iter().all(|a| a.is_permanent) {
+        "manual"
+    } else {
+        "auto"
+    }
+}
 
 pub struct NmSettingsConnection {
     pub ifindex: i32,
     pub state: SharedState,
 }
 
+impl PropertyAudit for NmSettingsConnection {
+    const IFACE: &'static str = "org.freedesktop.NetworkManager.Settings.Connection";
+
+    fn audit_ifindex(&self) -> Option<i32> {
+        Some(self.ifindex)
+    }
+}
+
+/// Everything `get_settings` needs, captured under a single read lock.
+struct ConnectionSnapshot {
+    iface_name: String,
+    id: String,
+    autoconnect_priority: i32,
+    device_type: u32,
+    /// A WireGuard link, not just a `vpn_interfaces` entry typed as one.
+    wireguard: bool,
+    /// VLAN id and parent interface name.
+    vlan: Option<(u16, Option<String>)>,
+    tunnel: Option<TunnelInfo>,
+    ipv4_method: &'static str,
+}
+
 #[zbus::interface(name = "org.freedesktop.NetworkManager.Settings.Connection")]
 impl NmSettingsConnection {
     async fn get_settings(&self) -> HashMap<String, HashMap<String, Value<'_>>> {
         let mut settings = HashMap::new();
         let mut connection = HashMap::new();
-        let (iface_name, device_type) = self
+        let snap = self
             .state
-            .with_device(self.ifindex, |d| (d.name.clone(), d.device_type))
+            .with_state(|s| {
+                let dev = s.devices.get(&self.ifindex)?;
+                let vlan = dev.vlan.as_ref().map(|v| {
+                    let parent = v
+                        .parent
+                        .and_then(|idx| s.devices.get(&idx))
+                        .map(|p| p.name.clone());
+                    (v.id, parent)
+                });
+                Some(ConnectionSnapshot {
+                    iface_name: dev.name.clone(),
+                    id: s.connection_id(dev),
+                    autoconnect_priority: s.autoconnect_priority(dev),
+                    device_type: dev.device_type,
+                    wireguard: dev.type_description == "wireguard",
+                    vlan,
+                    tunnel: dev.tunnel.clone(),
+                    ipv4_method: ipv4_method(&dev.ipv4_addrs),
+                })
+            })
             .await
-            .unwrap_or_else(|| (format!("eth{}", self.ifindex), nm_device_type::ETHERNET));
+            .unwrap_or_else(|| {
+                let iface_name = format!("eth{}", self.ifindex);
+                ConnectionSnapshot {
+                    id: iface_name.clone(),
+                    iface_name,
+                    autoconnect_priority: 0,
+                    device_type: nm_device_type::ETHERNET,
+                    wireguard: false,
+                    vlan: None,
+                    tunnel: None,
+                    ipv4_method: "auto",
+                }
+            });
 
-        let conn_type = mapping::device_type_to_connection_type(device_type);
+        let conn_type = mapping::device_type_to_connection_type(snap.device_type);
 
-        let uuid = state::connection_uuid(&iface_name);
-        connection.insert("id".to_string(), Value::new(iface_name.clone()));
+        let uuid = state::connection_uuid(&snap.iface_name);
+        connection.insert("id".to_string(), Value::new(snap.id));
         connection.insert("uuid".to_string(), Value::new(uuid));
         connection.insert("type".to_string(), Value::new(conn_type));
-        connection.insert("interface-name".to_string(), Value::new(iface_name));
+        connection.insert("interface-name".to_string(), Value::new(snap.iface_name));
+        connection.insert(
+            "autoconnect-priority".to_string(),
+            Value::new(snap.autoconnect_priority),
+        );
 
         settings.insert("connection".to_string(), connection);
 
+        let mut ipv4 = HashMap::new();
+        ipv4.insert("method".to_string(), Value::new(snap.ipv4_method));
+        settings.insert("ipv4".to_string(), ipv4);
+
         // Empty 802-3-ethernet section — required for libnm's
         // nm_device_filter_connections() to consider this connection
         // compatible with an ethernet device.
-        if device_type != nm_device_type::WIREGUARD {
-            settings.insert("802-3-ethernet".to_string(), HashMap::new());
+        match snap.device_type {
+            // libnm only accepts a wireguard connection with this section.
+            // Keys and peers aren't readable here: no secrets, no peers.
+            nm_device_type::WIREGUARD if snap.wireguard => {
+                let mut wireguard_section = HashMap::new();
+                wireguard_section.insert(
+                    "private-key-flags".to_string(),
+                    Value::new(4u32), // NM_SETTING_SECRET_FLAG_NOT_REQUIRED
+                );
+                wireguard_section.insert(
+                    "peers".to_string(),
+                    Value::new(Vec::<HashMap<String, Value<'_>>>::new()),
+                );
+                settings.insert("wireguard".to_string(), wireguard_section);
+            }
+            nm_device_type::WIREGUARD => {}
+            nm_device_type::VLAN => {
+                let mut vlan_section = HashMap::new();
+                if let Some((id, parent)) = snap.vlan {
+                    vlan_section.insert("id".to_string(), Value::new(id as u32));
+                    if let Some(parent) = parent {
+                        vlan_section.insert("parent".to_string(), Value::new(parent));
+                    }
+                }
+                settings.insert("vlan".to_string(), vlan_section);
+            }
+            nm_device_type::MODEM => {
+                settings.insert("gsm".to_string(), HashMap::new());
+            }
+            nm_device_type::IP_TUNNEL => {
+                let mut tunnel_section = HashMap::new();
+                if let Some(tunnel) = snap.tunnel {
+                    tunnel_section.insert("mode".to_string(), Value::new(tunnel.mode));
+                    if let Some(local) = tunnel.local {
+                        tunnel_section.insert("local".to_string(), Value::new(local.to_string()));
+                    }
+                    if let Some(remote) = tunnel.remote {
+                        tunnel_section.insert("remote".to_string(), Value::new(remote.to_string()));
+                    }
+                }
+                settings.insert("ip-tunnel".to_string(), tunnel_section);
+            }
+            t if mapping::is_ethernet_like(t) => {
+                settings.insert("802-3-ethernet".to_string(), HashMap::new());
+            }
+            _ => {
+                settings.insert("generic".to_string(), HashMap::new());
+            }
         }
 
         settings
     }
 
+    /// Store the user-facing parts of a connection profile. Only
+    /// `connection.id`, `connection.autoconnect-priority`, the ethernet MTU
+    /// and static IPv4 addresses and gateway are honoured, the latter two
+    /// dropped again by a non-"manual" `ipv4.method`; an empty id
+    /// reverts to the interface alias or name. Restricted to root and
+    /// nmlinkd's own user, refused in read-only mode and for ignored or
+    /// unmanaged devices.
+    async fn update(
+        &self,
+        properties: HashMap<String, HashMap<String, Value<'_>>>,
+        #[zbus(connection)] conn: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let live = super::LiveSettings::from_settings(&properties)?;
+        // Everything below is persisted and re-applied as root at the next
+        // start: refuse before anything is applied or stored.
+        super::ensure_privileged_caller(conn, &header).await?;
+        {
+            let state = self.state.read().await;
+            state.ensure_writable()?;
+            state.ensure_controllable(self.ifindex)?;
+        }
+        super::apply_live_settings(conn, &self.state, self.ifindex, &live).await?;
+        // Switching away from "manual" drops the stored static settings so
+        // they are not restored at the next start.
+        let ipv4_static_cleared =
+            super::settings_ipv4_method(&properties).is_some_and(|m| m != "manual");
+        let super::LiveSettings {
+            mtu,
+            ipv4_addresses,
+            ipv4_gateway,
+        } = live;
+
+        let new_id = properties
+            .get("connection")
+            .and_then(|c| c.get("id"))
+            .and_then(|v| <&str>::try_from(v).ok())
+            .map(str::to_string);
+        let new_priority = properties
+            .get("connection")
+            .and_then(|c| c.get("autoconnect-priority"))
+            .and_then(|v| i32::try_from(v).ok());
+
+        let (id, activation_id, primary_changed, stored) = {
+            let mut state = self.state.write().await;
+            state.ensure_writable()?;
+            state.ensure_controllable(self.ifindex)?;
+            let Some(dev) = state.devices.get(&self.ifindex) else {
+                return Err(zbus::fdo::Error::UnknownObject(format!(
+                    "No device for ifindex {}",
+                    self.ifindex
+                )));
+            };
+            let iface_name = dev.name.clone();
+            let old_primary = state.primary_device().map(|d| d.ifindex);
+            let profile = state.profiles.entry(iface_name.clone()).or_default();
+            if let Some(new_id) = new_id {
+                profile.id = (!new_id.is_empty()).then_some(new_id);
+            }
+            if let Some(priority) = new_priority {
+                profile.autoconnect_priority = priority;
+            }
+            if mtu.is_some() {
+                profile.mtu = mtu;
+            }
+            if ipv4_static_cleared {
+                profile.ipv4_addresses = None;
+                profile.ipv4_gateway = None;
+            }
+            if ipv4_addresses.is_some() {
+                profile.ipv4_addresses = ipv4_addresses;
+            }
+            if ipv4_gateway.is_some() {
+                profile.ipv4_gateway = ipv4_gateway;
+            }
+            let stored = (iface_name, profile.clone());
+            let primary_changed = state.primary_device().map(|d| d.ifindex) != old_primary;
+            let dev = &state.devices[&self.ifindex];
+            (
+                state.connection_id(dev),
+                dev.activation_id,
+                primary_changed,
+                stored,
+            )
+        };
+
+        let (iface_name, profile) = stored;
+        if let Err(e) = persistence::save(&iface_name, &profile).await {
+            warn!(iface = %iface_name, "failed to persist connection profile: {e}");
+        }
+
+        if let Err(e) = Self::updated(&emitter).await {
+            warn!("failed to emit Settings.Connection.Updated: {e}");
+        }
+        signals::notify_active_connection_id_changed(conn, activation_id, &id).await;
+        if primary_changed {
+            signals::notify_global_state_changed(conn, &self.state).await;
+        }
+        Ok(())
+    }
+
+    /// nmlinkd stores no secrets: always an empty settings map.
+    async fn get_secrets(
+        &self,
+        _setting_name: &str,
+    ) -> HashMap<String, HashMap<String, Value<'_>>> {
+        HashMap::new()
+    }
+
+    /// Profiles are saved as part of `Update`, so there is nothing left to
+    /// write; succeeds so connection editors can finish.
+    async fn save(&self) {}
+
+    /// nmlinkd stores no secrets: nothing to clear.
+    async fn clear_secrets(&self) {}
+
+    /// Not part of NetworkManager's API, accepted for clients that call it
+    /// anyway. nmlinkd stores no secrets: nothing to save.
+    async fn save_secrets(&self) {}
+
+    #[zbus(signal)]
+    async fn updated(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
     #[zbus(property)]
     fn unsaved(&self) -> bool {
-        false
+        self.audit("Unsaved", false)
     }
 
     #[zbus(property)]
     fn flags(&self) -> u32 {
-        0 // NM_SETTINGS_CONNECTION_FLAG_NONE
+        self.audit(
+            "Flags", 0, // NM_SETTINGS_CONNECTION_FLAG_NONE
+        )
     }
 
     #[zbus(property)]
-    fn filename(&self) -> String {
-        String::new()
+    async fn filename(&self) -> String {
+        let iface_name = self
+            .state
+            .with_device(self.ifindex, |d| d.name.clone())
+            .await
+            .unwrap_or_else(|| format!("eth{}", self.ifindex));
+        self.audit("Filename", state::connection_filename(&iface_name))
     }
 }
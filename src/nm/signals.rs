@@ -1,18 +1,26 @@
 use std::collections::HashMap;
 
+use tokio::time::Instant;
 use tracing::warn;
 use zbus::Connection;
 use zbus::zvariant::{ObjectPath, OwnedObjectPath, Value};
 
 use crate::mapping::{
-    nm_active_connection_state, nm_active_connection_state_reason, nm_device_state,
+    self, nm_active_connection_state, nm_active_connection_state_reason, nm_device_state,
     nm_device_state_reason,
 };
-use crate::state::{self, SharedState};
+use crate::state::{self, EmittedState, SharedState};
 
 const NM_IFACE: &str = "org.freedesktop.NetworkManager";
 const NM_DEVICE_IFACE: &str = "org.freedesktop.NetworkManager.Device";
 const NM_AC_IFACE: &str = "org.freedesktop.NetworkManager.Connection.Active";
+const NM_IP4_IFACE: &str = "org.freedesktop.NetworkManager.IP4Config";
+const NM_IP6_IFACE: &str = "org.freedesktop.NetworkManager.IP6Config";
+const NM_WIRED_IFACE: &str = "org.freedesktop.NetworkManager.Device.Wired";
+const NM_STATISTICS_IFACE: &str = "org.freedesktop.NetworkManager.Device.Statistics";
+const NM_BOND_IFACE: &str = "org.freedesktop.NetworkManager.Device.Bond";
+const NM_DNS_MANAGER_IFACE: &str = "org.freedesktop.NetworkManager.DnsManager";
+const NM_SETTINGS_IFACE: &str = "org.freedesktop.NetworkManager.Settings";
 
 /// Emit a PropertiesChanged signal with a mix of changed and invalidated properties.
 async fn emit_properties_changed(
@@ -22,16 +30,17 @@ async fn emit_properties_changed(
     changed: HashMap<&str, Value<'_>>,
     invalidated: &[&str],
 ) {
-    let Some(sender) = conn.unique_name() else {
-        warn!("no unique name on connection, cannot emit PropertiesChanged");
-        return;
-    };
+    // A peer-to-peer connection has no unique name; the signal goes out
+    // without a sender there.
     let Ok(msg) = zbus::message::Message::signal(
         path,
         "org.freedesktop.DBus.Properties",
         "PropertiesChanged",
     )
-    .and_then(|b| b.sender(sender))
+    .and_then(|b| match conn.unique_name() {
+        Some(sender) => b.sender(sender),
+        None => Ok(b),
+    })
     .and_then(|b| b.build(&(interface, changed, invalidated))) else {
         warn!("failed to build PropertiesChanged message");
         return;
@@ -43,12 +52,11 @@ async fn emit_properties_changed(
 }
 
 /// Notify D-Bus clients that the global NM state changed.
-/// Emits PropertiesChanged + StateChanged signal on the Manager.
-pub async fn notify_global_state_changed(
-    nm_conn: &Connection,
-    shared: &SharedState,
-    new_global_state: u32,
-) {
+/// Emits PropertiesChanged + StateChanged signal on the Manager. State,
+/// Connectivity, ActiveConnections and PrimaryConnection are read under one
+/// lock, so they always agree with each other, even if state moved on since
+/// the caller decided to notify.
+pub async fn notify_global_state_changed(nm_conn: &Connection, shared: &SharedState) {
     let Ok(path) = ObjectPath::try_from("/org/freedesktop/NetworkManager") else {
         return;
     };
@@ -58,21 +66,18 @@ pub async fn notify_global_state_changed(
         .interface::<_, super::manager::NmManager>(path.clone())
         .await;
 
-    let (connectivity, active_connections, primary_connection) = {
+    let (new_global_state, connectivity, active_connections, primary_connection) = {
         let st = shared.read().await;
-        let ac: Vec<OwnedObjectPath> = st
-            .devices
-            .values()
-            .filter(|d| d.nm_state == crate::mapping::nm_device_state::ACTIVATED)
-            .map(|d| state::active_connection_path(d.ifindex))
-            .collect();
         let primary: OwnedObjectPath = st
-            .devices
-            .values()
-            .find(|d| d.nm_state == crate::mapping::nm_device_state::ACTIVATED && d.has_gateway())
-            .map(|d| state::active_connection_path(d.ifindex))
+            .primary_device()
+            .map(|d| state::active_connection_path(d.activation_id))
             .unwrap_or_else(state::root_path);
-        (st.connectivity, ac, primary)
+        (
+            st.global_state,
+            st.connectivity,
+            st.active_connection_paths(),
+            primary,
+        )
     };
 
     let mut changed: HashMap<&str, Value> = HashMap::new();
@@ -93,9 +98,44 @@ pub async fn notify_global_state_changed(
     }
 }
 
+/// Tell clients to re-read GetPermissions (e.g. after `read_only` changed).
+pub async fn notify_check_permissions(nm_conn: &Connection) {
+    if let Ok(iface) = nm_conn
+        .object_server()
+        .interface::<_, super::manager::NmManager>("/org/freedesktop/NetworkManager")
+        .await
+        && let Err(e) = super::manager::NmManager::check_permissions(iface.signal_emitter()).await
+    {
+        warn!("failed to emit Manager.CheckPermissions: {e}");
+    }
+}
+
+/// Notify D-Bus clients that `Settings.CanModify` changed (`read_only`
+/// toggled by a config reload).
+pub async fn notify_settings_can_modify_changed(nm_conn: &Connection, can_modify: bool) {
+    if let Ok(path) = ObjectPath::try_from("/org/freedesktop/NetworkManager/Settings") {
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        changed.insert("CanModify", Value::Bool(can_modify));
+        emit_properties_changed(nm_conn, path, NM_SETTINGS_IFACE, changed, &[]).await;
+    }
+}
+
+/// Notify D-Bus clients that the connectivity state changed (probe result).
+pub async fn notify_connectivity_changed(nm_conn: &Connection, connectivity: u32) {
+    let Ok(path) = ObjectPath::try_from("/org/freedesktop/NetworkManager") else {
+        return;
+    };
+    let mut changed: HashMap<&str, Value> = HashMap::new();
+    changed.insert("Connectivity", Value::U32(connectivity));
+    emit_properties_changed(nm_conn, path, NM_IFACE, changed, &[]).await;
+}
+
 /// Notify D-Bus clients that a device's state changed.
 /// Emits PropertiesChanged + StateChanged signals on Device and ActiveConnection.
-/// Checks `user_disconnect_pending` to send reason=39 (USER_REQUESTED) when appropriate.
+/// Checks `user_disconnect_pending` to send reason=39 (USER_REQUESTED) when appropriate:
+/// the flag is reported on DEACTIVATING and consumed by the final downward transition.
+/// Other downward transitions report reason=40 (CARRIER) if the link lost its
+/// carrier while administratively up, and NONE if it was set down.
 pub async fn notify_device_state_changed(
     nm_conn: &Connection,
     shared: &SharedState,
@@ -103,23 +143,57 @@ pub async fn notify_device_state_changed(
     new_state: u32,
     old_state: u32,
 ) {
+    let activation_id = {
+        let mut state = shared.write().await;
+        state.emitted_states.insert(
+            ifindex,
+            EmittedState {
+                state: new_state,
+                previous: old_state,
+                at: Instant::now(),
+            },
+        );
+        state.devices.get(&ifindex).map_or(0, |d| d.activation_id)
+    };
+
     // Consume user-requested flag if transitioning to a disconnected state
-    let reason = if new_state < old_state {
+    let reason = if new_state == nm_device_state::DEACTIVATING {
+        if shared
+            .read()
+            .await
+            .user_disconnect_pending
+            .contains(&ifindex)
+        {
+            nm_device_state_reason::USER_REQUESTED
+        } else {
+            nm_device_state_reason::NONE
+        }
+    } else if new_state < old_state {
         let mut state = shared.write().await;
         if state.user_disconnect_pending.remove(&ifindex) {
             nm_device_state_reason::USER_REQUESTED
+        } else if state.devices.get(&ifindex).is_some_and(|d| d.carrier_lost) {
+            nm_device_state_reason::CARRIER
         } else {
             nm_device_state_reason::NONE
         }
+    } else if new_state == nm_device_state::FAILED
+        && shared
+            .write()
+            .await
+            .activation_failed_pending
+            .remove(&ifindex)
+    {
+        nm_device_state_reason::IP_CONFIG_UNAVAILABLE
     } else {
         nm_device_state_reason::NONE
     };
 
     let dev_path = state::device_path(ifindex);
-    let ac_path = state::active_connection_path(ifindex);
+    let ac_path = state::active_connection_path(activation_id);
 
-    let active_conn_path = if new_state >= nm_device_state::ACTIVATED {
-        state::active_connection_path(ifindex)
+    let active_conn_path = if mapping::has_active_connection(new_state) {
+        ac_path.clone()
     } else {
         state::root_path()
     };
@@ -150,16 +224,8 @@ pub async fn notify_device_state_changed(
         warn!("failed to emit Device.StateChanged: {e}");
     }
 
-    let ac_state = if new_state >= nm_device_state::ACTIVATED {
-        nm_active_connection_state::ACTIVATED
-    } else {
-        nm_active_connection_state::DEACTIVATED
-    };
-    let old_ac_state = if old_state >= nm_device_state::ACTIVATED {
-        nm_active_connection_state::ACTIVATED
-    } else {
-        nm_active_connection_state::DEACTIVATED
-    };
+    let ac_state = mapping::device_state_to_ac_state(new_state);
+    let old_ac_state = mapping::device_state_to_ac_state(old_state);
 
     // ActiveConnection uses a different reason enum than Device
     let ac_reason = if reason == nm_device_state_reason::USER_REQUESTED {
@@ -190,11 +256,18 @@ pub async fn notify_device_state_changed(
         changed.insert("State", Value::U32(ac_state));
         emit_properties_changed(nm_conn, path, NM_AC_IFACE, changed, &[]).await;
     }
+
+    if ac_state == nm_active_connection_state::DEACTIVATED
+        && old_ac_state != nm_active_connection_state::DEACTIVATED
+    {
+        super::renew_active_connection(nm_conn, shared, ifindex).await;
+    }
 }
 
 /// Notify D-Bus clients that IP config changed on a device.
 /// Emits PropertiesChanged on the Device with Ip4Config/Ip6Config paths,
-/// which triggers networkmanager-qt to invalidate its cache and re-read.
+/// which triggers networkmanager-qt to invalidate its cache and re-read,
+/// and invalidates the legacy Ip4Address.
 pub async fn notify_device_ip_config_changed(nm_conn: &Connection, ifindex: i32) {
     let dev_path = state::device_path(ifindex);
     if let Ok(path) = ObjectPath::try_from(dev_path.as_str()) {
@@ -207,6 +280,233 @@ pub async fn notify_device_ip_config_changed(nm_conn: &Connection, ifindex: i32)
             "Ip6Config",
             Value::ObjectPath(state::ip6_config_path(ifindex).into()),
         );
+        emit_properties_changed(nm_conn, path, NM_DEVICE_IFACE, changed, &["Ip4Address"]).await;
+    }
+}
+
+/// Invalidate the nameserver properties of every device's IP4Config and
+/// IP6Config after resolv.conf or the per-link servers changed.
+pub async fn notify_nameservers_changed(nm_conn: &Connection, ifindexes: &[i32]) {
+    if let Ok(path) = ObjectPath::try_from("/org/freedesktop/NetworkManager/DnsManager") {
+        emit_properties_changed(
+            nm_conn,
+            path,
+            NM_DNS_MANAGER_IFACE,
+            HashMap::new(),
+            &[
+                "Mode",
+                "Configuration",
+                "ResolvConfPath",
+                "ResolvConfOptions",
+            ],
+        )
+        .await;
+    }
+    for &ifindex in ifindexes {
+        let ip4_path = state::ip4_config_path(ifindex);
+        if let Ok(path) = ObjectPath::try_from(ip4_path.as_str()) {
+            emit_properties_changed(
+                nm_conn,
+                path,
+                NM_IP4_IFACE,
+                HashMap::new(),
+                &["NameserverData", "Domains"],
+            )
+            .await;
+        }
+        let ip6_path = state::ip6_config_path(ifindex);
+        if let Ok(path) = ObjectPath::try_from(ip6_path.as_str()) {
+            emit_properties_changed(
+                nm_conn,
+                path,
+                NM_IP6_IFACE,
+                HashMap::new(),
+                &["Nameservers", "NameserverData", "Domains"],
+            )
+            .await;
+        }
+    }
+}
+
+/// Invalidate IP4Config.AddressData after the device's addresses changed.
+/// The gateway follows routes and the nameservers resolv.conf, so neither is
+/// touched here.
+pub async fn notify_ip4_config_changed(nm_conn: &Connection, ifindex: i32) {
+    let ip4_path = state::ip4_config_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(ip4_path.as_str()) {
+        emit_properties_changed(
+            nm_conn,
+            path,
+            NM_IP4_IFACE,
+            HashMap::new(),
+            &["AddressData"],
+        )
+        .await;
+    }
+}
+
+/// Invalidate the IP6Config properties that change with addresses and router
+/// advertisements, so clients re-read them instead of trusting their cache.
+pub async fn notify_ip6_config_changed(nm_conn: &Connection, ifindex: i32) {
+    let ip6_path = state::ip6_config_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(ip6_path.as_str()) {
+        emit_properties_changed(
+            nm_conn,
+            path,
+            NM_IP6_IFACE,
+            HashMap::new(),
+            &["AddressData", "Gateway", "Domains"],
+        )
+        .await;
+    }
+}
+
+/// Notify D-Bus clients that an active connection's user-visible id changed.
+/// The UUID follows the interface name and stays as it is.
+pub async fn notify_active_connection_id_changed(
+    nm_conn: &Connection,
+    activation_id: i32,
+    id: &str,
+) {
+    let ac_path = state::active_connection_path(activation_id);
+    if let Ok(path) = ObjectPath::try_from(ac_path.as_str()) {
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        changed.insert("Id", Value::from(id));
+        emit_properties_changed(nm_conn, path, NM_AC_IFACE, changed, &[]).await;
+    }
+}
+
+/// Notify D-Bus clients that a device's interface flags (UP/LOWER_UP/CARRIER) changed.
+pub async fn notify_device_interface_flags_changed(
+    nm_conn: &Connection,
+    ifindex: i32,
+    interface_flags: u32,
+) {
+    let dev_path = state::device_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(dev_path.as_str()) {
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        changed.insert("InterfaceFlags", Value::U32(interface_flags));
+        emit_properties_changed(nm_conn, path, NM_DEVICE_IFACE, changed, &[]).await;
+    }
+}
+
+/// Notify D-Bus clients that a device's MTU changed.
+pub async fn notify_device_mtu_changed(nm_conn: &Connection, ifindex: i32, mtu: u32) {
+    let dev_path = state::device_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(dev_path.as_str()) {
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        changed.insert("Mtu", Value::U32(mtu));
+        emit_properties_changed(nm_conn, path, NM_DEVICE_IFACE, changed, &[]).await;
+    }
+}
+
+/// Notify D-Bus clients that a device's interface alias changed.
+pub async fn notify_device_alias_changed(nm_conn: &Connection, ifindex: i32, alias: Option<&str>) {
+    let dev_path = state::device_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(dev_path.as_str()) {
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        changed.insert("Alias", Value::from(alias.unwrap_or_default()));
+        emit_properties_changed(nm_conn, path, NM_DEVICE_IFACE, changed, &[]).await;
+    }
+}
+
+/// Notify D-Bus clients that a wired device's negotiated speed or duplex
+/// changed.
+pub async fn notify_device_speed_changed(
+    nm_conn: &Connection,
+    ifindex: i32,
+    speed: u32,
+    duplex: &str,
+) {
+    let dev_path = state::device_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(dev_path.as_str()) {
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        changed.insert("Speed", Value::U32(speed));
+        changed.insert("Duplex", Value::from(duplex));
+        emit_properties_changed(nm_conn, path, NM_WIRED_IFACE, changed, &[]).await;
+    }
+}
+
+/// Notify D-Bus clients of fresh byte counters.
+pub async fn notify_device_statistics_changed(
+    nm_conn: &Connection,
+    ifindex: i32,
+    rx_bytes: u64,
+    tx_bytes: u64,
+) {
+    let dev_path = state::device_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(dev_path.as_str()) {
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        changed.insert("RxBytes", Value::U64(rx_bytes));
+        changed.insert("TxBytes", Value::U64(tx_bytes));
+        emit_properties_changed(nm_conn, path, NM_STATISTICS_IFACE, changed, &[]).await;
+    }
+}
+
+/// Invalidate a bond's or bridge's Ports after a link joined or left it,
+/// and a bond's Slaves.
+pub async fn notify_device_ports_changed(nm_conn: &Connection, ifindex: i32, is_bond: bool) {
+    let dev_path = state::device_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(dev_path.as_str()) {
+        emit_properties_changed(
+            nm_conn,
+            path.clone(),
+            NM_DEVICE_IFACE,
+            HashMap::new(),
+            &["Ports"],
+        )
+        .await;
+        if is_bond {
+            emit_properties_changed(nm_conn, path, NM_BOND_IFACE, HashMap::new(), &["Slaves"])
+                .await;
+        }
+    }
+}
+
+/// Invalidate Controller and Master on a port's active connection after it
+/// joined or left a bond or bridge.
+pub async fn notify_active_connection_controller_changed(nm_conn: &Connection, activation_id: i32) {
+    let ac_path = state::active_connection_path(activation_id);
+    if let Ok(path) = ObjectPath::try_from(ac_path.as_str()) {
+        emit_properties_changed(
+            nm_conn,
+            path,
+            NM_AC_IFACE,
+            HashMap::new(),
+            &["Controller", "Master"],
+        )
+        .await;
+    }
+}
+
+/// Notify D-Bus clients that a bond failed over to another slave (or has
+/// none active).
+pub async fn notify_bond_active_slave_changed(
+    nm_conn: &Connection,
+    ifindex: i32,
+    active_slave: Option<i32>,
+) {
+    let dev_path = state::device_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(dev_path.as_str()) {
+        let slave = active_slave.map_or_else(state::root_path, state::device_path);
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        changed.insert("ActiveSlave", Value::ObjectPath(slave.into()));
+        emit_properties_changed(nm_conn, path, NM_BOND_IFACE, changed, &[]).await;
+    }
+}
+
+/// Notify D-Bus clients that a device's per-family connectivity changed.
+pub async fn notify_device_connectivity_changed(
+    nm_conn: &Connection,
+    ifindex: i32,
+    ip4_connectivity: u32,
+    ip6_connectivity: u32,
+) {
+    let dev_path = state::device_path(ifindex);
+    if let Ok(path) = ObjectPath::try_from(dev_path.as_str()) {
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        changed.insert("Ip4Connectivity", Value::U32(ip4_connectivity));
+        changed.insert("Ip6Connectivity", Value::U32(ip6_connectivity));
         emit_properties_changed(nm_conn, path, NM_DEVICE_IFACE, changed, &[]).await;
     }
 }
@@ -221,12 +521,27 @@ pub async fn notify_device_added(nm_conn: &Connection, ifindex: i32) {
             .interface::<_, super::manager::NmManager>(path)
             .await
         && let Err(e) =
-            super::manager::NmManager::device_added(iface.signal_emitter(), dev_path.into()).await
+            super::manager::NmManager::device_added(iface.signal_emitter(), dev_path).await
     {
         warn!("failed to emit Manager.DeviceAdded: {e}");
     }
 }
 
+/// Invalidate Manager.Devices and AllDevices after hotplug, for clients that
+/// read them as properties instead of following DeviceAdded/DeviceRemoved.
+pub async fn notify_devices_changed(nm_conn: &Connection) {
+    if let Ok(path) = ObjectPath::try_from("/org/freedesktop/NetworkManager") {
+        emit_properties_changed(
+            nm_conn,
+            path,
+            NM_IFACE,
+            HashMap::new(),
+            &["Devices", "AllDevices"],
+        )
+        .await;
+    }
+}
+
 /// Notify D-Bus clients that a device was removed (hotplug).
 pub async fn notify_device_removed(nm_conn: &Connection, ifindex: i32) {
     let dev_path = state::device_path(ifindex);
@@ -237,8 +552,44 @@ pub async fn notify_device_removed(nm_conn: &Connection, ifindex: i32) {
             .interface::<_, super::manager::NmManager>(path)
             .await
         && let Err(e) =
-            super::manager::NmManager::device_removed(iface.signal_emitter(), dev_path.into()).await
+            super::manager::NmManager::device_removed(iface.signal_emitter(), dev_path).await
     {
         warn!("failed to emit Manager.DeviceRemoved: {e}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use zbus::fdo::PropertiesProxy;
+
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn devices_change_invalidates_both_device_lists() {
+        let (_shared, server, client) = test_support::served_fake_state().await;
+        let properties = PropertiesProxy::builder(&client)
+            .destination("org.freedesktop.NetworkManager")
+            .unwrap()
+            .path("/org/freedesktop/NetworkManager")
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let mut changes = properties.receive_properties_changed().await.unwrap();
+
+        notify_devices_changed(&server).await;
+
+        let signal = tokio::time::timeout(std::time::Duration::from_secs(5), changes.next())
+            .await
+            .expect("PropertiesChanged within 5s")
+            .expect("signal stream open");
+        let args = signal.args().unwrap();
+        assert_eq!(args.interface_name().as_str(), NM_IFACE);
+        assert!(args.changed_properties().is_empty());
+        let mut invalidated: Vec<&str> = args.invalidated_properties().iter().copied().collect();
+        invalidated.sort();
+        assert_eq!(invalidated, ["AllDevices", "Devices"]);
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::netlink::queries;
+use crate::state::{AppState, ConnectionProfile, SharedState};
+use crate::state_file;
+
+pub const CONNECTIONS_DIR: &str = "/var/lib/nmlinkd/connections";
+
+/// A profile as stored on disk, in `<interface>.toml`. Addresses are kept in
+/// CIDR notation so the files stay easy to read and edit.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct StoredProfile {
+    id: Option<String>,
+    autoconnect_priority: i32,
+    mtu: Option<u32>,
+    ipv4_addresses: Option<Vec<String>>,
+    ipv4_gateway: Option<Ipv4Addr>,
+}
+
+impl From<&ConnectionProfile> for StoredProfile {
+    fn from(profile: &ConnectionProfile) -> Self {
+        Self {
+            id: profile.id.clone(),
+            autoconnect_priority: profile.autoconnect_priority,
+            mtu: profile.mtu,
+            ipv4_addresses: profile.ipv4_addresses.as_ref().map(|addrs| {
+                addrs
+                    .iter()
+                    .map(|(address, prefix_len)| format!("{address}/{prefix_len}"))
+                    .collect()
+            }),
+            ipv4_gateway: profile.ipv4_gateway,
+        }
+    }
+}
+
+impl TryFrom<StoredProfile> for ConnectionProfile {
+    type Error = String;
+
+    fn try_from(stored: StoredProfile) -> Result<Self, String> {
+        let ipv4_addresses = stored
+            .ipv4_addresses
+            .map(|addrs| addrs.iter().map(|a| parse_cidr(a)).collect())
+            .transpose()?;
+        Ok(Self {
+            id: stored.id,
+            autoconnect_priority: stored.autoconnect_priority,
+            mtu: stored.mtu,
+            ipv4_addresses,
+            ipv4_gateway: stored.ipv4_gateway,
+        })
+    }
+}
+
+fn parse_cidr(s: &str) -> Result<(Ipv4Addr, u8), String> {
+    let (address, prefix_len) = s
+        .split_once('/')
+        .ok_or_else(|| format!("address {s:?} has no prefix length"))?;
+    let address = address
+        .parse()
+        .map_err(|_| format!("invalid address {address:?}"))?;
+    let prefix_len = prefix_len
+        .parse()
+        .ok()
+        .filter(|&p| p <= 32)
+        .ok_or_else(|| format!("invalid prefix length {prefix_len:?}"))?;
+    Ok((address, prefix_len))
+}
+
+/// Load every stored profile, keyed by interface name. A missing directory
+/// yields none; unreadable or malformed files are skipped with a warning.
+pub fn load() -> HashMap<String, ConnectionProfile> {
+    let dir = Path::new(CONNECTIONS_DIR);
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return HashMap::new(),
+        Err(e) => {
+            warn!(path = %dir.display(), "failed to read connections directory: {e}");
+            return HashMap::new();
+        }
+    };
+
+    let mut profiles = HashMap::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "toml") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let profile = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| {
+                toml::from_str::<StoredProfile>(&contents).map_err(|e| e.to_string())
+            })
+            .and_then(ConnectionProfile::try_from);
+        match profile {
+            Ok(profile) => {
+                profiles.insert(name.to_string(), profile);
+            }
+            Err(e) => warn!(path = %path.display(), "skipping malformed connection profile: {e}"),
+        }
+    }
+    if !profiles.is_empty() {
+        info!(count = profiles.len(), "loaded connection profiles");
+    }
+    profiles
+}
+
+/// Write the profile of interface `name` atomically.
+pub async fn save(name: &str, profile: &ConnectionProfile) -> io::Result<()> {
+    let contents = toml::to_string(&StoredProfile::from(profile)).map_err(io::Error::other)?;
+    let path = Path::new(CONNECTIONS_DIR).join(format!("{name}.toml"));
+    state_file::write_atomic(&path, &contents).await
+}
+
+/// A stored profile to re-apply, with the device's current settings.
+#[derive(Debug)]
+struct Reapply {
+    ifindex: i32,
+    mtu: u32,
+    /// Static (permanent) IPv4 addresses.
+    current: Vec<(Ipv4Addr, u8)>,
+    gateway4: Option<Ipv4Addr>,
+    profile: ConnectionProfile,
+}
+
+/// Devices [`reapply`] acts on, with the profile to restore on each.
+fn pending_reapplies(state: &AppState) -> Vec<Reapply> {
+    state
+        .devices
+        .values()
+        .filter(|dev| dev.managed && state.autoconnect(dev))
+        .filter_map(|dev| {
+            let profile = state.profiles.get(&dev.name).filter(|p| {
+                p.mtu.is_some() || p.ipv4_addresses.is_some() || p.ipv4_gateway.is_some()
+            })?;
+            let current = dev
+                .ipv4_addrs
+                .iter()
+                .filter(|a| a.is_permanent)
+                .map(|a| (a.address, a.prefix_len))
+                .collect();
+            Some(Reapply {
+                ifindex: dev.ifindex,
+                mtu: dev.mtu,
+                current,
+                gateway4: dev.gateway4,
+                profile: profile.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Bring the kernel in line with the stored profiles of present, managed
+/// devices with autoconnect on: MTU, static IPv4 addresses and gateway.
+/// Failures are logged and the rest applied; the monitor picks up the
+/// resulting netlink events.
+pub async fn reapply(handle: &rtnetlink::Handle, shared: &SharedState) {
+    let work = {
+        let state = shared.read().await;
+        if state.config.read_only {
+            return;
+        }
+        pending_reapplies(&state)
+    };
+
+    for Reapply {
+        ifindex,
+        mtu,
+        current,
+        gateway4,
+        profile,
+    } in work
+    {
+        let mut failed = None;
+        if let Some(want) = profile.mtu.filter(|&m| m != mtu)
+            && let Err(e) = queries::link_set_mtu(handle, ifindex, want).await
+        {
+            warn!(ifindex, mtu = want, "failed to restore MTU: {e}");
+            failed.get_or_insert("MTU");
+        }
+        if let Some(addrs) = &profile.ipv4_addresses {
+            for &(address, prefix_len) in current.iter().filter(|a| !addrs.contains(a)) {
+                if let Err(e) =
+                    queries::address_del(handle, ifindex, address.into(), prefix_len).await
+                {
+                    warn!(ifindex, %address, "failed to remove stale address: {e}");
+                    failed.get_or_insert("IPv4 addresses");
+                }
+            }
+            for &(address, prefix_len) in addrs.iter().filter(|a| !current.contains(a)) {
+                if let Err(e) =
+                    queries::address_add(handle, ifindex, address.into(), prefix_len).await
+                {
+                    warn!(ifindex, %address, "failed to restore address: {e}");
+                    failed.get_or_insert("IPv4 addresses");
+                }
+            }
+        }
+        if let Some(gateway) = profile.ipv4_gateway.filter(|&gw| gateway4 != Some(gw)) {
+            let result = if gateway4.is_some() {
+                queries::route_replace_default(handle, ifindex, gateway).await
+            } else {
+                queries::route_add_default(handle, ifindex, gateway).await
+            };
+            if let Err(e) = result {
+                warn!(ifindex, %gateway, "failed to restore gateway: {e}");
+                failed.get_or_insert("IPv4 gateway");
+            }
+        }
+        match failed {
+            None => info!(ifindex, "re-applied stored connection profile"),
+            Some(step) => warn!(
+                ifindex,
+                step, "stored connection profile only partly re-applied"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cidr_accepts_address_and_prefix() {
+        assert_eq!(
+            parse_cidr("192.0.2.10/24"),
+            Ok((Ipv4Addr::new(192, 0, 2, 10), 24))
+        );
+        assert_eq!(parse_cidr("0.0.0.0/0"), Ok((Ipv4Addr::UNSPECIFIED, 0)));
+        assert_eq!(
+            parse_cidr("198.51.100.1/32"),
+            Ok((Ipv4Addr::new(198, 51, 100, 1), 32))
+        );
+    }
+
+    #[test]
+    fn parse_cidr_rejects_malformed_input() {
+        for bad in [
+            "192.0.2.10",
+            "192.0.2.10/33",
+            "192.0.2.10/-1",
+            "192.0.2.10/",
+            "192.0.2/24",
+            "2001:db8::1/64",
+            "/24",
+        ] {
+            assert!(parse_cidr(bad).is_err(), "{bad:?} accepted");
+        }
+    }
+
+    #[test]
+    fn stored_profile_round_trips() {
+        let profile = ConnectionProfile {
+            id: Some("Office".to_string()),
+            autoconnect_priority: 10,
+            mtu: Some(9000),
+            ipv4_addresses: Some(vec![
+                (Ipv4Addr::new(192, 0, 2, 10), 24),
+                (Ipv4Addr::new(198, 51, 100, 1), 32),
+            ]),
+            ipv4_gateway: Some(Ipv4Addr::new(192, 0, 2, 1)),
+        };
+        let contents = toml::to_string(&StoredProfile::from(&profile)).unwrap();
+        assert!(contents.contains("\"192.0.2.10/24\""), "{contents}");
+        let stored: StoredProfile = toml::from_str(&contents).unwrap();
+        assert_eq!(ConnectionProfile::try_from(stored), Ok(profile));
+    }
+
+    #[test]
+    fn empty_stored_profile_is_default() {
+        let stored: StoredProfile = toml::from_str("").unwrap();
+        assert_eq!(
+            ConnectionProfile::try_from(stored),
+            Ok(ConnectionProfile::default())
+        );
+    }
+
+    #[test]
+    fn stored_profile_with_bad_address_is_rejected() {
+        let stored: StoredProfile = toml::from_str("ipv4_addresses = [\"192.0.2.10\"]").unwrap();
+        assert!(ConnectionProfile::try_from(stored).is_err());
+    }
+
+    #[test]
+    fn reapply_skips_unmanaged_and_non_autoconnect_devices() {
+        use crate::config::Config;
+        use crate::state::{self, DeviceInfo};
+        use crate::state_file::PersistentState;
+
+        let mut persistent = PersistentState::default();
+        persistent.autoconnect_disabled.insert("eth2".to_string());
+        let shared = state::new_shared_state(Config::default(), persistent);
+        let mut state = shared.try_write().unwrap();
+        for (ifindex, name) in [(2, "eth0"), (3, "eth1"), (4, "eth2"), (5, "eth3")] {
+            let mut dev = DeviceInfo::new(ifindex, name.to_string());
+            dev.managed = name != "eth1";
+            state.insert_device(dev);
+            if name != "eth3" {
+                let profile = ConnectionProfile {
+                    mtu: Some(9000),
+                    ..ConnectionProfile::default()
+                };
+                state.profiles.insert(name.to_string(), profile);
+            }
+        }
+
+        let work = pending_reapplies(&state);
+        let ifindexes: Vec<i32> = work.iter().map(|r| r.ifindex).collect();
+        assert_eq!(ifindexes, [2]);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures::StreamExt;
+use inotify::{Inotify, WatchMask, Watches};
+use tracing::{debug, info, warn};
+use zbus::Connection;
+
+use crate::netlink::queries;
+use crate::nm;
+use crate::resolved;
+use crate::state::SharedState;
+
+/// Coalesces the burst of events a single rewrite produces
+/// (create, write, close, rename).
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Watch the resolv.conf files and notify clients of nameserver changes as
+/// soon as they're rewritten, independently of netlink events. This is what
+/// fills in NameserverData when resolv.conf is written after startup; the
+/// global state is left alone, as it only depends on addresses and routes.
+pub async fn run(nm_conn: Connection, shared: SharedState) {
+    let inotify = match Inotify::init() {
+        Ok(i) => i,
+        Err(e) => {
+            warn!("inotify unavailable, resolv.conf changes won't be tracked: {e}");
+            return;
+        }
+    };
+    let mut events = match inotify.into_event_stream([0u8; 4096]) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("failed to create inotify stream: {e}");
+            return;
+        }
+    };
+    let mut paths = shared.read().await.config.resolv_conf_path.clone();
+    if !paths.iter().any(|p| Path::new(p).exists()) {
+        warn!(?paths, "none of the configured resolv.conf files exist");
+    }
+
+    let mut watches = events.watches();
+    let mut missing_dirs = HashSet::new();
+    let mut names = arm(&mut watches, &paths, &mut missing_dirs);
+    reload(&nm_conn, &shared).await;
+
+    loop {
+        let event = match events.next().await {
+            Some(Ok(ev)) => ev,
+            Some(Err(e)) => {
+                warn!("inotify read failed: {e}");
+                return;
+            }
+            None => return,
+        };
+        // Directory watches report every entry; only the watched files and
+        // the directories leading to them matter.
+        if event.name.as_ref().is_some_and(|n| !names.contains(n)) {
+            continue;
+        }
+
+        while let Ok(Some(_)) = tokio::time::timeout(DEBOUNCE, events.next()).await {}
+
+        // A file replaced by rename is a new inode: the old watch is gone.
+        // The paths may also have changed with a config reload.
+        paths = shared.read().await.config.resolv_conf_path.clone();
+        names = arm(&mut watches, &paths, &mut missing_dirs);
+
+        reload(&nm_conn, &shared).await;
+    }
+}
+
+/// Re-read resolv.conf and the per-link servers from systemd-resolved (which
+/// rewrites resolv.conf whenever link DNS changes), notifying on any change.
+async fn reload(nm_conn: &Connection, shared: &SharedState) {
+    let file_changed = queries::reload_nameservers(shared).await;
+    let links_changed = resolved::reload_link_nameservers(nm_conn, shared).await;
+    if file_changed || links_changed {
+        info!("nameservers changed");
+        let ifindexes: Vec<i32> = shared.read().await.devices.keys().copied().collect();
+        nm::signals::notify_nameservers_changed(nm_conn, &ifindexes).await;
+    }
+}
+
+/// (Re-)add watches on each resolv.conf and its parent directory. The
+/// directory watch catches the file being created or renamed into place;
+/// re-adding an existing watch is harmless. A directory that doesn't exist
+/// yet (e.g. /run/systemd/resolve before systemd-resolved has started) is
+/// waited for by watching its nearest existing ancestor; each such directory
+/// is logged once, remembered in `missing_dirs`.
+///
+/// Returns the entry names whose creation calls for re-arming: the files,
+/// and the next component of each missing directory.
+fn arm(
+    watches: &mut Watches,
+    paths: &[String],
+    missing_dirs: &mut HashSet<PathBuf>,
+) -> HashSet<OsString> {
+    let mut names = HashSet::new();
+    for path in paths {
+        let path = Path::new(path);
+        names.extend(path.file_name().map(OsString::from));
+        let Some(dir) = path.parent() else {
+            continue;
+        };
+        let Some(watched) = dir.ancestors().find(|d| d.is_dir()) else {
+            continue;
+        };
+        if watched != dir {
+            if missing_dirs.insert(dir.to_path_buf()) {
+                info!(dir = %dir.display(), "resolv.conf directory does not exist, waiting for it");
+            }
+            names.extend(
+                dir.strip_prefix(watched)
+                    .ok()
+                    .and_then(|rest| rest.iter().next())
+                    .map(OsString::from),
+            );
+        } else if missing_dirs.remove(dir) {
+            debug!(dir = %dir.display(), "resolv.conf directory appeared");
+        }
+        if let Err(e) = watches.add(watched, WatchMask::CREATE | WatchMask::MOVED_TO) {
+            debug!(dir = %watched.display(), "cannot watch directory: {e}");
+        }
+        if let Err(e) = watches.add(
+            path,
+            WatchMask::CLOSE_WRITE | WatchMask::DELETE_SELF | WatchMask::MOVE_SELF,
+        ) {
+            debug!(path = %path.display(), "cannot watch file: {e}");
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_directory_is_waited_for_through_its_ancestor() {
+        let root =
+            std::env::temp_dir().join(format!("nmlinkd-resolv-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let dir = root.join("systemd").join("resolve");
+        let paths = [dir.join("resolv.conf").to_string_lossy().into_owned()];
+        let inotify = Inotify::init().unwrap();
+        let mut watches = inotify.watches();
+        let mut missing_dirs = HashSet::new();
+
+        let names = arm(&mut watches, &paths, &mut missing_dirs);
+        assert_eq!(
+            names,
+            HashSet::from([OsString::from("resolv.conf"), OsString::from("systemd")])
+        );
+        assert_eq!(missing_dirs, HashSet::from([dir.clone()]));
+
+        std::fs::create_dir_all(&dir).unwrap();
+        let names = arm(&mut watches, &paths, &mut missing_dirs);
+        assert_eq!(names, HashSet::from([OsString::from("resolv.conf")]));
+        assert!(missing_dirs.is_empty());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use tracing::debug;
+use zbus::Connection;
+
+use crate::state::SharedState;
+
+const AF_INET: i32 = 2;
+const AF_INET6: i32 = 10;
+
+/// systemd-networkd's per-link state files, named by ifindex.
+const NETWORKD_LINKS_DIR: &str = "/run/systemd/netif/links";
+
+/// Per-link DNS servers known to systemd-resolved, keyed by ifindex.
+/// Global servers (ifindex 0) are left out: resolv.conf already covers them.
+async fn query_link_dns(conn: &Connection) -> zbus::Result<HashMap<i32, Vec<String>>> {
+    let proxy = zbus::Proxy::new(
+        conn,
+        "org.freedesktop.resolve1",
+        "/org/freedesktop/resolve1",
+        "org.freedesktop.resolve1.Manager",
+    )
+    .await?;
+    let servers: Vec<(i32, i32, Vec<u8>)> = proxy.get_property("DNS").await?;
+
+    let mut by_link: HashMap<i32, Vec<String>> = HashMap::new();
+    for (ifindex, family, bytes) in servers {
+        if ifindex == 0 {
+            continue;
+        }
+        let addr = match (family, bytes.len()) {
+            (AF_INET, 4) => IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])),
+            (AF_INET6, 16) => {
+                let octets: [u8; 16] = bytes.try_into().unwrap_or_default();
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => continue,
+        };
+        by_link.entry(ifindex).or_default().push(addr.to_string());
+    }
+    Ok(by_link)
+}
+
+/// Per-link DNS servers from systemd-networkd's link state files (the `DNS=`
+/// line, which includes servers learned from IPv6 RAs via RDNSS), keyed by
+/// ifindex. Links without a state file or servers are left out.
+async fn read_networkd_link_dns(ifindexes: &[i32]) -> HashMap<i32, Vec<String>> {
+    let mut by_link = HashMap::new();
+    for &ifindex in ifindexes {
+        let path = format!("{NETWORKD_LINKS_DIR}/{ifindex}");
+        let Ok(contents) = tokio::fs::read_to_string(&path).await else {
+            continue;
+        };
+        let servers: Vec<String> = contents
+            .lines()
+            .filter_map(|line| line.strip_prefix("DNS="))
+            .flat_map(str::split_whitespace)
+            .filter(|s| s.parse::<IpAddr>().is_ok())
+            .map(str::to_string)
+            .collect();
+        if !servers.is_empty() {
+            by_link.insert(ifindex, servers);
+        }
+    }
+    by_link
+}
+
+/// Refresh every device's own nameservers from systemd-resolved, or from
+/// systemd-networkd's link state when resolved isn't running.
+/// Returns true if any device's list changed. With neither, devices keep
+/// no per-link servers and clients see the resolv.conf list instead.
+pub async fn reload_link_nameservers(conn: &Connection, shared: &SharedState) -> bool {
+    let mut by_link = match query_link_dns(conn).await {
+        Ok(m) => m,
+        Err(e) => {
+            debug!("per-link DNS from systemd-resolved unavailable: {e}");
+            let ifindexes: Vec<i32> = shared.read().await.devices.keys().copied().collect();
+            read_networkd_link_dns(&ifindexes).await
+        }
+    };
+
+    let mut state = shared.write().await;
+    let mut changed = false;
+    for dev in state.devices.values_mut() {
+        let servers = by_link.remove(&dev.ifindex).unwrap_or_default();
+        if dev.nameservers != servers {
+            debug!(iface = %dev.name, ?servers, "per-link nameservers changed");
+            dev.nameservers = servers;
+            changed = true;
+        }
+    }
+    changed
+}
@@ -1,43 +1,131 @@
-use std::collections::{HashMap, HashSet};
-use std::net::{Ipv4Addr, Ipv6Addr};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::sync::{Arc, LazyLock};
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, oneshot};
+use tokio::task::AbortHandle;
+use tokio::time::Instant;
+use tracing::warn;
 
 use zbus::zvariant::OwnedObjectPath;
 
+use crate::config::Config;
+use crate::ethtool;
 use crate::mapping;
+use crate::netlink::queries::ResolvConfStamp;
+use crate::state_file::PersistentState;
 
 const NM_PREFIX: &str = "/org/freedesktop/NetworkManager";
 
 const NMLINKD_UUID_NAMESPACE: uuid::Uuid = uuid::uuid!("90bb69d5-2a09-40fc-96b5-3c0e34f9809c");
 
-/// Generate a stable UUID for a connection based on interface name.
+/// Namespace for connection UUIDs: nmlinkd's own, narrowed to this host by
+/// /etc/machine-id. Falls back to the fixed namespace if it can't be read.
+static HOST_UUID_NAMESPACE: LazyLock<uuid::Uuid> =
+    LazyLock::new(|| match std::fs::read_to_string("/etc/machine-id") {
+        Ok(id) if !id.trim().is_empty() => {
+            uuid::Uuid::new_v5(&NMLINKD_UUID_NAMESPACE, id.trim().as_bytes())
+        }
+        Ok(_) => {
+            warn!("/etc/machine-id is empty, connection UUIDs won't be host-specific");
+            NMLINKD_UUID_NAMESPACE
+        }
+        Err(e) => {
+            warn!("can't read /etc/machine-id, connection UUIDs won't be host-specific: {e}");
+            NMLINKD_UUID_NAMESPACE
+        }
+    });
+
+/// Generate a stable UUID for a connection from the host and interface name.
 pub fn connection_uuid(iface_name: &str) -> String {
-    uuid::Uuid::new_v5(&NMLINKD_UUID_NAMESPACE, iface_name.as_bytes()).to_string()
+    uuid::Uuid::new_v5(&HOST_UUID_NAMESPACE, iface_name.as_bytes()).to_string()
+}
+
+/// Keyfile path reported as a connection's Settings.Connection.Filename,
+/// named after its UUID. Nothing is written there; clients only need a
+/// stable, non-empty name.
+pub fn connection_filename(iface_name: &str) -> String {
+    format!(
+        "/run/nmlinkd/connections/{}.nmconnection",
+        connection_uuid(iface_name)
+    )
+}
+
+/// Kind of per-device NM object, i.e. the path segment after the NM prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    Device,
+    ActiveConnection,
+    Ip4Config,
+    Ip6Config,
+    Settings,
 }
 
-fn nm_path(kind: &str, ifindex: i32) -> OwnedObjectPath {
-    OwnedObjectPath::try_from(format!("{NM_PREFIX}/{kind}/{ifindex}")).unwrap()
+impl PathKind {
+    const ALL: [PathKind; 5] = [
+        PathKind::Device,
+        PathKind::ActiveConnection,
+        PathKind::Ip4Config,
+        PathKind::Ip6Config,
+        PathKind::Settings,
+    ];
+
+    /// Path template of this kind, for error messages.
+    pub fn template(self) -> String {
+        format!("{NM_PREFIX}/{}/<n>", self.segment())
+    }
+
+    fn segment(self) -> &'static str {
+        match self {
+            PathKind::Device => "Devices",
+            PathKind::ActiveConnection => "ActiveConnection",
+            PathKind::Ip4Config => "IP4Config",
+            PathKind::Ip6Config => "IP6Config",
+            PathKind::Settings => "Settings",
+        }
+    }
+}
+
+fn nm_path(kind: PathKind, ifindex: i32) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(format!("{NM_PREFIX}/{}/{ifindex}", kind.segment())).unwrap()
+}
+
+/// Parse a per-device NM object path like `/org/freedesktop/NetworkManager/Devices/3`
+/// into its kind and index: the ifindex, except for ActiveConnection paths,
+/// which carry an activation id. Returns None for anything that isn't exactly
+/// `{NM_PREFIX}/{kind}/{index}`, with the index a positive decimal number
+/// as `nm_path` writes it (no sign, no leading zeros).
+pub fn parse_nm_path(path: &str) -> Option<(PathKind, i32)> {
+    let rest = path.strip_prefix(NM_PREFIX)?.strip_prefix('/')?;
+    let (segment, index) = rest.split_once('/')?;
+    let kind = PathKind::ALL.into_iter().find(|k| k.segment() == segment)?;
+    if !index.bytes().all(|b| b.is_ascii_digit()) || index.starts_with('0') {
+        return None;
+    }
+    let index = index.parse().ok()?;
+    Some((kind, index))
 }
 
 pub fn device_path(ifindex: i32) -> OwnedObjectPath {
-    nm_path("Devices", ifindex)
+    nm_path(PathKind::Device, ifindex)
 }
 
-pub fn active_connection_path(ifindex: i32) -> OwnedObjectPath {
-    nm_path("ActiveConnection", ifindex)
+/// Active connections are numbered per activation, as in NetworkManager,
+/// so clients can tell one activation of a device from the next.
+pub fn active_connection_path(activation_id: i32) -> OwnedObjectPath {
+    nm_path(PathKind::ActiveConnection, activation_id)
 }
 
 pub fn ip4_config_path(ifindex: i32) -> OwnedObjectPath {
-    nm_path("IP4Config", ifindex)
+    nm_path(PathKind::Ip4Config, ifindex)
 }
 
 pub fn ip6_config_path(ifindex: i32) -> OwnedObjectPath {
-    nm_path("IP6Config", ifindex)
+    nm_path(PathKind::Ip6Config, ifindex)
 }
 
 pub fn settings_path(ifindex: i32) -> OwnedObjectPath {
-    nm_path("Settings", ifindex)
+    nm_path(PathKind::Settings, ifindex)
 }
 
 static ROOT_PATH: LazyLock<OwnedObjectPath> =
@@ -49,8 +137,12 @@ pub fn root_path() -> OwnedObjectPath {
 
 pub type SharedState = Arc<RwLock<AppState>>;
 
-pub fn new_shared_state() -> SharedState {
-    Arc::new(RwLock::new(AppState::default()))
+pub fn new_shared_state(config: Config, persistent: PersistentState) -> SharedState {
+    Arc::new(RwLock::new(AppState {
+        config,
+        persistent,
+        ..AppState::default()
+    }))
 }
 
 /// Extension trait for ergonomic access on SharedState.
@@ -73,22 +165,73 @@ impl SharedStateExt for SharedState {
 
 #[derive(Default)]
 pub struct AppState {
+    pub config: Config,
+    /// Runtime settings saved to the state file.
+    pub persistent: PersistentState,
     pub global_state: u32,
     pub connectivity: u32,
+    /// Result of the last connectivity probe; None until one has run.
+    pub connectivity_check: Option<u32>,
     pub devices: HashMap<i32, DeviceInfo>,
+    /// Interface name → ifindex, kept in sync with `devices`.
+    pub name_to_ifindex: HashMap<String, i32>,
     pub nameservers: Vec<String>,
+    /// Search domains from resolv.conf (`search`/`domain` lines).
+    pub search_domains: Vec<String>,
+    /// Resolver options from resolv.conf (`options` lines), not acted on.
+    pub resolv_conf_options: Vec<String>,
+    /// The resolv.conf the nameservers above were parsed from.
+    pub resolv_conf_stamp: Option<ResolvConfStamp>,
     pub netlink_handle: Option<rtnetlink::Handle>,
+    /// User-stored connection profiles, keyed by interface name.
+    pub profiles: HashMap<String, ConnectionProfile>,
     /// ifindexes where disconnect was user-initiated (consumed by signal emission).
     pub user_disconnect_pending: HashSet<i32>,
+    /// ifindexes whose activation timed out (consumed by signal emission).
+    pub activation_failed_pending: HashSet<i32>,
+    /// `ActivateConnection` calls waiting for a device to reach ACTIVATED.
+    pub activation_waiters: HashMap<i32, Vec<oneshot::Sender<()>>>,
+    /// Statistics polling tasks, for devices with a non-zero RefreshRateMs.
+    pub stats_refreshers: HashMap<i32, AbortHandle>,
+    /// Last device state change announced to clients, per ifindex.
+    pub emitted_states: HashMap<i32, EmittedState>,
+    /// Pending announcement of a flapping device's settled state, per
+    /// ifindex; replaced on every held-back change.
+    pub settle_tasks: HashMap<i32, AbortHandle>,
+    /// Last activation id handed out; see `DeviceInfo::activation_id`.
+    pub last_activation_id: i32,
+}
+
+/// The daemon's view of the network, as `Manager.DumpState` reports it.
+#[derive(Serialize)]
+pub struct StateDump<'a> {
+    global_state: u32,
+    connectivity: u32,
+    nameservers: &'a [String],
+    search_domains: &'a [String],
+    resolv_conf_path: Option<&'a str>,
+    devices: Vec<&'a DeviceInfo>,
+    profiles: BTreeMap<&'a str, &'a ConnectionProfile>,
+}
+
+/// A device StateChanged as clients saw it.
+#[derive(Debug, Clone, Copy)]
+pub struct EmittedState {
+    pub state: u32,
+    pub previous: u32,
+    pub at: Instant,
 }
 
 impl std::fmt::Debug for AppState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AppState")
+            .field("config", &self.config)
+            .field("persistent", &self.persistent)
             .field("global_state", &self.global_state)
             .field("connectivity", &self.connectivity)
             .field("devices", &self.devices)
             .field("nameservers", &self.nameservers)
+            .field("search_domains", &self.search_domains)
             .field(
                 "netlink_handle",
                 &self.netlink_handle.as_ref().map(|_| "..."),
@@ -98,32 +241,436 @@ impl std::fmt::Debug for AppState {
 }
 
 impl AppState {
-    /// Get the shared netlink handle. Panics if not initialized (always set after startup).
+    /// Get the shared netlink handle. Panics if not initialized.
+    ///
+    /// Only for the monitor path, which starts after `load_initial_state`.
+    /// D-Bus method handlers must use [`AppState::try_handle`].
     pub fn handle(&self) -> &rtnetlink::Handle {
         self.netlink_handle
             .as_ref()
             .expect("netlink handle not initialized")
     }
 
+    /// Get a clone of the shared netlink handle, or None if startup hasn't set it yet.
+    pub fn try_handle(&self) -> Option<rtnetlink::Handle> {
+        self.netlink_handle.clone()
+    }
+
+    /// Snapshot for `Manager.DumpState`, devices ordered by ifindex.
+    pub fn dump(&self) -> StateDump<'_> {
+        let mut devices: Vec<&DeviceInfo> = self.devices.values().collect();
+        devices.sort_by_key(|d| d.ifindex);
+        StateDump {
+            global_state: self.global_state,
+            connectivity: self.connectivity,
+            nameservers: &self.nameservers,
+            search_domains: &self.search_domains,
+            resolv_conf_path: self.resolv_conf_stamp.as_ref().map(|s| s.path.as_str()),
+            devices,
+            profiles: self
+                .profiles
+                .iter()
+                .map(|(name, profile)| (name.as_str(), profile))
+                .collect(),
+        }
+    }
+
+    /// Reject a state-modifying D-Bus call when running in read-only mode.
+    pub fn ensure_writable(&self) -> zbus::fdo::Result<()> {
+        if self.config.read_only {
+            return Err(zbus::fdo::Error::AccessDenied(
+                "nmlinkd is running in read-only mode".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Complete the `ActivateConnection` calls waiting on `ifindex`.
+    pub fn notify_activated(&mut self, ifindex: i32) {
+        for waiter in self
+            .activation_waiters
+            .remove(&ifindex)
+            .into_iter()
+            .flatten()
+        {
+            let _ = waiter.send(());
+        }
+    }
+
+    /// Reject a D-Bus call that would act on an ignored interface.
+    pub fn ensure_not_ignored(&self, ifindex: i32) -> zbus::fdo::Result<()> {
+        match self.devices.get(&ifindex) {
+            Some(dev) if dev.ignored => Err(zbus::fdo::Error::NotSupported(format!(
+                "{} is ignored by nmlinkd",
+                dev.name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject a D-Bus call that would act on an ignored or unmanaged
+    /// interface: nmlinkd leaves those to whoever else configures them.
+    pub fn ensure_controllable(&self, ifindex: i32) -> zbus::fdo::Result<()> {
+        self.ensure_not_ignored(ifindex)?;
+        match self.devices.get(&ifindex) {
+            Some(dev) if !dev.managed => Err(zbus::fdo::Error::NotSupported(format!(
+                "{} is not managed by nmlinkd",
+                dev.name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn next_activation_id(&mut self) -> i32 {
+        self.last_activation_id += 1;
+        self.last_activation_id
+    }
+
+    /// The device whose current active connection has `activation_id`.
+    pub fn ifindex_by_activation_id(&self, activation_id: i32) -> Option<i32> {
+        self.devices
+            .values()
+            .find(|d| d.activation_id == activation_id)
+            .map(|d| d.ifindex)
+    }
+
+    /// Insert (or replace) a device, keeping the name index in sync.
+    /// A fresh activation id is assigned.
+    pub fn insert_device(&mut self, mut dev: DeviceInfo) {
+        dev.activation_id = self.next_activation_id();
+        if self.persistent.unmanaged.contains(&dev.name) {
+            dev.managed = false;
+            dev.nm_state = mapping::nm_device_state::UNMANAGED;
+        }
+        if let Some(old) = self.devices.get(&dev.ifindex) {
+            self.name_to_ifindex.remove(&old.name);
+        }
+        self.name_to_ifindex.insert(dev.name.clone(), dev.ifindex);
+        self.devices.insert(dev.ifindex, dev);
+    }
+
+    /// Whether interface `name` is unmanaged, by config or over D-Bus.
+    pub fn is_unmanaged(&self, name: &str) -> bool {
+        self.config.is_unmanaged(name) || self.persistent.unmanaged.contains(name)
+    }
+
+    /// Whether the configured `max_devices` cap leaves no room for `dev`.
+    /// Ignored interfaces shown as unmanaged count towards it like any
+    /// other: each one registers its own D-Bus objects.
+    /// Logs a warning naming the interface that was turned away.
+    pub fn device_cap_reached(&self, dev: &DeviceInfo) -> bool {
+        let max = self.config.max_devices;
+        if self.devices.contains_key(&dev.ifindex) || self.devices.len() < max {
+            return false;
+        }
+        warn!(
+            ifindex = dev.ifindex,
+            iface = %dev.name,
+            max_devices = max,
+            "device limit reached, not exporting interface"
+        );
+        true
+    }
+
+    /// Remove a device, keeping the name index in sync.
+    pub fn remove_device(&mut self, ifindex: i32) -> Option<DeviceInfo> {
+        let dev = self.devices.remove(&ifindex)?;
+        self.emitted_states.remove(&ifindex);
+        self.activation_waiters.remove(&ifindex);
+        // Unplugged mid-disconnect: a link that reuses the index must not
+        // inherit the pending reason.
+        self.user_disconnect_pending.remove(&ifindex);
+        self.activation_failed_pending.remove(&ifindex);
+        if let Some(task) = self.stats_refreshers.remove(&ifindex) {
+            task.abort();
+        }
+        if let Some(task) = self.settle_tasks.remove(&ifindex) {
+            task.abort();
+        }
+        if self.name_to_ifindex.get(&dev.name) == Some(&ifindex) {
+            self.name_to_ifindex.remove(&dev.name);
+        }
+        Some(dev)
+    }
+
+    /// Rename a device. Returns the old name if the name actually changed.
+    pub fn rename_device(&mut self, ifindex: i32, new_name: &str) -> Option<String> {
+        let dev = self.devices.get_mut(&ifindex)?;
+        if dev.name == new_name {
+            return None;
+        }
+        let old_name = std::mem::replace(&mut dev.name, new_name.to_string());
+        if self.name_to_ifindex.get(&old_name) == Some(&ifindex) {
+            self.name_to_ifindex.remove(&old_name);
+        }
+        self.name_to_ifindex.insert(new_name.to_string(), ifindex);
+        Some(old_name)
+    }
+
+    /// Connection id for a device: the user-set profile id if any, else the
+    /// interface alias, else the interface name. Unlike the id, the
+    /// connection UUID comes from the interface name (see
+    /// [`connection_uuid`]), so renaming a connection keeps its UUID, as in
+    /// NetworkManager.
+    pub fn connection_id(&self, dev: &DeviceInfo) -> String {
+        self.profiles
+            .get(&dev.name)
+            .and_then(|p| p.id.clone())
+            .or_else(|| dev.alias.clone())
+            .unwrap_or_else(|| dev.name.clone())
+    }
+
+    /// Set a device's interface alias. Returns the device's activation id and
+    /// its connection id if that changed with the alias; None if the alias
+    /// didn't change.
+    pub fn set_alias(
+        &mut self,
+        ifindex: i32,
+        alias: Option<String>,
+    ) -> Option<(i32, Option<String>)> {
+        let dev = self.devices.get(&ifindex)?;
+        if dev.alias == alias {
+            return None;
+        }
+        let old_id = self.connection_id(dev);
+        let dev = self.devices.get_mut(&ifindex)?;
+        dev.alias = alias;
+        let dev = &self.devices[&ifindex];
+        let new_id = self.connection_id(dev);
+        Some((dev.activation_id, (new_id != old_id).then_some(new_id)))
+    }
+
+    /// The profile's `autoconnect-priority` for a device (0 if unset).
+    pub fn autoconnect_priority(&self, dev: &DeviceInfo) -> i32 {
+        self.profiles
+            .get(&dev.name)
+            .map_or(0, |p| p.autoconnect_priority)
+    }
+
+    /// ActiveConnection objects of activated (or deactivating) devices.
+    pub fn active_connection_paths(&self) -> Vec<OwnedObjectPath> {
+        self.devices
+            .values()
+            .filter(|d| mapping::has_active_connection(d.nm_state))
+            .map(|d| active_connection_path(d.activation_id))
+            .collect()
+    }
+
+    /// Device carrying the primary connection: the activated device with a
+    /// default gateway whose profile has the highest autoconnect priority,
+    /// the lowest ifindex breaking ties so the choice doesn't depend on
+    /// which link came up first.
+    pub fn primary_device(&self) -> Option<&DeviceInfo> {
+        self.devices
+            .values()
+            .filter(|d| d.nm_state == mapping::nm_device_state::ACTIVATED && d.has_gateway())
+            .max_by_key(|d| (self.autoconnect_priority(d), std::cmp::Reverse(d.ifindex)))
+    }
+
+    /// Whether the device may be brought up automatically (on unless the
+    /// user turned `Autoconnect` off for this interface name).
+    pub fn autoconnect(&self, dev: &DeviceInfo) -> bool {
+        !self.persistent.autoconnect_disabled.contains(&dev.name)
+    }
+
+    /// Nameservers to report for a device: its per-link servers, or the
+    /// resolv.conf list when none are known for that link.
+    pub fn device_nameservers<'a>(&'a self, dev: &'a DeviceInfo) -> &'a [String] {
+        if dev.nameservers.is_empty() {
+            &self.nameservers
+        } else {
+            &dev.nameservers
+        }
+    }
+
+    /// Look up a device's ifindex by interface name.
+    pub fn ifindex_by_name(&self, name: &str) -> Option<i32> {
+        self.name_to_ifindex.get(name).copied()
+    }
+
+    /// Exported ports of controller `ifindex`, by ifindex.
+    pub fn ports(&self, ifindex: i32) -> Vec<i32> {
+        let mut ports: Vec<i32> = self
+            .devices
+            .values()
+            .filter(|d| d.controller == Some(ifindex))
+            .map(|d| d.ifindex)
+            .collect();
+        ports.sort_unstable();
+        ports
+    }
+
+    /// Refresh whether each bond and bridge has a port with carrier, and
+    /// re-evaluate the state of those where that changed. Only ports nmlinkd
+    /// tracks count; a controller without any keeps following its own
+    /// carrier. Returns the state changes as (ifindex, new_state, old_state).
+    pub fn update_controller_states(&mut self) -> Vec<(i32, u32, u32)> {
+        use mapping::nm_device_type::{BOND, BRIDGE};
+
+        let mut ports_carrier: HashMap<i32, bool> = HashMap::new();
+        for dev in self.devices.values() {
+            if let Some(controller) = dev.controller {
+                *ports_carrier.entry(controller).or_default() |= dev.carrier();
+            }
+        }
+        let mut changes = Vec::new();
+        for dev in self.devices.values_mut() {
+            if !matches!(dev.device_type, BOND | BRIDGE) {
+                continue;
+            }
+            let carrier = ports_carrier.get(&dev.ifindex).copied();
+            if dev.ports_carrier == carrier {
+                continue;
+            }
+            dev.ports_carrier = carrier;
+            let change =
+                dev.update_state_on_link_change(dev.link_flags, dev.kernel_carrier, dev.operstate);
+            if let Some((new_state, old_state)) = change {
+                changes.push((dev.ifindex, new_state, old_state));
+            }
+        }
+        changes
+    }
+
     /// Recompute global NM state based on device states and connectivity.
+    /// A probe result takes precedence over the state-derived guess, and is
+    /// discarded once we're disconnected.
     pub fn recompute_global_state(&mut self) {
-        self.global_state = mapping::deduce_global_state(&self.devices);
-        self.connectivity = mapping::global_state_to_connectivity(self.global_state);
+        self.global_state = mapping::deduce_global_state(&self.devices, |ifindex| {
+            self.activation_waiters
+                .get(&ifindex)
+                .is_some_and(|waiters| waiters.iter().any(|w| !w.is_closed()))
+        });
+        if self.global_state < mapping::nm_state::CONNECTED_LOCAL {
+            self.connectivity_check = None;
+        }
+        self.connectivity = self
+            .connectivity_check
+            .unwrap_or_else(|| mapping::global_state_to_connectivity(self.global_state));
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DeviceInfo {
     pub ifindex: i32,
     pub name: String,
+    /// IFLA_IFALIAS: free-form description set with `ip link set alias`.
+    pub alias: Option<String>,
     pub device_type: u32,
+    /// Link kind (e.g. "bond", "ipoib") or ARPHRD name, for Device.Generic.
+    pub type_description: String,
+    /// ARPHRD_* hardware type from the link header (1 = Ethernet).
+    pub arp_type: u16,
     pub nm_state: u32,
     pub hw_address: String,
+    /// Burned-in MAC (IFLA_PERM_ADDRESS, kernel 5.6+); None when the kernel
+    /// or driver doesn't report one.
+    pub perm_hw_address: Option<String>,
     pub link_flags: u32,
+    /// IFLA_CARRIER; None when the kernel doesn't report it.
+    pub kernel_carrier: Option<bool>,
+    /// IFLA_OPERSTATE (`mapping::if_oper`); UNKNOWN if not reported.
+    pub operstate: u8,
+    /// The last state change left the link administratively up but without
+    /// carrier (cable unplugged), rather than set down.
+    pub carrier_lost: bool,
+    pub mtu: u32,
+    /// IFLA_TXQLEN and IFLA_NUM_TX_QUEUES.
+    pub tx_queue_len: u32,
+    pub num_tx_queues: u32,
     pub ipv4_addrs: Vec<AddrInfo<Ipv4Addr>>,
     pub ipv6_addrs: Vec<AddrInfo<Ipv6Addr>>,
     pub gateway4: Option<Ipv4Addr>,
     pub gateway6: Option<Ipv6Addr>,
+    pub vlan: Option<VlanInfo>,
+    pub tunnel: Option<TunnelInfo>,
+    /// ifindex of a bond's active slave (active-backup and similar modes).
+    pub bond_active_slave: Option<i32>,
+    /// IFLA_MASTER: the bond or bridge this link is a port of.
+    pub controller: Option<i32>,
+    /// For bonds and bridges: whether any tracked port has carrier, None
+    /// without ports. Kept up to date by `AppState::update_controller_states`.
+    pub ports_carrier: Option<bool>,
+    /// False for interfaces listed in the config's `unmanaged` patterns.
+    pub managed: bool,
+    /// Matches `ignore_interfaces`, exported only for
+    /// `show_ignored_as_unmanaged`: never managed, and without the objects
+    /// a connection needs.
+    pub ignored: bool,
+    /// Number of the device's current (or next) activation, naming its
+    /// ActiveConnection object; renewed each time a connection deactivates.
+    pub activation_id: i32,
+    /// Listed in the config's `vpn_interfaces`: its connection is a VPN.
+    pub vpn: bool,
+    /// Per-link DNS servers from systemd-resolved; empty if none are known.
+    pub nameservers: Vec<String>,
+    /// Negotiated speed (Mb/s) and duplex ("full", "half", or "" if unknown),
+    /// as last announced; both unset without carrier.
+    pub link_speed: u32,
+    pub duplex: String,
+    /// Kernel driver bound to the underlying device; empty for virtual links.
+    pub driver: String,
+    /// Statistics.RefreshRateMs as set by a client; 0 while nobody polls.
+    pub stats_refresh_ms: u32,
+    /// Byte counters as of the last statistics refresh.
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Driver and firmware details, read on first use.
+    pub hardware: Option<HardwareInfo>,
+    /// Last result of the connectivity probe bound to this link, per family.
+    pub ip4_connectivity_check: Option<u32>,
+    pub ip6_connectivity_check: Option<u32>,
+    /// Neighbour cache entries on this link; empty unless `track_neighbors`.
+    pub neighbors: Vec<NeighborInfo>,
+}
+
+/// Hardware details for inventory; fields are empty when unavailable.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HardwareInfo {
+    pub driver_version: String,
+    pub firmware_version: String,
+    /// Bus vendor id from sysfs, e.g. "0x8086" for an Intel PCI NIC.
+    pub vendor: String,
+}
+
+impl HardwareInfo {
+    /// Read ethtool's driver info and the sysfs vendor id of interface `name`.
+    pub fn read(name: &str) -> Self {
+        let drvinfo = ethtool::driver_info(name).unwrap_or_default();
+        let vendor = std::fs::read_to_string(format!("/sys/class/net/{name}/device/vendor"))
+            .map(|v| v.trim().to_string())
+            .unwrap_or_default();
+        Self {
+            driver_version: drvinfo.version,
+            firmware_version: drvinfo.fw_version,
+            vendor,
+        }
+    }
+}
+
+/// 802.1Q parameters of a VLAN sub-interface (from IFLA_INFO_DATA).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VlanInfo {
+    pub parent: Option<i32>,
+    pub id: u16,
+    pub flags: u32,
+}
+
+/// Parameters of a GRE/SIT/IPIP tunnel (from IFLA_INFO_DATA).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TunnelInfo {
+    /// NMIPTunnelMode.
+    pub mode: u32,
+    pub local: Option<IpAddr>,
+    pub remote: Option<IpAddr>,
+}
+
+fn link_connectivity(has_gateway: bool, check: Option<u32>) -> u32 {
+    if has_gateway {
+        check.unwrap_or(mapping::nm_connectivity::FULL)
+    } else {
+        mapping::nm_connectivity::NONE
+    }
 }
 
 impl DeviceInfo {
@@ -131,43 +678,187 @@ impl DeviceInfo {
         Self {
             ifindex,
             name,
+            alias: None,
             device_type: mapping::nm_device_type::ETHERNET,
+            type_description: String::new(),
+            arp_type: 0,
             nm_state: mapping::nm_device_state::UNKNOWN,
             hw_address: String::new(),
+            perm_hw_address: None,
             link_flags: 0,
+            kernel_carrier: None,
+            operstate: mapping::if_oper::UNKNOWN,
+            carrier_lost: false,
+            mtu: 0,
+            tx_queue_len: 0,
+            num_tx_queues: 0,
             ipv4_addrs: Vec::new(),
             ipv6_addrs: Vec::new(),
             gateway4: None,
             gateway6: None,
+            vlan: None,
+            tunnel: None,
+            bond_active_slave: None,
+            controller: None,
+            ports_carrier: None,
+            managed: true,
+            ignored: false,
+            activation_id: 0,
+            vpn: false,
+            nameservers: Vec::new(),
+            link_speed: 0,
+            duplex: String::new(),
+            driver: String::new(),
+            stats_refresh_ms: 0,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            hardware: None,
+            ip4_connectivity_check: None,
+            ip6_connectivity_check: None,
+            neighbors: Vec::new(),
         }
     }
 
+    /// IFLA_CARRIER when the kernel reported it, else inferred from the flags.
     pub fn carrier(&self) -> bool {
-        use crate::mapping::netlink_flags;
-        (self.link_flags & netlink_flags::IFF_RUNNING) != 0
-            || (self.link_flags & netlink_flags::IFF_LOWER_UP) != 0
+        self.kernel_carrier
+            .unwrap_or_else(|| mapping::flags_carrier(self.link_flags))
     }
 
-    pub fn speed(&self) -> u32 {
-        if !self.carrier() {
-            return 0;
+    /// NM's default dns-priority: 50 for VPNs (WireGuard and links listed in
+    /// `vpn_interfaces`, both exported as WireGuard), 100 otherwise.
+    pub fn dns_priority(&self) -> i32 {
+        if self.device_type == mapping::nm_device_type::WIREGUARD {
+            50
+        } else {
+            100
         }
-        std::fs::read_to_string(format!("/sys/class/net/{}/speed", self.name))
-            .ok()
-            .and_then(|s| s.trim().parse::<i32>().ok())
+    }
+
+    /// Whether the device exports Device.Wired, which carries its speed and
+    /// duplex.
+    pub fn has_wired_interface(&self) -> bool {
+        !self.ignored && mapping::is_ethernet_like(self.device_type)
+    }
+
+    /// Read link `name`'s current speed and duplex from sysfs; (0, "")
+    /// without carrier or when the driver doesn't report them. Blocking, so
+    /// not to be called with the state lock held.
+    pub fn read_link_mode(name: &str, carrier: bool) -> (u32, String) {
+        if !carrier {
+            return (0, String::new());
+        }
+        let read = |attr: &str| {
+            std::fs::read_to_string(format!("/sys/class/net/{name}/{attr}"))
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default()
+        };
+        let speed = read("speed")
+            .parse::<i32>()
             .map(|v| if v < 0 { 0 } else { v as u32 })
-            .unwrap_or(0)
+            .unwrap_or(0);
+        let duplex = match read("duplex").as_str() {
+            d @ ("full" | "half") => d.to_string(),
+            _ => String::new(),
+        };
+        (speed, duplex)
+    }
+
+    /// Record the link's speed and duplex, returning them if either changed.
+    pub fn set_link_mode(&mut self, speed: u32, duplex: &str) -> Option<(u32, String)> {
+        if speed == self.link_speed && duplex == self.duplex {
+            return None;
+        }
+        self.link_speed = speed;
+        self.duplex = duplex.to_string();
+        Some((speed, self.duplex.clone()))
     }
 
-    fn has_ip_address(&self) -> bool {
+    /// Read the driver name from the `/sys/class/net/{name}/device/driver`
+    /// symlink. Virtual interfaces have no such link and get "".
+    pub fn read_driver(name: &str) -> String {
+        std::fs::read_link(format!("/sys/class/net/{name}/device/driver"))
+            .ok()
+            .and_then(|target| Some(target.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_default()
+    }
+
+    pub fn has_ip_address(&self) -> bool {
         !self.ipv4_addrs.is_empty() || !self.ipv6_addrs.is_empty()
     }
 
+    /// Whether the link is IP-configured, i.e. ACTIVATED rather than
+    /// IP_CONFIG once it has carrier: it has an address or a default route.
+    /// PPPoE and tunnel links often route via the peer without a local
+    /// address of their own.
+    pub fn has_ip_config(&self) -> bool {
+        self.has_ip_address() || self.has_gateway()
+    }
+
+    /// IPv4 connectivity through this link: NONE without a default route,
+    /// otherwise the last probe result (FULL until one has run).
+    pub fn ip4_connectivity(&self) -> u32 {
+        link_connectivity(self.gateway4.is_some(), self.ip4_connectivity_check)
+    }
+
+    /// IPv6 counterpart of [`DeviceInfo::ip4_connectivity`].
+    pub fn ip6_connectivity(&self) -> u32 {
+        link_connectivity(self.gateway6.is_some(), self.ip6_connectivity_check)
+    }
+
+    /// Forget the probe result of a family that lost its default route, so
+    /// a returning gateway is not reported with a verdict about the old one.
+    pub fn clear_stale_connectivity_checks(&mut self) {
+        if self.gateway4.is_none() {
+            self.ip4_connectivity_check = None;
+        }
+        if self.gateway6.is_none() {
+            self.ip6_connectivity_check = None;
+        }
+    }
+
     pub fn has_gateway(&self) -> bool {
         self.gateway4.is_some() || self.gateway6.is_some()
     }
 
-    /// Update device state when IP addresses change.
+    /// Whether `other`, built from a NewLink for the same ifindex, is still
+    /// this link rather than a new one the kernel gave a reused index. The
+    /// hardware type never changes for a link, nor does the burned-in MAC
+    /// when both report one; name and current MAC can.
+    pub fn is_same_link(&self, other: &DeviceInfo) -> bool {
+        self.arp_type == other.arp_type
+            && match (&self.perm_hw_address, &other.perm_hw_address) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+    }
+
+    /// Replace the addresses with a fresh kernel dump. Removing one of
+    /// several addresses leaves the device as it was: only gaining the first
+    /// or losing the last one moves its state.
+    pub fn replace_addresses(
+        &mut self,
+        ipv4: &[AddrInfo<Ipv4Addr>],
+        ipv6: &[AddrInfo<Ipv6Addr>],
+    ) -> AddressChange {
+        let had_ip = self.has_ip_config();
+        let change = AddressChange {
+            ipv4_changed: self.ipv4_addrs != ipv4,
+            ipv6_changed: self.ipv6_addrs != ipv6,
+            state_change: None,
+        };
+        self.ipv4_addrs = ipv4.to_vec();
+        self.ipv6_addrs = ipv6.to_vec();
+        if self.has_ip_config() == had_ip {
+            return change;
+        }
+        AddressChange {
+            state_change: self.update_state_on_ip_change(),
+            ..change
+        }
+    }
+
+    /// Update device state when IP addresses or default routes change.
     /// Returns (new_state, old_state) if state changed, None otherwise.
     pub fn update_state_on_ip_change(&mut self) -> Option<(u32, u32)> {
         let old_state = self.nm_state;
@@ -176,8 +867,7 @@ impl DeviceInfo {
             return None;
         }
 
-        let has_ip = self.has_ip_address();
-        let new_state = if has_ip {
+        let new_state = if self.has_ip_config() {
             mapping::nm_device_state::ACTIVATED
         } else {
             mapping::nm_device_state::IP_CONFIG
@@ -191,23 +881,46 @@ impl DeviceInfo {
         }
     }
 
-    /// Update device state when link flags change.
+    /// Update device state when link flags or operational state change.
     /// Returns (new_state, old_state) if state changed, None otherwise.
-    pub fn update_state_on_link_change(&mut self, flags: u32) -> Option<(u32, u32)> {
+    /// A bond or bridge is UNAVAILABLE while none of its ports has carrier,
+    /// whatever its own flags say.
+    ///
+    /// A link that becomes UNAVAILABLE with IFF_UP still set lost its carrier;
+    /// `carrier_lost` records this so the change is reported with reason
+    /// CARRIER instead of as an administrative down.
+    pub fn update_state_on_link_change(
+        &mut self,
+        flags: u32,
+        carrier: Option<bool>,
+        operstate: u8,
+    ) -> Option<(u32, u32)> {
         self.link_flags = flags;
+        self.kernel_carrier = carrier;
+        self.operstate = operstate;
         let old_state = self.nm_state;
-        let has_ipv4 = !self.ipv4_addrs.is_empty();
-        let has_ipv6 = !self.ipv6_addrs.is_empty();
-        let new_state = mapping::netlink_flags_to_nm_device(flags, has_ipv4, has_ipv6);
+        let mut new_state = mapping::netlink_flags_to_nm_device(
+            flags,
+            carrier,
+            operstate,
+            self.managed,
+            self.has_ip_config(),
+        );
+        if self.ports_carrier == Some(false) && new_state > mapping::nm_device_state::UNAVAILABLE {
+            new_state = mapping::nm_device_state::UNAVAILABLE;
+        }
 
         if old_state != new_state {
             self.nm_state = new_state;
+            self.carrier_lost = new_state == mapping::nm_device_state::UNAVAILABLE
+                && (flags & mapping::netlink_flags::IFF_UP) != 0;
 
             if new_state == mapping::nm_device_state::DISCONNECTED
                 || new_state == mapping::nm_device_state::UNAVAILABLE
             {
                 self.gateway4 = None;
                 self.gateway6 = None;
+                self.clear_stale_connectivity_checks();
             }
 
             Some((new_state, old_state))
@@ -217,8 +930,400 @@ impl DeviceInfo {
     }
 }
 
-#[derive(Debug, Clone)]
+/// What [`DeviceInfo::replace_addresses`] changed: which address families
+/// differ from before, and the device state transition it caused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressChange {
+    pub ipv4_changed: bool,
+    pub ipv6_changed: bool,
+    /// (new_state, old_state) if the device state moved.
+    pub state_change: Option<(u32, u32)>,
+}
+
+/// One ARP/NDP neighbour cache entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct NeighborInfo {
+    pub address: IpAddr,
+    /// Empty while the entry is unresolved (INCOMPLETE/FAILED).
+    pub mac: String,
+    /// Raw `ndm_state` (NUD_* bits: 0x02 REACHABLE, 0x04 STALE, ...).
+    pub state: u16,
+}
+
+impl DeviceInfo {
+    /// Insert or replace the entry for `neighbor.address`.
+    pub fn update_neighbor(&mut self, neighbor: NeighborInfo) {
+        self.remove_neighbor(neighbor.address);
+        self.neighbors.push(neighbor);
+    }
+
+    pub fn remove_neighbor(&mut self, address: IpAddr) {
+        self.neighbors.retain(|n| n.address != address);
+    }
+}
+
+/// User-supplied settings for an interface's connection, set via `Update`
+/// and stored by [`crate::persistence`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConnectionProfile {
+    pub id: Option<String>,
+    /// `connection.autoconnect-priority`: higher wins the primary connection.
+    pub autoconnect_priority: i32,
+    /// Static settings last applied through `Update`, restored at startup.
+    pub mtu: Option<u32>,
+    pub ipv4_addresses: Option<Vec<(Ipv4Addr, u8)>>,
+    pub ipv4_gateway: Option<Ipv4Addr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct AddrInfo<A> {
     pub address: A,
     pub prefix_len: u8,
+    /// IFA_F_PERMANENT: statically configured rather than leased (DHCP/SLAAC).
+    pub is_permanent: bool,
+    /// Raw `ifa_scope` (RT_SCOPE_*: 0 global, 200 link, 254 host).
+    pub scope: u8,
+    /// IFA_LABEL (IPv4 only): the interface name, or an alias like `eth0:0`.
+    pub label: Option<String>,
+}
+
+/// Put addresses in the order clients show them, since the first one is
+/// treated as primary: global scope first, static before leased or
+/// temporary, then by address.
+pub fn sort_addresses<A: Ord + Copy>(addrs: &mut [AddrInfo<A>]) {
+    addrs.sort_by_key(|a| (a.scope, !a.is_permanent, a.address));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mapping::netlink_flags::{IFF_LOWER_UP, IFF_RUNNING, IFF_UP};
+    use mapping::{if_oper, nm_device_state};
+
+    fn ipv4(address: [u8; 4], prefix_len: u8) -> AddrInfo<Ipv4Addr> {
+        AddrInfo {
+            address: Ipv4Addr::from(address),
+            prefix_len,
+            is_permanent: true,
+            scope: 0,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn bond_goes_unavailable_with_its_own_carrier() {
+        let mut bond = DeviceInfo::new(10, "bond0".to_string());
+        bond.device_type = mapping::nm_device_type::BOND;
+        bond.ipv4_addrs.push(ipv4([192, 0, 2, 10], 24));
+        let running = IFF_UP | IFF_RUNNING | IFF_LOWER_UP;
+        assert_eq!(
+            bond.update_state_on_link_change(running, Some(true), if_oper::UP),
+            Some((nm_device_state::ACTIVATED, nm_device_state::UNKNOWN))
+        );
+        bond.gateway4 = Some(Ipv4Addr::new(192, 0, 2, 1));
+
+        // The kernel drops the bond's carrier with its last port's, leaving
+        // it administratively up.
+        assert_eq!(
+            bond.update_state_on_link_change(IFF_UP, Some(false), if_oper::DOWN),
+            Some((nm_device_state::UNAVAILABLE, nm_device_state::ACTIVATED))
+        );
+        assert!(bond.carrier_lost);
+        assert_eq!(bond.gateway4, None);
+
+        assert_eq!(
+            bond.update_state_on_link_change(running, Some(true), if_oper::UP),
+            Some((nm_device_state::ACTIVATED, nm_device_state::UNAVAILABLE))
+        );
+    }
+
+    #[test]
+    fn connection_filename_follows_the_uuid() {
+        let filename = connection_filename("eth0");
+        assert!(filename.contains(&connection_uuid("eth0")));
+        assert_ne!(filename, connection_filename("eth1"));
+    }
+
+    #[test]
+    fn bond_goes_unavailable_when_sole_port_loses_carrier() {
+        let running = IFF_UP | IFF_RUNNING | IFF_LOWER_UP;
+        let shared = new_shared_state(Config::default(), PersistentState::default());
+        let mut state = shared.try_write().unwrap();
+        let mut bond = DeviceInfo::new(10, "bond0".to_string());
+        bond.device_type = mapping::nm_device_type::BOND;
+        bond.ipv4_addrs.push(ipv4([192, 0, 2, 10], 24));
+        bond.update_state_on_link_change(running, Some(true), if_oper::UP);
+        state.insert_device(bond);
+        let mut port = DeviceInfo::new(2, "eth0".to_string());
+        port.controller = Some(10);
+        port.update_state_on_link_change(running, Some(true), if_oper::UP);
+        state.insert_device(port);
+        assert!(state.update_controller_states().is_empty());
+        assert_eq!(state.devices[&10].nm_state, nm_device_state::ACTIVATED);
+
+        // The port loses carrier; the bond's own flags still say it's up.
+        let port = state.devices.get_mut(&2).unwrap();
+        port.update_state_on_link_change(IFF_UP, Some(false), if_oper::DOWN);
+        assert_eq!(
+            state.update_controller_states(),
+            vec![(10, nm_device_state::UNAVAILABLE, nm_device_state::ACTIVATED)]
+        );
+        assert!(state.devices[&10].carrier_lost);
+
+        // Re-applying the bond's unchanged flags keeps it down.
+        let bond = state.devices.get_mut(&10).unwrap();
+        assert_eq!(
+            bond.update_state_on_link_change(running, Some(true), if_oper::UP),
+            None
+        );
+
+        let port = state.devices.get_mut(&2).unwrap();
+        port.update_state_on_link_change(running, Some(true), if_oper::UP);
+        assert_eq!(
+            state.update_controller_states(),
+            vec![(10, nm_device_state::ACTIVATED, nm_device_state::UNAVAILABLE)]
+        );
+
+        // Released from the bond, the port no longer holds it down.
+        state.devices.get_mut(&2).unwrap().controller = None;
+        state.update_controller_states();
+        assert_eq!(state.devices[&10].ports_carrier, None);
+    }
+
+    #[test]
+    fn losing_the_gateway_forgets_the_probe_result() {
+        use mapping::nm_connectivity::{FULL, NONE, PORTAL};
+
+        let mut dev = DeviceInfo::new(2, "eth0".to_string());
+        dev.gateway4 = Some(Ipv4Addr::new(192, 0, 2, 1));
+        dev.ip4_connectivity_check = Some(PORTAL);
+        assert_eq!(dev.ip4_connectivity(), PORTAL);
+
+        dev.gateway4 = None;
+        dev.clear_stale_connectivity_checks();
+        assert_eq!(dev.ip4_connectivity(), NONE);
+
+        dev.gateway4 = Some(Ipv4Addr::new(192, 0, 2, 254));
+        assert_eq!(dev.ip4_connectivity(), FULL);
+    }
+
+    #[test]
+    fn removing_one_of_two_addresses_keeps_the_state() {
+        let mut dev = DeviceInfo::new(2, "eth0".to_string());
+        dev.nm_state = nm_device_state::ACTIVATED;
+        let both = [ipv4([192, 0, 2, 10], 24), ipv4([198, 51, 100, 10], 24)];
+        dev.ipv4_addrs = both.to_vec();
+
+        let change = dev.replace_addresses(&both[..1], &[]);
+        assert!(change.ipv4_changed);
+        assert!(!change.ipv6_changed);
+        assert_eq!(change.state_change, None);
+        assert_eq!(dev.nm_state, nm_device_state::ACTIVATED);
+
+        let change = dev.replace_addresses(&[], &[]);
+        assert!(change.ipv4_changed);
+        assert_eq!(
+            change.state_change,
+            Some((nm_device_state::IP_CONFIG, nm_device_state::ACTIVATED))
+        );
+        assert_eq!(dev.nm_state, nm_device_state::IP_CONFIG);
+    }
+
+    #[test]
+    fn addresses_sort_global_static_first() {
+        let addr = |address: &str, is_permanent, scope| AddrInfo {
+            address: address.parse::<Ipv6Addr>().unwrap(),
+            prefix_len: 64,
+            is_permanent,
+            scope,
+            label: None,
+        };
+        let mut addrs = vec![
+            addr("fe80::1", true, 253),
+            addr("2001:db8::ffff", false, 0),
+            addr("::1", true, 254),
+            addr("2001:db8::20", true, 0),
+            addr("2001:db8::3", false, 0),
+            addr("2001:db8::10", true, 0),
+        ];
+        sort_addresses(&mut addrs);
+        let order: Vec<String> = addrs.iter().map(|a| a.address.to_string()).collect();
+        assert_eq!(
+            order,
+            [
+                "2001:db8::10",
+                "2001:db8::20",
+                "2001:db8::3",
+                "2001:db8::ffff",
+                "fe80::1",
+                "::1",
+            ]
+        );
+    }
+
+    #[test]
+    fn nm_paths_parse_back() {
+        for kind in PathKind::ALL {
+            let path = nm_path(kind, 42);
+            assert_eq!(parse_nm_path(&path), Some((kind, 42)), "{path}");
+        }
+        assert_eq!(
+            parse_nm_path(&device_path(i32::MAX)),
+            Some((PathKind::Device, i32::MAX))
+        );
+    }
+
+    #[test]
+    fn malformed_nm_paths_are_rejected() {
+        for path in [
+            "/org/freedesktop/NetworkManager/Devices/3/",
+            "/org/freedesktop/NetworkManager/Devices/3/extra",
+            "/org/freedesktop/NetworkManager/Devices/",
+            "/org/freedesktop/NetworkManager/Devices",
+            "/org/freedesktop/NetworkManager/Devices/-3",
+            "/org/freedesktop/NetworkManager/Devices/+3",
+            "/org/freedesktop/NetworkManager/Devices/03",
+            "/org/freedesktop/NetworkManager/Devices/0",
+            "/org/freedesktop/NetworkManager/Devices/2147483648",
+            "/org/freedesktop/NetworkManager/Devices/ 3",
+            "/org/freedesktop/NetworkManager/Bogus/3",
+            "/org/freedesktop/NetworkManagerX/Devices/3",
+            "/org/freedesktop/NetworkManager",
+            "/",
+        ] {
+            assert_eq!(parse_nm_path(path), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn tunnel_without_address_is_primary_through_its_gateway() {
+        let shared = new_shared_state(Config::default(), PersistentState::default());
+        let mut state = shared.try_write().unwrap();
+        let mut tun = DeviceInfo::new(7, "wg0".to_string());
+        tun.device_type = mapping::nm_device_type::WIREGUARD;
+        assert_eq!(
+            tun.update_state_on_link_change(IFF_UP | IFF_RUNNING, None, if_oper::UNKNOWN),
+            Some((nm_device_state::IP_CONFIG, nm_device_state::UNKNOWN))
+        );
+
+        // A default route via the peer, with no local address.
+        tun.gateway4 = Some(Ipv4Addr::new(10, 64, 0, 1));
+        assert_eq!(
+            tun.update_state_on_ip_change(),
+            Some((nm_device_state::ACTIVATED, nm_device_state::IP_CONFIG))
+        );
+        state.insert_device(tun);
+        state.recompute_global_state();
+        assert_eq!(state.global_state, mapping::nm_state::CONNECTED_GLOBAL);
+        assert_eq!(state.primary_device().map(|d| d.ifindex), Some(7));
+        assert_eq!(state.active_connection_paths().len(), 1);
+
+        // Link flags changing keep it activated while the route stays.
+        let dev = state.devices.get_mut(&7).unwrap();
+        assert_eq!(
+            dev.update_state_on_link_change(
+                IFF_UP | IFF_RUNNING | IFF_LOWER_UP,
+                None,
+                if_oper::UNKNOWN
+            ),
+            None
+        );
+
+        dev.gateway4 = None;
+        assert_eq!(
+            dev.update_state_on_ip_change(),
+            Some((nm_device_state::IP_CONFIG, nm_device_state::ACTIVATED))
+        );
+        state.recompute_global_state();
+        assert!(state.primary_device().is_none());
+        // Left in IP_CONFIG with no activation requested: idle, not connecting.
+        assert_eq!(state.global_state, mapping::nm_state::DISCONNECTED);
+    }
+
+    #[test]
+    fn device_cap_turns_away_new_devices_only() {
+        let config = Config {
+            max_devices: 2,
+            ..Config::default()
+        };
+        let shared = new_shared_state(config, PersistentState::default());
+        let mut state = shared.try_write().unwrap();
+        // An ignored interface shown as unmanaged takes a slot too.
+        let mut veth = DeviceInfo::new(1, "veth0".to_string());
+        veth.ignored = true;
+        assert!(!state.device_cap_reached(&veth));
+        state.insert_device(veth);
+        let eth = DeviceInfo::new(2, "eth2".to_string());
+        assert!(!state.device_cap_reached(&eth));
+        state.insert_device(eth);
+
+        assert!(state.device_cap_reached(&DeviceInfo::new(3, "eth3".to_string())));
+        let mut veth = DeviceInfo::new(4, "veth1".to_string());
+        veth.ignored = true;
+        assert!(state.device_cap_reached(&veth));
+        // Updates for an exported device are never refused.
+        assert!(!state.device_cap_reached(&DeviceInfo::new(2, "eth2".to_string())));
+
+        state.remove_device(1);
+        assert!(!state.device_cap_reached(&DeviceInfo::new(3, "eth3".to_string())));
+    }
+
+    #[test]
+    fn reused_ifindex_is_told_apart_by_hardware() {
+        let link = |name: &str, arp_type: u16, perm_hw_address: Option<&str>| {
+            let mut dev = DeviceInfo::new(7, name.to_string());
+            dev.arp_type = arp_type;
+            dev.perm_hw_address = perm_hw_address.map(str::to_string);
+            dev
+        };
+        let eth = link("eth0", 1, Some("52:54:00:12:34:56"));
+
+        // Renames and missing permanent addresses keep the identity.
+        assert!(eth.is_same_link(&link("lan0", 1, Some("52:54:00:12:34:56"))));
+        assert!(eth.is_same_link(&link("eth0", 1, None)));
+        assert!(link("eth0", 1, None).is_same_link(&eth));
+
+        assert!(!eth.is_same_link(&link("eth0", 1, Some("52:54:00:65:43:21"))));
+        assert!(!eth.is_same_link(&link("eth0", 65534, None)));
+
+        // Removed while a disconnect or activation failure was pending: the
+        // next link on the index starts clean.
+        let shared = new_shared_state(Config::default(), PersistentState::default());
+        let mut state = shared.try_write().unwrap();
+        state.insert_device(eth);
+        state.user_disconnect_pending.insert(7);
+        state.activation_failed_pending.insert(7);
+        state.remove_device(7);
+        state.insert_device(link("usb0", 1, Some("52:54:00:65:43:21")));
+        assert!(!state.user_disconnect_pending.contains(&7));
+        assert!(!state.activation_failed_pending.contains(&7));
+    }
+
+    #[test]
+    fn unmanaged_and_ignored_devices_are_not_controllable() {
+        let shared = new_shared_state(Config::default(), PersistentState::default());
+        let mut state = shared.try_write().unwrap();
+        state.insert_device(DeviceInfo::new(2, "eth0".to_string()));
+        let mut unmanaged = DeviceInfo::new(3, "eth1".to_string());
+        unmanaged.managed = false;
+        state.insert_device(unmanaged);
+        let mut ignored = DeviceInfo::new(4, "veth0".to_string());
+        ignored.ignored = true;
+        ignored.managed = false;
+        state.insert_device(ignored);
+
+        assert!(state.ensure_controllable(2).is_ok());
+        for ifindex in [3, 4] {
+            assert!(
+                matches!(
+                    state.ensure_controllable(ifindex),
+                    Err(zbus::fdo::Error::NotSupported(_))
+                ),
+                "{ifindex} controllable"
+            );
+        }
+        // Managed can be turned back on for the unmanaged one only.
+        assert!(state.ensure_not_ignored(3).is_ok());
+        assert!(state.ensure_not_ignored(4).is_err());
+    }
 }
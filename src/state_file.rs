@@ -0,0 +1,111 @@
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+pub const STATE_PATH: &str = "/var/lib/nmlinkd/state.toml";
+
+/// Settings changed at runtime over D-Bus that must survive a restart.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersistentState {
+    /// Interfaces whose `Autoconnect` property was turned off.
+    pub autoconnect_disabled: BTreeSet<String>,
+    /// Interfaces whose `Managed` property was turned off.
+    pub unmanaged: BTreeSet<String>,
+}
+
+/// Load the state file. A missing or unreadable file yields empty state:
+/// losing runtime settings must never prevent startup.
+pub fn load() -> PersistentState {
+    let path = Path::new(STATE_PATH);
+    let contents = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return PersistentState::default(),
+        Err(e) => {
+            warn!(path = %path.display(), "failed to read state file: {e}");
+            return PersistentState::default();
+        }
+    };
+    match toml::from_str(&contents) {
+        Ok(state) => {
+            info!(path = %path.display(), "loaded persistent state");
+            state
+        }
+        Err(e) => {
+            warn!(path = %path.display(), "ignoring malformed state file: {e}");
+            PersistentState::default()
+        }
+    }
+}
+
+/// Write the state file atomically.
+pub async fn save(state: &PersistentState) -> io::Result<()> {
+    let contents = toml::to_string(state).map_err(io::Error::other)?;
+    write_atomic(Path::new(STATE_PATH), &contents).await
+}
+
+/// Replace `path` with `contents` atomically (temp file + rename), creating
+/// its directory if needed. Each call writes its own temp file, so
+/// concurrent writers never interleave; the last rename wins.
+pub async fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file path", path.display()),
+        ));
+    };
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp = path.with_file_name(tmp_name);
+    if let Err(e) = tokio::fs::write(&tmp, contents).await {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+    if let Err(e) = tokio::fs::rename(&tmp, path).await {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_atomic_writes_leave_one_complete_file() {
+        let dir = std::env::temp_dir().join(format!("nmlinkd-write-atomic-{}", std::process::id()));
+        let path = dir.join("eth0.toml");
+        let contents: Vec<String> = (0..16).map(|i| format!("mtu = {i}\n").repeat(64)).collect();
+
+        let writes = contents.iter().map(|c| write_atomic(&path, c));
+        for result in futures::future::join_all(writes).await {
+            result.unwrap();
+        }
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains(&written));
+        let leftovers: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .filter(|name| name != "eth0.toml")
+            .collect();
+        assert!(leftovers.is_empty(), "{leftovers:?}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+//! Fixtures for tests: an in-memory state with fake devices, and D-Bus
+//! connections that need no bus daemon.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use tokio::net::UnixStream;
+use zbus::Connection;
+use zbus::connection::Builder;
+
+use crate::config::Config;
+use crate::mapping::{if_oper, netlink_flags, nm_device_state, nm_device_type};
+use crate::state::{self, AddrInfo, DeviceInfo, HardwareInfo, SharedState};
+use crate::state_file::PersistentState;
+
+/// ifindex of the fake ethernet device, `nmtest0`.
+pub const ETH_IFINDEX: i32 = 2;
+/// ifindex of the fake WireGuard device, `nmtest1`.
+pub const WG_IFINDEX: i32 = 3;
+
+fn addr<A>(address: A, prefix_len: u8, is_permanent: bool, scope: u8) -> AddrInfo<A> {
+    AddrInfo {
+        address,
+        prefix_len,
+        is_permanent,
+        scope,
+        label: None,
+    }
+}
+
+/// A state as `load_initial_state` would leave it on a host with one wired
+/// uplink (dual-stack, with default routes) and one WireGuard tunnel. The
+/// interface names are chosen so nothing is found under /sys.
+pub fn fake_state() -> SharedState {
+    let shared = state::new_shared_state(Config::default(), PersistentState::default());
+    let mut state = shared.try_write().expect("fresh state is unlocked");
+
+    let up = netlink_flags::IFF_UP | netlink_flags::IFF_RUNNING | netlink_flags::IFF_LOWER_UP;
+
+    let mut eth = DeviceInfo::new(ETH_IFINDEX, "nmtest0".to_string());
+    eth.type_description = "ethernet".to_string();
+    eth.arp_type = 1;
+    eth.hw_address = "52:54:00:12:34:56".to_string();
+    eth.perm_hw_address = Some("52:54:00:12:34:56".to_string());
+    eth.link_flags = up;
+    eth.kernel_carrier = Some(true);
+    eth.operstate = if_oper::UP;
+    eth.mtu = 1500;
+    eth.tx_queue_len = 1000;
+    eth.num_tx_queues = 1;
+    eth.ipv4_addrs = vec![addr(Ipv4Addr::new(192, 0, 2, 10), 24, false, 0)];
+    eth.ipv6_addrs = vec![
+        addr("2001:db8::10".parse::<Ipv6Addr>().unwrap(), 64, false, 0),
+        addr("fe80::5054:ff:fe12:3456".parse().unwrap(), 64, true, 253),
+    ];
+    eth.gateway4 = Some(Ipv4Addr::new(192, 0, 2, 1));
+    eth.gateway6 = Some("fe80::1".parse().unwrap());
+    eth.nameservers = vec!["192.0.2.53".to_string()];
+    eth.link_speed = 1000;
+    eth.duplex = "full".to_string();
+    eth.driver = "e1000e".to_string();
+    eth.hardware = Some(HardwareInfo::default());
+    eth.nm_state = nm_device_state::ACTIVATED;
+    state.insert_device(eth);
+
+    let mut wg = DeviceInfo::new(WG_IFINDEX, "nmtest1".to_string());
+    wg.device_type = nm_device_type::WIREGUARD;
+    wg.type_description = "wireguard".to_string();
+    wg.arp_type = 65534;
+    wg.link_flags = netlink_flags::IFF_UP | netlink_flags::IFF_RUNNING;
+    wg.operstate = if_oper::UNKNOWN;
+    wg.mtu = 1420;
+    wg.ipv4_addrs = vec![addr(Ipv4Addr::new(10, 8, 0, 2), 32, true, 0)];
+    wg.hardware = Some(HardwareInfo::default());
+    wg.nm_state = nm_device_state::ACTIVATED;
+    state.insert_device(wg);
+
+    state.nameservers = vec!["192.0.2.53".to_string(), "2001:db8::53".to_string()];
+    state.search_domains = vec!["example.com".to_string()];
+    state.recompute_global_state();
+    drop(state);
+    shared
+}
+
+/// A connected pair of peer-to-peer D-Bus connections, (server, client).
+/// Messages carry no sender, so privileged methods accept them.
+pub async fn p2p_connections() -> (Connection, Connection) {
+    let (server, client) = UnixStream::pair().expect("socketpair");
+    let guid = zbus::Guid::generate();
+    let server = Builder::unix_stream(server)
+        .server(guid)
+        .expect("valid guid")
+        .p2p()
+        .build();
+    let client = Builder::unix_stream(client).p2p().build();
+    futures::try_join!(server, client).expect("p2p handshake")
+}
+
+/// `fake_state()` exported on the server end of a p2p pair, as `serve`
+/// exports it on the bus. Returns (state, server, client).
+pub async fn served_fake_state() -> (SharedState, Connection, Connection) {
+    let shared = fake_state();
+    let (server, client) = p2p_connections().await;
+    crate::nm::register_objects(&server, &shared, &[ETH_IFINDEX, WG_IFINDEX])
+        .await
+        .expect("register objects");
+    (shared, server, client)
+}